@@ -20,7 +20,7 @@ use fork_tree::ForkTree;
 use parking_lot::RwLock;
 use finality_grandpa::voter_set::VoterSet;
 use parity_scale_codec::{Encode, Decode};
-use log::debug;
+use log::{debug, info, warn};
 use sc_telemetry::{telemetry, CONSENSUS_INFO};
 use sp_finality_grandpa::{AuthorityId, AuthorityList};
 
@@ -36,6 +36,8 @@ pub enum Error<E> {
 	InvalidAuthoritySet,
 	#[display(fmt = "Invalid operation in the pending changes tree: {}", _0)]
 	ForkTree(fork_tree::Error<E>),
+	#[display(fmt = "Authority set id would overflow on handoff")]
+	SetIdOverflow,
 }
 
 /// A shared authority set.
@@ -97,6 +99,24 @@ pub(crate) struct Status<H, N> {
 	pub(crate) new_set_block: Option<(H, N)>,
 }
 
+/// The differences between two authority sets, see `AuthoritySet::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AuthoritySetDiff<H, N> {
+	/// Authorities only in the other set.
+	pub(crate) added: Vec<AuthorityId>,
+	/// Authorities only in this set.
+	pub(crate) removed: Vec<AuthorityId>,
+	/// Authorities in both sets with a different weight, with their weight in
+	/// this set and in the other set.
+	pub(crate) reweighted: Vec<(AuthorityId, u64, u64)>,
+	/// The set id of the other set minus the set id of this set.
+	pub(crate) set_id_delta: i128,
+	/// Pending changes only in the other set.
+	pub(crate) added_pending_changes: Vec<PendingChange<H, N>>,
+	/// Pending changes only in this set.
+	pub(crate) removed_pending_changes: Vec<PendingChange<H, N>>,
+}
+
 /// A set of authorities.
 #[derive(Debug, Clone, Encode, Decode, PartialEq)]
 pub(crate) struct AuthoritySet<H, N> {
@@ -158,6 +178,43 @@ where H: PartialEq,
 	pub(crate) fn current(&self) -> (u64, &[(AuthorityId, u64)]) {
 		(self.set_id, &self.current_authorities[..])
 	}
+
+	/// Get the current set id.
+	pub(crate) fn set_id(&self) -> u64 {
+		self.set_id
+	}
+
+	/// Get the set id of the authority set handed off to from this one,
+	/// failing instead of wrapping around if it overflows.
+	pub(crate) fn next_set_id<E>(&self) -> Result<u64, Error<E>> {
+		self.set_id.checked_add(1).ok_or(Error::SetIdOverflow)
+	}
+
+	/// Get a reference to the current authority set.
+	pub(crate) fn authorities(&self) -> &[(AuthorityId, u64)] {
+		&self.current_authorities[..]
+	}
+
+	/// Get the pending forced changes, across all forks.
+	pub(crate) fn pending_forced_changes(&self) -> &[PendingChange<H, N>] {
+		&self.pending_forced_changes[..]
+	}
+
+	/// Get the total weight of the current authority set. Returns `None` if
+	/// it overflows.
+	pub(crate) fn total_weight(&self) -> Option<u64> {
+		self.current_authorities.iter()
+			.try_fold(0u64, |total, (_, weight)| total.checked_add(*weight))
+	}
+
+	/// Get the supermajority threshold weight of the current authority set,
+	/// i.e. more than two thirds of its total weight. Returns `None` if the
+	/// total weight overflows.
+	pub(crate) fn threshold(&self) -> Option<u64> {
+		let total_weight = self.total_weight()?;
+		let faulty = total_weight.checked_sub(1)? / 3;
+		total_weight.checked_sub(faulty)
+	}
 }
 
 impl<H: Eq, N> AuthoritySet<H, N>
@@ -269,6 +326,55 @@ where
 			.chain(self.pending_forced_changes.iter())
 	}
 
+	/// Inspect pending standard changes ordered by the height they were
+	/// signaled at. Changes at the same height keep their order in the tree.
+	pub(crate) fn pending_standard_changes_iter(&self) -> impl Iterator<Item=&PendingChange<H, N>> {
+		let mut changes = self.pending_standard_changes.iter()
+			.map(|(_, _, c)| c)
+			.collect::<Vec<_>>();
+
+		changes.sort_by(|a, b| a.canon_height.cmp(&b.canon_height));
+		changes.into_iter()
+	}
+
+	/// Describe how the given authority set differs from this one. Standard
+	/// and forced pending changes are compared together.
+	pub(crate) fn diff(&self, other: &AuthoritySet<H, N>) -> AuthoritySetDiff<H, N> {
+		let weight_in = |set: &AuthoritySet<H, N>, id: &AuthorityId| set.current_authorities.iter()
+			.find(|(authority, _)| authority == id)
+			.map(|(_, weight)| *weight);
+
+		let added = other.current_authorities.iter()
+			.filter(|(id, _)| weight_in(self, id).is_none())
+			.map(|(id, _)| id.clone())
+			.collect();
+
+		let mut removed = Vec::new();
+		let mut reweighted = Vec::new();
+		for (id, weight) in &self.current_authorities {
+			match weight_in(other, id) {
+				None => removed.push(id.clone()),
+				Some(other_weight) if other_weight != *weight =>
+					reweighted.push((id.clone(), *weight, other_weight)),
+				Some(_) => {},
+			}
+		}
+
+		let pending_changes_only_in = |set: &AuthoritySet<H, N>, not_in: &AuthoritySet<H, N>| set.pending_changes()
+			.filter(|change| !not_in.pending_changes().any(|c| c == *change))
+			.cloned()
+			.collect();
+
+		AuthoritySetDiff {
+			added,
+			removed,
+			reweighted,
+			set_id_delta: i128::from(other.set_id) - i128::from(self.set_id),
+			added_pending_changes: pending_changes_only_in(other, self),
+			removed_pending_changes: pending_changes_only_in(self, other),
+		}
+	}
+
 	/// Get the earliest limit-block number, if any. If there are pending changes across
 	/// different forks, this method will return the earliest effective number (across the
 	/// different branches) that is higher or equal to the given min number.
@@ -297,8 +403,9 @@ where
 		best_number: N,
 		is_descendent_of: &F,
 		initial_sync: bool,
-	) -> Result<Option<(N, Self)>, E>
+	) -> Result<Option<(N, Self)>, Error<E>>
 		where F: Fn(&H, &H) -> Result<bool, E>,
+			  E: std::error::Error,
 	{
 		let mut new_set = None;
 
@@ -307,7 +414,10 @@ where
 			.filter(|c| c.effective_number() == best_number)
 		{
 			// check if the given best block is in the same branch as the block that signaled the change.
-			if is_descendent_of(&change.canon_hash, &best_hash)? {
+			if is_descendent_of(&change.canon_hash, &best_hash).map_err(fork_tree::Error::Client)? {
+				// fail before logging the change as applied.
+				let set_id = self.next_set_id()?;
+
 				// apply this change: make the set canonical
 				afg_log!(initial_sync,
 					"👴 Applying authority set change forced at block #{:?}",
@@ -324,7 +434,7 @@ where
 
 				new_set = Some((median_last_finalized, AuthoritySet {
 					current_authorities: change.next_authorities.clone(),
-					set_id: self.set_id + 1,
+					set_id,
 					pending_standard_changes: ForkTree::new(), // new set, new changes.
 					pending_forced_changes: Vec::new(),
 				}));
@@ -364,6 +474,26 @@ where
 			new_set_block: None,
 		};
 
+		// the next set id is computed before the pending changes are pruned, so
+		// that an overflow leaves the authority set untouched.
+		let next_set_id = match self.next_set_id() {
+			Ok(set_id) => Some(set_id),
+			Err(err) => {
+				let enacts_change = self.pending_standard_changes.finalizes_any_with_descendent_if(
+					&finalized_hash,
+					finalized_number.clone(),
+					is_descendent_of,
+					|change| change.effective_number() <= finalized_number
+				)? == Some(true);
+
+				if enacts_change {
+					return Err(err);
+				}
+
+				None
+			},
+		};
+
 		match self.pending_standard_changes.finalize_with_descendent_if(
 			&finalized_hash,
 			finalized_number.clone(),
@@ -387,7 +517,8 @@ where
 					);
 
 					self.current_authorities = change.next_authorities;
-					self.set_id += 1;
+					self.set_id = next_set_id
+						.expect("the next set id only overflows if no change is enacted; qed");
 
 					status.new_set_block = Some((
 						finalized_hash,
@@ -431,7 +562,7 @@ where
 
 /// Kinds of delays for pending changes.
 #[derive(Debug, Clone, Encode, Decode, PartialEq)]
-pub(crate) enum DelayKind<N> {
+pub enum DelayKind<N> {
 	/// Depth in finalized chain.
 	Finalized,
 	/// Depth in best chain. The median last finalized block is calculated at the time the
@@ -444,18 +575,18 @@ pub(crate) enum DelayKind<N> {
 /// This will be applied when the announcing block is at some depth within
 /// the finalized or unfinalized chain.
 #[derive(Debug, Clone, Encode, PartialEq)]
-pub(crate) struct PendingChange<H, N> {
+pub struct PendingChange<H, N> {
 	/// The new authorities and weights to apply.
-	pub(crate) next_authorities: AuthorityList,
+	pub next_authorities: AuthorityList,
 	/// How deep in the chain the announcing block must be
 	/// before the change is applied.
-	pub(crate) delay: N,
+	pub delay: N,
 	/// The announcing block's height.
-	pub(crate) canon_height: N,
+	pub canon_height: N,
 	/// The announcing block's hash.
-	pub(crate) canon_hash: H,
+	pub canon_hash: H,
 	/// The delay kind.
-	pub(crate) delay_kind: DelayKind<N>,
+	pub delay_kind: DelayKind<N>,
 }
 
 impl<H: Decode, N: Decode> Decode for PendingChange<H, N> {
@@ -484,6 +615,50 @@ impl<H, N: Add<Output=N> + Clone> PendingChange<H, N> {
 	}
 }
 
+/// Maximum number of authorities listed when logging an authority set diff.
+const MAX_LOGGED_AUTHORITIES: usize = 16;
+
+fn format_authorities<T: Debug>(authorities: &[T]) -> String {
+	if authorities.len() > MAX_LOGGED_AUTHORITIES {
+		format!(
+			"{:?} ... and {} more",
+			&authorities[..MAX_LOGGED_AUTHORITIES],
+			authorities.len() - MAX_LOGGED_AUTHORITIES,
+		)
+	} else {
+		format!("{:?}", authorities)
+	}
+}
+
+/// Log the authorities added, removed and reweighted by an authority set
+/// handoff, see `AuthoritySet::diff`, along with the threshold weight of the
+/// new set. Must be called before the new authority set is written.
+pub(crate) fn log_authority_set_diff<H, N>(old: &AuthoritySet<H, N>, new: &AuthoritySet<H, N>) where
+	H: Eq + Clone + Debug,
+	N: Add<Output=N> + Ord + Clone + Debug,
+{
+	let diff = old.diff(new);
+
+	info!(target: "afg", "👴 GRANDPA authority set change by {} set ids added {}, removed {}, reweighted {}",
+		diff.set_id_delta,
+		format_authorities(&diff.added),
+		format_authorities(&diff.removed),
+		format_authorities(&diff.reweighted),
+	);
+	debug!(target: "afg", "GRANDPA authority set change added pending changes {:?}, removed {:?}",
+		diff.added_pending_changes,
+		diff.removed_pending_changes,
+	);
+
+	match new.threshold() {
+		Some(threshold) => info!(target: "afg", "👴 GRANDPA authority set {} has a threshold weight of {}",
+			new.set_id(),
+			threshold,
+		),
+		None => warn!(target: "afg", "GRANDPA authority set {} total weight overflows", new.set_id()),
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -512,7 +687,7 @@ mod tests {
 			pending_forced_changes: Vec::new(),
 		};
 
-		let change = |height| {
+		let change = |height: u64| {
 			PendingChange {
 				next_authorities: current_authorities.clone(),
 				delay: 0,
@@ -548,6 +723,110 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn threshold_is_a_supermajority_of_the_total_weight() {
+		let authorities = AuthoritySet::<(), u64>::genesis(vec![
+			(AuthorityId::from_slice(&[1; 32]), 2),
+			(AuthorityId::from_slice(&[2; 32]), 3),
+			(AuthorityId::from_slice(&[3; 32]), 4),
+		]).unwrap();
+		assert_eq!(authorities.total_weight(), Some(9));
+		assert_eq!(authorities.threshold(), Some(6));
+
+		let authorities = AuthoritySet::<(), u64>::genesis(
+			vec![(AuthorityId::from_slice(&[1; 32]), 1)],
+		).unwrap();
+		assert_eq!(authorities.total_weight(), Some(1));
+		assert_eq!(authorities.threshold(), Some(1));
+
+		let authorities = AuthoritySet::<(), u64>::genesis(vec![
+			(AuthorityId::from_slice(&[1; 32]), u64::max_value()),
+			(AuthorityId::from_slice(&[2; 32]), 1),
+		]).unwrap();
+		assert_eq!(authorities.total_weight(), None);
+		assert_eq!(authorities.threshold(), None);
+	}
+
+	#[test]
+	fn set_id_handoff_fails_on_overflow() {
+		let current_authorities = vec![(AuthorityId::from_slice(&[1; 32]), 1)];
+
+		let mut authorities = AuthoritySet {
+			current_authorities: current_authorities.clone(),
+			set_id: u64::max_value() - 1,
+			pending_standard_changes: ForkTree::new(),
+			pending_forced_changes: Vec::new(),
+		};
+
+		let change = |height: u64| {
+			PendingChange {
+				next_authorities: current_authorities.clone(),
+				delay: 0,
+				canon_height: height,
+				canon_hash: height.to_string(),
+				delay_kind: DelayKind::Finalized,
+			}
+		};
+
+		let is_descendent_of = static_is_descendent_of(true);
+
+		authorities.add_pending_change(change(1), &is_descendent_of).unwrap();
+		authorities.apply_standard_changes("1".to_string(), 1, &is_descendent_of, false).unwrap();
+		assert_eq!(authorities.set_id(), u64::max_value());
+
+		authorities.add_pending_change(change(2), &is_descendent_of).unwrap();
+		match authorities.apply_standard_changes("2".to_string(), 2, &is_descendent_of, false) {
+			Err(Error::SetIdOverflow) => {},
+			_ => panic!("expected the set id to overflow"),
+		}
+		assert_eq!(authorities.set_id(), u64::max_value());
+		// the pending change wasn't pruned by the failed handoff.
+		assert_eq!(authorities.pending_changes().count(), 1);
+	}
+
+	#[test]
+	fn diff_describes_changed_authorities() {
+		let id = |n: u8| AuthorityId::from_slice(&[n; 32]);
+
+		let mut stored = AuthoritySet {
+			current_authorities: vec![(id(1), 1), (id(2), 1), (id(3), 1)],
+			set_id: 4,
+			pending_standard_changes: ForkTree::new(),
+			pending_forced_changes: Vec::new(),
+		};
+
+		let mut proposed = AuthoritySet {
+			current_authorities: vec![(id(1), 1), (id(3), 5)],
+			set_id: 5,
+			pending_standard_changes: ForkTree::new(),
+			pending_forced_changes: Vec::new(),
+		};
+
+		let change = |hash: &'static str| PendingChange {
+			next_authorities: vec![(id(4), 1)],
+			delay: 0,
+			canon_height: 10,
+			canon_hash: hash,
+			delay_kind: DelayKind::Finalized,
+		};
+
+		stored.add_pending_change(change("hash_a"), &static_is_descendent_of(false)).unwrap();
+		proposed.add_pending_change(change("hash_b"), &static_is_descendent_of(false)).unwrap();
+
+		let diff = stored.diff(&proposed);
+		assert!(diff.added.is_empty());
+		assert_eq!(diff.removed, vec![id(2)]);
+		assert_eq!(diff.reweighted, vec![(id(3), 1, 5)]);
+		assert_eq!(diff.set_id_delta, 1);
+		assert_eq!(diff.added_pending_changes, vec![change("hash_b")]);
+		assert_eq!(diff.removed_pending_changes, vec![change("hash_a")]);
+
+		let diff = proposed.diff(&stored);
+		assert_eq!(diff.added, vec![id(2)]);
+		assert_eq!(diff.set_id_delta, -1);
+		assert!(stored.diff(&stored).reweighted.is_empty());
+	}
+
 	#[test]
 	fn changes_iterated_in_pre_order() {
 		let current_authorities = vec![(AuthorityId::from_slice(&[1; 32]), 1)];
@@ -617,6 +896,52 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn pending_standard_changes_iterated_by_height() {
+		let current_authorities = vec![(AuthorityId::from_slice(&[1; 32]), 1)];
+
+		let mut authorities = AuthoritySet {
+			current_authorities: current_authorities.clone(),
+			set_id: 0,
+			pending_standard_changes: ForkTree::new(),
+			pending_forced_changes: Vec::new(),
+		};
+
+		let change_a = PendingChange {
+			next_authorities: current_authorities.clone(),
+			delay: 0,
+			canon_height: 20,
+			canon_hash: "hash_a",
+			delay_kind: DelayKind::Finalized,
+		};
+
+		let change_b = PendingChange {
+			next_authorities: current_authorities.clone(),
+			delay: 0,
+			canon_height: 10,
+			canon_hash: "hash_b",
+			delay_kind: DelayKind::Finalized,
+		};
+
+		// the higher change is inserted first, on a different fork
+		authorities.add_pending_change(change_a.clone(), &static_is_descendent_of(false)).unwrap();
+		authorities.add_pending_change(change_b.clone(), &static_is_descendent_of(false)).unwrap();
+
+		assert_eq!(
+			authorities.pending_standard_changes_iter().collect::<Vec<_>>(),
+			vec![&change_b, &change_a],
+		);
+	}
+
+	#[test]
+	fn accessors_match_current() {
+		let authorities = vec![(AuthorityId::from_slice(&[1; 32]), 5)];
+		let set = AuthoritySet::<(), u64>::genesis(authorities).unwrap();
+
+		assert_eq!(set.set_id(), set.current().0);
+		assert_eq!(set.authorities(), set.current().1);
+	}
+
 	#[test]
 	fn apply_change() {
 		let mut authorities = AuthoritySet {
@@ -991,4 +1316,14 @@ mod tests {
 			Err(Error::InvalidAuthoritySet)
 		));
 	}
+
+	#[test]
+	fn logged_authority_list_is_capped() {
+		let authorities = (0..20u8)
+			.map(|i| AuthorityId::from_slice(&[i; 32]))
+			.collect::<Vec<_>>();
+
+		assert!(format_authorities(&authorities).ends_with(" ... and 4 more"));
+		assert!(!format_authorities(&authorities[..2]).contains("... and"));
+	}
 }
@@ -16,31 +16,229 @@
 
 //! Schema for stuff in the aux-db.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::sync::Arc;
-use parity_scale_codec::{Encode, Decode};
+use parity_scale_codec::{Encode, Decode, Compact};
 use sc_client_api::backend::AuxStore;
 use sp_blockchain::{Result as ClientResult, Error as ClientError};
 use fork_tree::ForkTree;
 use finality_grandpa::round::State as RoundState;
-use sp_runtime::traits::{Block as BlockT, NumberFor};
+use sp_runtime::Justification;
+use sp_runtime::traits::{Block as BlockT, CheckedAdd, NumberFor, Saturating};
 use log::{info, warn};
-use sp_finality_grandpa::{AuthorityList, SetId, RoundNumber};
+use prometheus_endpoint::{
+	register, CounterVec, HistogramOpts, HistogramVec, Opts, PrometheusError, Registry, U64,
+};
+use sp_finality_grandpa::{AuthorityId, AuthorityList, SetId, RoundNumber};
 
 use crate::authorities::{AuthoritySet, SharedAuthoritySet, PendingChange, DelayKind};
 use crate::consensus_changes::{SharedConsensusChanges, ConsensusChanges};
 use crate::environment::{
-	CompletedRound, CompletedRounds, CurrentRounds, HasVoted, SharedVoterSetState, VoterSetState,
+	CompletedRound, CompletedRounds, CurrentRounds, HasVoted, Metrics, PauseReason,
+	SharedVoterSetState, VoterSetState,
 };
-use crate::NewAuthoritySet;
+use crate::{NewAuthoritySet, SignedMessage};
 
 const VERSION_KEY: &[u8] = b"grandpa_schema_version";
 const SET_STATE_KEY: &[u8] = b"grandpa_completed_round";
 const CONCLUDED_ROUNDS: &[u8] = b"grandpa_concluded_rounds";
 const AUTHORITY_SET_KEY: &[u8] = b"grandpa_voters";
 const CONSENSUS_CHANGES_KEY: &[u8] = b"grandpa_consensus_changes";
+const BEST_FINALIZED_KEY: &[u8] = b"grandpa_best_finalized";
+const LAST_JUSTIFICATION_KEY: &[u8] = b"grandpa_last_justification";
+const EQUIVOCATIONS_KEY: &[u8] = b"grandpa_equivocations";
+const MAX_SET_ID_KEY: &[u8] = b"grandpa_max_set_id";
+const GENESIS_KEY: &[u8] = b"grandpa_genesis";
+const PARTICIPATION_KEY: &[u8] = b"grandpa_participation";
+const LAST_HANDOFF_KEY: &[u8] = b"grandpa_last_handoff";
+const INIT_KEY: &[u8] = b"grandpa_initialized";
+const HANDOFF_LOG_KEY: &[u8] = b"grandpa_handoff_log";
+
+const CURRENT_VERSION: u32 = 8;
+
+/// The default number of handoffs kept in the handoff log.
+pub(crate) const DEFAULT_HANDOFF_LOG_LIMIT: usize = 256;
+
+/// The prefix shared by all the keys above.
+const DEFAULT_KEY_PREFIX: &[u8] = b"grandpa_";
+
+/// The aux-db keys used by a GRANDPA instance, derived from the default keys
+/// by replacing their `grandpa_` prefix. Allows several instances to share a
+/// single `AuxStore`, see `PrefixedAuxStore`. The default prefix leaves the
+/// keys unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaKeys {
+	prefix: Vec<u8>,
+}
+
+impl Default for SchemaKeys {
+	fn default() -> Self {
+		SchemaKeys::new(DEFAULT_KEY_PREFIX)
+	}
+}
+
+impl SchemaKeys {
+	/// Create the keys for the given prefix, e.g. `grandpa2_`.
+	pub fn new(prefix: impl Into<Vec<u8>>) -> Self {
+		SchemaKeys { prefix: prefix.into() }
+	}
+
+	/// The key under which the entry stored under the given default key is
+	/// stored by this instance.
+	pub fn key(&self, key: &[u8]) -> Vec<u8> {
+		if !key.starts_with(DEFAULT_KEY_PREFIX) {
+			return key.to_vec();
+		}
+
+		let mut prefixed = self.prefix.clone();
+		prefixed.extend_from_slice(&key[DEFAULT_KEY_PREFIX.len()..]);
+		prefixed
+	}
+
+	/// Map the keys of entries written through an `update_*` function to the
+	/// keys of this instance.
+	pub fn entries(&self, insert: &[(&[u8], &[u8])]) -> Vec<(Vec<u8>, Vec<u8>)> {
+		insert.iter().map(|(key, value)| (self.key(key), value.to_vec())).collect()
+	}
+}
+
+/// An `AuxStore` storing the GRANDPA entries of the wrapped backend under the
+/// given schema keys. Pass it to `load_persistent`, `write_voter_set_state`
+/// etc. to use keys other than the default ones.
+pub struct PrefixedAuxStore<'a, B> {
+	backend: &'a B,
+	keys: &'a SchemaKeys,
+}
+
+impl<'a, B> PrefixedAuxStore<'a, B> {
+	/// Wrap the given backend.
+	pub fn new(backend: &'a B, keys: &'a SchemaKeys) -> Self {
+		PrefixedAuxStore { backend, keys }
+	}
+}
+
+impl<'a, B: AuxStore> AuxStore for PrefixedAuxStore<'a, B> {
+	fn insert_aux<
+		'x,
+		'y: 'x,
+		'z: 'x,
+		I: IntoIterator<Item=&'x(&'z [u8], &'z [u8])>,
+		D: IntoIterator<Item=&'x &'y [u8]>,
+	>(&self, insert: I, delete: D) -> ClientResult<()> {
+		let insert = insert.into_iter()
+			.map(|(key, value)| (self.keys.key(key), *value))
+			.collect::<Vec<_>>();
+		let delete = delete.into_iter()
+			.map(|key| self.keys.key(key))
+			.collect::<Vec<_>>();
+
+		let insert = insert.iter().map(|(key, value)| (&key[..], *value)).collect::<Vec<_>>();
+		let delete = delete.iter().map(|key| &key[..]).collect::<Vec<_>>();
+
+		self.backend.insert_aux(&insert, &delete)
+	}
+
+	fn get_aux(&self, key: &[u8]) -> ClientResult<Option<Vec<u8>>> {
+		self.backend.get_aux(&self.keys.key(key))
+	}
+}
+
+/// An `AuxStore` caching the entries read from the wrapped backend, including
+/// absent ones. Entries written or deleted through it are read from the
+/// backend again. Writes made to the backend directly aren't observed, so it
+/// should only be used for short-lived exclusive access, e.g. while loading.
+pub(crate) struct CachingAuxStore<'a, B> {
+	backend: &'a B,
+	cache: parking_lot::Mutex<HashMap<Vec<u8>, Option<Vec<u8>>>>,
+}
 
-const CURRENT_VERSION: u32 = 2;
+impl<'a, B> CachingAuxStore<'a, B> {
+	/// Wrap the given backend.
+	pub(crate) fn new(backend: &'a B) -> Self {
+		CachingAuxStore { backend, cache: Default::default() }
+	}
+}
+
+impl<'a, B: AuxStore> AuxStore for CachingAuxStore<'a, B> {
+	fn insert_aux<
+		'x,
+		'y: 'x,
+		'z: 'x,
+		I: IntoIterator<Item=&'x(&'z [u8], &'z [u8])>,
+		D: IntoIterator<Item=&'x &'y [u8]>,
+	>(&self, insert: I, delete: D) -> ClientResult<()> {
+		let insert = insert.into_iter().collect::<Vec<_>>();
+		let delete = delete.into_iter().collect::<Vec<_>>();
+
+		{
+			let mut cache = self.cache.lock();
+			for key in insert.iter().map(|(key, _)| *key).chain(delete.iter().map(|key| **key)) {
+				cache.remove(key);
+			}
+		}
+
+		self.backend.insert_aux(insert, delete)
+	}
+
+	fn get_aux(&self, key: &[u8]) -> ClientResult<Option<Vec<u8>>> {
+		if let Some(value) = self.cache.lock().get(key) {
+			return Ok(value.clone());
+		}
+
+		let value = self.backend.get_aux(key)?;
+		self.cache.lock().insert(key.to_vec(), value.clone());
+		Ok(value)
+	}
+}
+
+/// Observer of the entries written to the aux-db, e.g. for live debugging.
+pub trait AuxWriteObserver {
+	/// Called for each entry written, before it is written.
+	fn on_write(&self, key: &[u8], value_len: usize);
+}
+
+/// An `AuxStore` reporting the entries written through it to the given
+/// observer, if any. Writes are passed through as is without an observer. Pass
+/// it to `write_voter_set_state` etc., or use it in the `write_aux` closure of
+/// `commit_grandpa_state` and `update_consensus_changes`.
+pub struct ObservedAuxStore<'a, B> {
+	backend: &'a B,
+	observer: Option<&'a dyn AuxWriteObserver>,
+}
+
+impl<'a, B> ObservedAuxStore<'a, B> {
+	/// Wrap the given backend.
+	pub fn new(backend: &'a B, observer: Option<&'a dyn AuxWriteObserver>) -> Self {
+		ObservedAuxStore { backend, observer }
+	}
+}
+
+impl<'a, B: AuxStore> AuxStore for ObservedAuxStore<'a, B> {
+	fn insert_aux<
+		'x,
+		'y: 'x,
+		'z: 'x,
+		I: IntoIterator<Item=&'x(&'z [u8], &'z [u8])>,
+		D: IntoIterator<Item=&'x &'y [u8]>,
+	>(&self, insert: I, delete: D) -> ClientResult<()> {
+		match self.observer {
+			Some(observer) => {
+				let insert = insert.into_iter().collect::<Vec<_>>();
+				for (key, value) in &insert {
+					observer.on_write(key, value.len());
+				}
+
+				self.backend.insert_aux(insert, delete)
+			},
+			None => self.backend.insert_aux(insert, delete),
+		}
+	}
+
+	fn get_aux(&self, key: &[u8]) -> ClientResult<Option<Vec<u8>>> {
+		self.backend.get_aux(key)
+	}
+}
 
 /// The voter set state.
 #[derive(Debug, Clone, Encode, Decode)]
@@ -69,11 +267,13 @@ struct V0AuthoritySet<H, N> {
 	pending_changes: Vec<V0PendingChange<H, N>>,
 }
 
-impl<H, N> Into<AuthoritySet<H, N>> for V0AuthoritySet<H, N>
+impl<H, N> V0AuthoritySet<H, N>
 where H: Clone + Debug + PartialEq,
 	  N: Clone + Debug + Ord,
 {
-	fn into(self) -> AuthoritySet<H, N> {
+	/// Convert to the current authority set, fails if the stored authorities
+	/// are empty or have a zero weight.
+	fn into_authority_set(self) -> Option<AuthoritySet<H, N>> {
 		let mut pending_standard_changes = ForkTree::new();
 
 		for old_change in self.pending_changes {
@@ -97,531 +297,7560 @@ where H: Clone + Debug + PartialEq,
 			}
 		}
 
-		let authority_set = AuthoritySet::new(
+		AuthoritySet::new(
 			self.current_authorities,
 			self.set_id,
 			pending_standard_changes,
 			Vec::new(),
-		);
-
-		authority_set.expect("current_authorities is non-empty and weights are non-zero; qed.")
+		)
 	}
 }
 
-pub(crate) fn load_decode<B: AuxStore, T: Decode>(backend: &B, key: &[u8]) -> ClientResult<Option<T>> {
-	match backend.get_aux(key)? {
-		None => Ok(None),
-		Some(t) => T::decode(&mut &t[..])
-			.map_err(
-				|e| ClientError::Backend(format!("GRANDPA DB is corrupted: {}", e.what())),
-			)
-			.map(Some)
-	}
+/// A completed round as stored up to version 3, without a completion time.
+#[derive(Debug, Clone, Encode)]
+struct V3CompletedRound<Block: BlockT> {
+	number: RoundNumber,
+	state: RoundState<Block::Hash, NumberFor<Block>>,
+	base: (Block::Hash, NumberFor<Block>),
+	votes: Vec<SignedMessage<Block>>,
 }
 
-/// Persistent data kept between runs.
-pub(crate) struct PersistentData<Block: BlockT> {
-	pub(crate) authority_set: SharedAuthoritySet<Block::Hash, NumberFor<Block>>,
-	pub(crate) consensus_changes: SharedConsensusChanges<Block::Hash, NumberFor<Block>>,
-	pub(crate) set_state: SharedVoterSetState<Block>,
-}
+impl<Block: BlockT> Decode for V3CompletedRound<Block> {
+	fn decode<I: parity_scale_codec::Input>(input: &mut I) -> Result<Self, parity_scale_codec::Error> {
+		let number = Decode::decode(input)?;
+		let state = Decode::decode(input)?;
+		let base = Decode::decode(input)?;
 
-fn migrate_from_version0<Block: BlockT, B, G>(
-	backend: &B,
-	genesis_round: &G,
-) -> ClientResult<Option<(
-	AuthoritySet<Block::Hash, NumberFor<Block>>,
-	VoterSetState<Block>,
-)>> where B: AuxStore,
-		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
-{
-	CURRENT_VERSION.using_encoded(|s|
-		backend.insert_aux(&[(VERSION_KEY, s)], &[])
-	)?;
+		// a pre-release format didn't store the votes. It can only be told apart
+		// if the round is at the end of the input.
+		let votes = match input.remaining_len()? {
+			Some(0) => Vec::new(),
+			_ => Decode::decode(input)?,
+		};
 
-	if let Some(old_set) = load_decode::<_, V0AuthoritySet<Block::Hash, NumberFor<Block>>>(
-		backend,
-		AUTHORITY_SET_KEY,
-	)? {
-		let new_set: AuthoritySet<Block::Hash, NumberFor<Block>> = old_set.into();
-		backend.insert_aux(&[(AUTHORITY_SET_KEY, new_set.encode().as_slice())], &[])?;
+		Ok(V3CompletedRound { number, state, base, votes })
+	}
+}
 
-		let (last_round_number, last_round_state) = match load_decode::<_, V0VoterSetState<Block::Hash, NumberFor<Block>>>(
-			backend,
-			SET_STATE_KEY,
-		)? {
-			Some((number, state)) => (number, state),
-			None => (0, genesis_round()),
-		};
+/// Completed rounds (newest first), set id and voters.
+type V3CompletedRounds<Block> = (Vec<V3CompletedRound<Block>>, SetId, Vec<AuthorityId>);
 
-		let set_id = new_set.current().0;
+/// The voter set state as stored up to version 3.
+#[derive(Debug, Encode, Decode)]
+enum V3VoterSetState<Block: BlockT> {
+	Live {
+		completed_rounds: V3CompletedRounds<Block>,
+		current_rounds: CurrentRounds<Block>,
+	},
+	Paused {
+		completed_rounds: V3CompletedRounds<Block>,
+	},
+}
 
-		let base = last_round_state.prevote_ghost
-			.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+impl<Block: BlockT> Into<VoterSetState<Block>> for V3VoterSetState<Block> {
+	fn into(self) -> VoterSetState<Block> {
+		let completed_rounds = |(rounds, set_id, voters): V3CompletedRounds<Block>| {
+			let rounds = rounds.into_iter().map(|round| CompletedRound {
+				number: round.number,
+				state: round.state,
+				base: round.base,
+				votes: round.votes,
+				completed_at: None,
+				set_id: None,
+			}).collect();
 
-		let mut current_rounds = CurrentRounds::new();
-		current_rounds.insert(last_round_number + 1, HasVoted::No);
+			let mut completed_rounds = CompletedRounds::from_rounds(rounds, set_id, voters);
+			completed_rounds.fill_round_set_ids();
+			completed_rounds
+		};
 
-		let set_state = VoterSetState::Live {
-			completed_rounds: CompletedRounds::new(
-				CompletedRound {
-					number: last_round_number,
-					state: last_round_state,
-					votes: Vec::new(),
-					base,
+		match self {
+			V3VoterSetState::Live { completed_rounds: rounds, current_rounds } =>
+				VoterSetState::Live {
+					completed_rounds: completed_rounds(rounds),
+					current_rounds,
 				},
-				set_id,
-				&new_set,
-			),
-			current_rounds,
-		};
+			V3VoterSetState::Paused { completed_rounds: rounds } =>
+				paused_since_last_round(completed_rounds(rounds), PauseReason::Unknown),
+		}
+	}
+}
 
-		backend.insert_aux(&[(SET_STATE_KEY, set_state.encode().as_slice())], &[])?;
+impl<'a, Block: BlockT> From<&'a VoterSetState<Block>> for V3VoterSetState<Block> {
+	fn from(state: &'a VoterSetState<Block>) -> Self {
+		let completed_rounds = |completed_rounds: &CompletedRounds<Block>| {
+			let (set_id, voters) = completed_rounds.set_info();
+			let rounds = completed_rounds.iter().rev().map(|round| V3CompletedRound {
+				number: round.number,
+				state: round.state.clone(),
+				base: round.base,
+				votes: round.votes.clone(),
+			}).collect();
 
-		return Ok(Some((new_set, set_state)));
+			(rounds, set_id, voters.to_vec())
+		};
+
+		match state {
+			VoterSetState::Live { completed_rounds: rounds, current_rounds } =>
+				V3VoterSetState::Live {
+					completed_rounds: completed_rounds(rounds),
+					current_rounds: current_rounds.clone(),
+				},
+			VoterSetState::Paused { completed_rounds: rounds, .. } =>
+				V3VoterSetState::Paused {
+					completed_rounds: completed_rounds(rounds),
+				},
+		}
 	}
+}
 
-	Ok(None)
+/// A completed round as stored in versions 4 and 5, without a set id.
+#[derive(Debug, Clone, Encode, Decode)]
+struct V5CompletedRound<Block: BlockT> {
+	number: RoundNumber,
+	state: RoundState<Block::Hash, NumberFor<Block>>,
+	base: (Block::Hash, NumberFor<Block>),
+	votes: Vec<SignedMessage<Block>>,
+	completed_at: Option<u64>,
 }
 
-fn migrate_from_version1<Block: BlockT, B, G>(
-	backend: &B,
-	genesis_round: &G,
-) -> ClientResult<Option<(
-	AuthoritySet<Block::Hash, NumberFor<Block>>,
-	VoterSetState<Block>,
-)>> where B: AuxStore,
-		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
-{
-	CURRENT_VERSION.using_encoded(|s|
-		backend.insert_aux(&[(VERSION_KEY, s)], &[])
-	)?;
+impl<Block: BlockT> Into<CompletedRound<Block>> for V5CompletedRound<Block> {
+	fn into(self) -> CompletedRound<Block> {
+		CompletedRound {
+			number: self.number,
+			state: self.state,
+			base: self.base,
+			votes: self.votes,
+			completed_at: self.completed_at,
+			set_id: None,
+		}
+	}
+}
 
-	if let Some(set) = load_decode::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(
-		backend,
-		AUTHORITY_SET_KEY,
-	)? {
-		let set_id = set.current().0;
+/// Completed rounds as stored in versions 4 and 5. Once decoded, the rounds of
+/// the current set are assigned its set id, see `fill_round_set_ids`.
+#[derive(Debug)]
+struct V5CompletedRounds<Block: BlockT>(CompletedRounds<Block>);
 
-		let completed_rounds = |number, state, base| CompletedRounds::new(
-			CompletedRound {
-				number,
-				state,
-				votes: Vec::new(),
-				base,
-			},
-			set_id,
-			&set,
-		);
+impl<Block: BlockT> Encode for V5CompletedRounds<Block> {
+	fn encode(&self) -> Vec<u8> {
+		let (set_id, voters) = self.0.set_info();
+		let rounds = self.0.iter().rev().map(|round| V5CompletedRound::<Block> {
+			number: round.number,
+			state: round.state.clone(),
+			base: round.base,
+			votes: round.votes.clone(),
+			completed_at: round.completed_at,
+		}).collect::<Vec<_>>();
 
-		let set_state = match load_decode::<_, V1VoterSetState<Block::Hash, NumberFor<Block>>>(
-			backend,
-			SET_STATE_KEY,
-		)? {
-			Some(V1VoterSetState::Paused(last_round_number, set_state)) => {
-				let base = set_state.prevote_ghost
-					.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+		(rounds, set_id, voters).encode()
+	}
+}
 
-				VoterSetState::Paused {
-					completed_rounds: completed_rounds(last_round_number, set_state, base),
-				}
-			},
-			Some(V1VoterSetState::Live(last_round_number, set_state)) => {
-				let base = set_state.prevote_ghost
-					.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+impl<Block: BlockT> Decode for V5CompletedRounds<Block> {
+	fn decode<I: parity_scale_codec::Input>(input: &mut I) -> Result<Self, parity_scale_codec::Error> {
+		let mut completed_rounds = CompletedRounds::decode_as::<V5CompletedRound<Block>, _>(input)?;
+		completed_rounds.fill_round_set_ids();
 
-				let mut current_rounds = CurrentRounds::new();
-				current_rounds.insert(last_round_number + 1, HasVoted::No);
+		Ok(V5CompletedRounds(completed_rounds))
+	}
+}
 
-				VoterSetState::Live {
-					completed_rounds: completed_rounds(last_round_number, set_state, base),
-					current_rounds,
-				}
-			},
-			None => {
-				let set_state = genesis_round();
-				let base = set_state.prevote_ghost
-					.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+/// The voter set state as stored in version 4, without a pause reason.
+#[derive(Debug, Encode, Decode)]
+enum V4VoterSetState<Block: BlockT> {
+	Live {
+		completed_rounds: V5CompletedRounds<Block>,
+		current_rounds: CurrentRounds<Block>,
+	},
+	Paused {
+		completed_rounds: V5CompletedRounds<Block>,
+	},
+}
 
-				VoterSetState::live(
-					set_id,
-					&set,
-					base,
-				)
-			},
-		};
+impl<Block: BlockT> Into<VoterSetState<Block>> for V4VoterSetState<Block> {
+	fn into(self) -> VoterSetState<Block> {
+		match self {
+			V4VoterSetState::Live { completed_rounds, current_rounds } =>
+				VoterSetState::Live { completed_rounds: completed_rounds.0, current_rounds },
+			V4VoterSetState::Paused { completed_rounds } =>
+				paused_since_last_round(completed_rounds.0, PauseReason::Unknown),
+		}
+	}
+}
 
-		backend.insert_aux(&[(SET_STATE_KEY, set_state.encode().as_slice())], &[])?;
+/// The voter set state as stored in version 5, without the set ids of the
+/// completed rounds.
+#[derive(Debug, Encode, Decode)]
+enum V5VoterSetState<Block: BlockT> {
+	Live {
+		completed_rounds: V5CompletedRounds<Block>,
+		current_rounds: CurrentRounds<Block>,
+	},
+	Paused {
+		completed_rounds: V5CompletedRounds<Block>,
+		reason: PauseReason,
+	},
+}
 
-		return Ok(Some((set, set_state)));
+impl<Block: BlockT> Into<VoterSetState<Block>> for V5VoterSetState<Block> {
+	fn into(self) -> VoterSetState<Block> {
+		match self {
+			V5VoterSetState::Live { completed_rounds, current_rounds } =>
+				VoterSetState::Live { completed_rounds: completed_rounds.0, current_rounds },
+			V5VoterSetState::Paused { completed_rounds, reason } =>
+				paused_since_last_round(completed_rounds.0, reason),
+		}
 	}
+}
 
-	Ok(None)
+/// The voter set state as stored in version 6, without the block a paused
+/// voter set state was paused since.
+#[derive(Debug, Encode, Decode)]
+enum V6VoterSetState<Block: BlockT> {
+	Live {
+		completed_rounds: CompletedRounds<Block>,
+		current_rounds: CurrentRounds<Block>,
+	},
+	Paused {
+		completed_rounds: CompletedRounds<Block>,
+		reason: PauseReason,
+	},
 }
 
-/// Load or initialize persistent data from backend.
-pub(crate) fn load_persistent<Block: BlockT, B, G>(
-	backend: &B,
-	genesis_hash: Block::Hash,
-	genesis_number: NumberFor<Block>,
-	genesis_authorities: G,
-)
-	-> ClientResult<PersistentData<Block>>
-	where
-		B: AuxStore,
-		G: FnOnce() -> ClientResult<AuthorityList>,
-{
-	let version: Option<u32> = load_decode(backend, VERSION_KEY)?;
-	let consensus_changes = load_decode(backend, CONSENSUS_CHANGES_KEY)?
-		.unwrap_or_else(ConsensusChanges::<Block::Hash, NumberFor<Block>>::empty);
+impl<Block: BlockT> Into<VoterSetState<Block>> for V6VoterSetState<Block> {
+	fn into(self) -> VoterSetState<Block> {
+		match self {
+			V6VoterSetState::Live { completed_rounds, current_rounds } =>
+				VoterSetState::Live { completed_rounds, current_rounds },
+			V6VoterSetState::Paused { completed_rounds, reason } =>
+				paused_since_last_round(completed_rounds, reason),
+		}
+	}
+}
 
-	let make_genesis_round = move || RoundState::genesis((genesis_hash, genesis_number));
+/// Pause a voter set state migrated from a version that didn't record the
+/// block it was paused since, which is taken to be the block finalized by its
+/// last completed round, or that round's base if it finalized none.
+fn paused_since_last_round<Block: BlockT>(
+	completed_rounds: CompletedRounds<Block>,
+	reason: PauseReason,
+) -> VoterSetState<Block> {
+	let last = completed_rounds.last();
+	let since = last.state.finalized.as_ref().map_or(last.base.1, |(_, number)| *number);
 
-	match version {
-		None => {
-			if let Some((new_set, set_state)) = migrate_from_version0::<Block, _, _>(backend, &make_genesis_round)? {
-				return Ok(PersistentData {
-					authority_set: new_set.into(),
-					consensus_changes: Arc::new(consensus_changes.into()),
-					set_state: set_state.into(),
-				});
-			}
-		},
-		Some(1) => {
-			if let Some((new_set, set_state)) = migrate_from_version1::<Block, _, _>(backend, &make_genesis_round)? {
-				return Ok(PersistentData {
-					authority_set: new_set.into(),
-					consensus_changes: Arc::new(consensus_changes.into()),
-					set_state: set_state.into(),
-				});
-			}
-		},
-		Some(2) => {
-			if let Some(set) = load_decode::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(
-				backend,
-				AUTHORITY_SET_KEY,
-			)? {
-				let set_state = match load_decode::<_, VoterSetState<Block>>(
-					backend,
-					SET_STATE_KEY,
-				)? {
-					Some(state) => state,
-					None => {
-						let state = make_genesis_round();
-						let base = state.prevote_ghost
-							.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
-
-						VoterSetState::live(
-							set.current().0,
-							&set,
-							base,
-						)
-					}
-				};
+	VoterSetState::paused(completed_rounds, reason, since)
+}
 
-				return Ok(PersistentData {
-					authority_set: set.into(),
-					consensus_changes: Arc::new(consensus_changes.into()),
-					set_state: set_state.into(),
-				});
-			}
+/// Errors returned when reading the GRANDPA schema from the aux-db.
+#[derive(Debug, derive_more::Display)]
+pub(crate) enum GrandpaAuxError {
+	/// The entry stored under the given key failed to decode.
+	#[display(fmt = "GRANDPA DB is corrupted: failed to decode {}: {}", key, reason)]
+	CorruptEntry {
+		/// The key of the corrupted entry.
+		key: String,
+		/// The decoding error.
+		reason: &'static str,
+	},
+	/// The checksum of the entry stored under the given key doesn't match its
+	/// contents.
+	#[display(fmt = "GRANDPA DB is corrupted: checksum mismatch for {}", key)]
+	ChecksumMismatch {
+		/// The key of the corrupted entry.
+		key: String,
+	},
+	/// The stored schema version is not supported by this node.
+	#[display(fmt = "Unsupported GRANDPA DB version: {:?}", _0)]
+	UnsupportedVersion(u32),
+	/// The stored schema version must be migrated by `load_persistent` first.
+	#[display(fmt = "GRANDPA DB version {} is outdated, it must be migrated by loading it with `load_persistent` first", _0)]
+	OutdatedVersion(u32),
+	/// The schema version is stored but the authority set is missing.
+	#[display(fmt = "GRANDPA DB is inconsistent: version {} is stored but the authority set is missing", _0)]
+	MissingAuthoritySet(u32),
+	/// The DB was initialized but the authority set has been deleted since.
+	#[display(fmt = "GRANDPA DB was initialized but its authority set is missing, it may have been deleted")]
+	AuthoritySetDeleted,
+	/// A completed round stored under the given key has no prevote ghost.
+	#[display(fmt = "GRANDPA DB is corrupted: completed round {} stored in {} has no prevote ghost", round, key)]
+	MissingPrevoteGhost {
+		/// The key of the corrupted entry.
+		key: String,
+		/// The number of the completed round.
+		round: RoundNumber,
+	},
+	/// Fetching the genesis authorities failed.
+	#[display(fmt = "failed to fetch GRANDPA genesis authorities: {}", _0)]
+	GenesisAuthorities(ClientError),
+	/// The genesis authorities are empty or have a zero weight.
+	#[display(fmt = "GRANDPA genesis authorities are empty or have a zero weight")]
+	InvalidGenesisAuthorities,
+	/// The authority set isn't stored yet.
+	#[display(fmt = "GRANDPA authority set is not initialized")]
+	Uninitialized,
+	/// An authority weight update doesn't apply to the stored authority set.
+	#[display(fmt = "Invalid GRANDPA authority weight update for {}: {}", authority, reason)]
+	InvalidWeightUpdate {
+		/// The authority whose weight was updated.
+		authority: String,
+		/// Why the update is invalid.
+		reason: &'static str,
+	},
+	/// An authority set with a lower set id than the stored one was written.
+	#[display(fmt = "GRANDPA set id would go backwards from {} to {}", stored, set_id)]
+	SetIdRegression {
+		/// The set id of the stored authority set.
+		stored: SetId,
+		/// The set id of the authority set being written.
+		set_id: SetId,
+	},
+	/// The total weight of the stored authority set overflows.
+	#[display(fmt = "GRANDPA authority set weight overflows")]
+	WeightOverflow,
+	/// An authority of the stored authority set has a zero weight.
+	#[display(fmt = "GRANDPA DB is corrupted: authority {} has a zero weight", _0)]
+	ZeroWeight(String),
+	/// The DB was initialized with a different genesis block.
+	#[display(fmt = "GRANDPA DB belongs to a different chain: initialized with genesis {}, got {}", stored, given)]
+	GenesisMismatch {
+		/// The genesis hash and number the DB was initialized with.
+		stored: String,
+		/// The genesis hash and number passed on load.
+		given: String,
+	},
+	/// The stored schema version must be migrated but the backend rejects writes.
+	#[display(fmt = "GRANDPA DB version {} requires migration but the backend is read-only", _0)]
+	ReadOnlyBackend(u32),
+	/// The voter set state belongs to another authority set than the stored one.
+	#[display(fmt = "GRANDPA voter set state is for set id {} but the authority set id is {}", set_state, authority_set)]
+	SetIdMismatch {
+		/// The set id of the voter set state.
+		set_state: SetId,
+		/// The set id of the authority set.
+		authority_set: SetId,
+	},
+	/// The encrypted voter set state can't be decrypted.
+	#[display(fmt = "GRANDPA voter set state can't be decrypted: {}", _0)]
+	UndecryptableState(&'static str),
+	/// The pending forced changes of the stored authority set conflict.
+	#[display(fmt = "GRANDPA DB is inconsistent: {}", _0)]
+	InvalidForcedChanges(String),
+	/// A voter set state diff doesn't apply to the given voter set state.
+	#[display(fmt = "GRANDPA voter set state diff doesn't apply: {}", _0)]
+	InvalidDiff(&'static str),
+	/// The backend failed.
+	#[display(fmt = "{}", _0)]
+	Backend(ClientError),
+}
+
+impl From<ClientError> for GrandpaAuxError {
+	fn from(error: ClientError) -> Self {
+		GrandpaAuxError::Backend(error)
+	}
+}
+
+impl From<GrandpaAuxError> for ClientError {
+	fn from(error: GrandpaAuxError) -> Self {
+		match error {
+			GrandpaAuxError::Backend(error) => error,
+			error => ClientError::Backend(error.to_string()),
 		}
-		Some(other) => return Err(ClientError::Backend(
-			format!("Unsupported GRANDPA DB version: {:?}", other)
-		).into()),
 	}
+}
 
-	// genesis.
-	info!(target: "afg", "👴 Loading GRANDPA authority set \
-		from genesis on what appears to be first startup.");
+fn decode_entry<T: Decode>(key: &[u8], mut value: &[u8]) -> Result<T, GrandpaAuxError> {
+	T::decode(&mut value).map_err(|e| GrandpaAuxError::CorruptEntry {
+		key: String::from_utf8_lossy(key).into_owned(),
+		reason: e.what(),
+	})
+}
 
-	let genesis_authorities = genesis_authorities()?;
-	let genesis_set = AuthoritySet::genesis(genesis_authorities.clone())
-		.expect("genesis authorities is non-empty; all weights are non-zero; qed.");
-	let state = make_genesis_round();
-	let base = state.prevote_ghost
-		.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+pub(crate) fn load_decode<B: AuxStore, T: Decode>(
+	backend: &B,
+	key: &[u8],
+) -> Result<Option<T>, GrandpaAuxError> {
+	load_decode_with_metrics(backend, key, None)
+}
 
-	let genesis_state = VoterSetState::live(
-		0,
-		&genesis_set,
-		base,
-	);
+/// Load and decode an entry like `load_decode`, counting the decode attempt
+/// and its failure, if any, in the given metrics. Absent entries aren't
+/// counted.
+pub(crate) fn load_decode_with_metrics<B: AuxStore, T: Decode>(
+	backend: &B,
+	key: &[u8],
+	metrics: Option<&DecodeMetrics>,
+) -> Result<Option<T>, GrandpaAuxError> {
+	let stored = match backend.get_aux(key)? {
+		None => return Ok(None),
+		Some(stored) => stored,
+	};
 
-	backend.insert_aux(
-		&[
-			(AUTHORITY_SET_KEY, genesis_set.encode().as_slice()),
-			(SET_STATE_KEY, genesis_state.encode().as_slice()),
-		],
-		&[],
-	)?;
+	let decoded = decode_entry(key, &stored);
+	if let Some(metrics) = metrics {
+		metrics.note_decode(key, decoded.is_ok());
+	}
 
-	Ok(PersistentData {
-		authority_set: genesis_set.into(),
-		set_state: genesis_state.into(),
-		consensus_changes: Arc::new(consensus_changes.into()),
-	})
+	decoded.map(Some)
 }
 
-/// Update the authority set on disk after a change.
-///
-/// If there has just been a handoff, pass a `new_set` parameter that describes the
-/// handoff. `set` in all cases should reflect the current authority set, with all
-/// changes and handoffs applied.
-pub(crate) fn update_authority_set<Block: BlockT, F, R>(
+/// The schema version since which the authority set and voter set state are
+/// always stored framed, see `framed`.
+const FRAMED_SINCE: u32 = 8;
+/// The format of the framed values written by this node.
+const VALUE_FORMAT: u8 = 1;
+const FRAME_HEADER_LEN: usize = 5;
+
+/// Frame the given encoded value for storage: a format byte, describing the
+/// layout of the value independently of the schema version, followed by a
+/// CRC32 checksum of the value. A frame can't be told apart from a plain
+/// encoding by its contents, e.g. a version 0 voter set state starts with a
+/// `u64` round number which may start with any byte, so values are only
+/// unframed at the schema versions they are known to be framed at.
+fn framed(value: &[u8]) -> Vec<u8> {
+	let mut framed = Vec::with_capacity(FRAME_HEADER_LEN + value.len());
+	framed.push(VALUE_FORMAT);
+	framed.extend_from_slice(&crc32fast::hash(value).to_le_bytes());
+	framed.extend_from_slice(value);
+	framed
+}
+
+/// Unframe a value stored with `framed`, validating its format and verifying
+/// its checksum.
+fn unframe<'a>(key: &[u8], stored: &'a [u8]) -> Result<&'a [u8], GrandpaAuxError> {
+	match stored.first() {
+		Some(&VALUE_FORMAT) if stored.len() >= FRAME_HEADER_LEN => {},
+		_ => return Err(GrandpaAuxError::CorruptEntry {
+			key: String::from_utf8_lossy(key).into_owned(),
+			reason: "unknown value format",
+		}),
+	}
+
+	let mut checksum = [0u8; 4];
+	checksum.copy_from_slice(&stored[1..FRAME_HEADER_LEN]);
+
+	let value = &stored[FRAME_HEADER_LEN..];
+	if u32::from_le_bytes(checksum) != crc32fast::hash(value) {
+		return Err(GrandpaAuxError::ChecksumMismatch {
+			key: String::from_utf8_lossy(key).into_owned(),
+		});
+	}
+
+	Ok(value)
+}
+
+/// Prefixes of the format byte and checksum that values were framed with
+/// before `FRAMED_SINCE`, when values were stored both plain and framed.
+const LEGACY_FORMAT_MAGIC: &[u8] = b"\xfffmt";
+const LEGACY_CHECKSUM_MAGIC: &[u8] = b"\xffcrc";
+
+/// Unframe a value stored before `FRAMED_SINCE`. The legacy prefixes are
+/// stripped, verifying the checksum, if present. Values stored plain are
+/// returned as is.
+fn unframe_legacy<'a>(key: &[u8], stored: &'a [u8]) -> Result<&'a [u8], GrandpaAuxError> {
+	let stored = if stored.starts_with(LEGACY_FORMAT_MAGIC) {
+		match stored.get(LEGACY_FORMAT_MAGIC.len()) {
+			Some(&VALUE_FORMAT) => &stored[LEGACY_FORMAT_MAGIC.len() + 1..],
+			_ => return Err(GrandpaAuxError::CorruptEntry {
+				key: String::from_utf8_lossy(key).into_owned(),
+				reason: "unknown value format",
+			}),
+		}
+	} else {
+		stored
+	};
+
+	let header_len = LEGACY_CHECKSUM_MAGIC.len() + 4;
+	if stored.len() < header_len || !stored.starts_with(LEGACY_CHECKSUM_MAGIC) {
+		return Ok(stored);
+	}
+
+	let mut checksum = [0u8; 4];
+	checksum.copy_from_slice(&stored[LEGACY_CHECKSUM_MAGIC.len()..header_len]);
+
+	let value = &stored[header_len..];
+	if u32::from_le_bytes(checksum) != crc32fast::hash(value) {
+		return Err(GrandpaAuxError::ChecksumMismatch {
+			key: String::from_utf8_lossy(key).into_owned(),
+		});
+	}
+
+	Ok(value)
+}
+
+/// Unframe a value stored at the given schema version.
+fn unframe_at<'a>(version: u32, key: &[u8], stored: &'a [u8]) -> Result<&'a [u8], GrandpaAuxError> {
+	if version >= FRAMED_SINCE {
+		unframe(key, stored)
+	} else {
+		unframe_legacy(key, stored)
+	}
+}
+
+fn load_unframed_at<B: AuxStore>(
+	backend: &B,
+	version: u32,
+	key: &[u8],
+) -> Result<Option<Vec<u8>>, GrandpaAuxError> {
+	match backend.get_aux(key)? {
+		None => Ok(None),
+		Some(stored) => unframe_at(version, key, &stored).map(|value| Some(value.to_vec())),
+	}
+}
+
+/// Load and decode a value stored at the given schema version, unframing it
+/// first, see `unframe_at`.
+fn load_decode_at<B: AuxStore, T: Decode>(
+	backend: &B,
+	version: u32,
+	key: &[u8],
+) -> Result<Option<T>, GrandpaAuxError> {
+	match backend.get_aux(key)? {
+		None => Ok(None),
+		Some(stored) => decode_entry(key, unframe_at(version, key, &stored)?).map(Some),
+	}
+}
+
+/// Load and decode a value written with `framed` at the current schema
+/// version, verifying its checksum first.
+pub(crate) fn load_decode_checked<B: AuxStore, T: Decode>(
+	backend: &B,
+	key: &[u8],
+) -> Result<Option<T>, GrandpaAuxError> {
+	load_decode_at(backend, CURRENT_VERSION, key)
+}
+
+/// A symmetric cipher encrypting the voter set state at rest, which reveals
+/// the voting history of the node. The key is managed by the embedder.
+pub trait StateCipher: Send + Sync {
+	/// Encrypt the given plaintext.
+	fn encrypt(&self, plaintext: &[u8]) -> Vec<u8>;
+	/// Decrypt the given ciphertext. Returns `None` if it wasn't encrypted with
+	/// this key, which requires the cipher to be authenticated.
+	fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>>;
+}
+
+impl Debug for dyn StateCipher {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str("StateCipher")
+	}
+}
+
+/// Prefix of values encrypted with a `StateCipher`, followed by the cipher
+/// version byte. A leading `0xff` byte can't start a valid encoding of the
+/// voter set state, so plaintext and encrypted values can coexist.
+const ENCRYPTED_MAGIC: &[u8] = b"\xffenc";
+/// The version of the encrypted value layout written by this node.
+const CIPHER_VERSION: u8 = 1;
+
+/// Encrypt the given stored value with the cipher, if any.
+fn encrypted(stored: Vec<u8>, cipher: Option<&dyn StateCipher>) -> Vec<u8> {
+	match cipher {
+		Some(cipher) => {
+			let mut encrypted = ENCRYPTED_MAGIC.to_vec();
+			encrypted.push(CIPHER_VERSION);
+			encrypted.extend(cipher.encrypt(&stored));
+			encrypted
+		},
+		None => stored,
+	}
+}
+
+/// Decrypt the given stored value with the cipher. Values stored without
+/// encryption are returned as is.
+fn decrypted(stored: Vec<u8>, cipher: Option<&dyn StateCipher>) -> Result<Vec<u8>, GrandpaAuxError> {
+	if !stored.starts_with(ENCRYPTED_MAGIC) {
+		return Ok(stored);
+	}
+
+	match (stored.get(ENCRYPTED_MAGIC.len()), cipher) {
+		(Some(&CIPHER_VERSION), Some(cipher)) => cipher.decrypt(&stored[ENCRYPTED_MAGIC.len() + 1..])
+			.ok_or(GrandpaAuxError::UndecryptableState("wrong key or tampered ciphertext")),
+		(Some(&CIPHER_VERSION), None) => Err(GrandpaAuxError::UndecryptableState("no cipher configured")),
+		_ => Err(GrandpaAuxError::UndecryptableState("unknown cipher version")),
+	}
+}
+
+/// Encode the voter set state for storage, framed and encrypted with the
+/// cipher if any. Every write of the voter set state is encoded here.
+fn encoded_set_state<Block: BlockT>(
+	state: &VoterSetState<Block>,
+	cipher: Option<&dyn StateCipher>,
+) -> Vec<u8> {
+	encrypted(framed(&state.encode()), cipher)
+}
+
+/// Load the voter set state stored at the given schema version, decrypting it
+/// with the cipher if it was stored encrypted, and unframe it. Every read of
+/// the voter set state goes through here.
+fn load_set_state_unframed_at<B: AuxStore>(
+	backend: &B,
+	version: u32,
+	cipher: Option<&dyn StateCipher>,
+) -> Result<Option<Vec<u8>>, GrandpaAuxError> {
+	match backend.get_aux(SET_STATE_KEY)? {
+		None => Ok(None),
+		Some(stored) => {
+			let stored = decrypted(stored, cipher)?;
+			unframe_at(version, SET_STATE_KEY, &stored).map(|value| Some(value.to_vec()))
+		},
+	}
+}
+
+/// Load and decode the voter set state stored in the format `S` at the given
+/// schema version, see `load_set_state_unframed_at`.
+fn load_set_state_at<S: Decode, B: AuxStore>(
+	backend: &B,
+	version: u32,
+	cipher: Option<&dyn StateCipher>,
+) -> Result<Option<S>, GrandpaAuxError> {
+	match load_set_state_unframed_at(backend, version, cipher)? {
+		None => Ok(None),
+		Some(value) => decode_entry(SET_STATE_KEY, &value).map(Some),
+	}
+}
+
+/// Load and decode the voter set state stored at the current schema version.
+fn load_set_state<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	cipher: Option<&dyn StateCipher>,
+) -> Result<Option<VoterSetState<Block>>, GrandpaAuxError> {
+	load_set_state_at(backend, CURRENT_VERSION, cipher)
+}
+
+/// Persistent data kept between runs.
+pub(crate) struct PersistentData<Block: BlockT> {
+	pub(crate) authority_set: SharedAuthoritySet<Block::Hash, NumberFor<Block>>,
+	pub(crate) consensus_changes: SharedConsensusChanges<Block::Hash, NumberFor<Block>>,
+	pub(crate) set_state: SharedVoterSetState<Block>,
+	/// The cipher the voter set state was loaded with, which must encrypt it
+	/// whenever it is written.
+	pub(crate) state_cipher: Option<Arc<dyn StateCipher>>,
+}
+
+/// A short summary of the persisted GRANDPA data, e.g. for logging on startup.
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+#[display(
+	fmt = "set_id={} authorities={} last_round={} paused={}",
+	set_id, authority_count, last_round_number, is_paused,
+)]
+pub(crate) struct PersistentSummary {
+	/// The id of the current authority set.
+	pub(crate) set_id: SetId,
+	/// The number of authorities in the current set.
+	pub(crate) authority_count: usize,
+	/// The number of the last completed round.
+	pub(crate) last_round_number: RoundNumber,
+	/// Whether the voter is paused.
+	pub(crate) is_paused: bool,
+}
+
+impl<Block: BlockT> PersistentData<Block> {
+	/// Summarize the persisted data.
+	pub(crate) fn summary(&self) -> PersistentSummary {
+		let authority_set = self.authority_set.inner().read();
+		let set_state = self.set_state.read();
+
+		PersistentSummary {
+			set_id: authority_set.set_id(),
+			authority_count: authority_set.authorities().len(),
+			last_round_number: set_state.completed_rounds().last_number(),
+			is_paused: set_state.is_paused(),
+		}
+	}
+}
+
+fn backup_key(key: &[u8], version: u32) -> Vec<u8> {
+	let mut backup = key.to_vec();
+	backup.extend(format!("_bak_v{}", version).as_bytes());
+	backup
+}
+
+/// Check that the weights of the current authorities are non-zero and that
+/// their total doesn't overflow, which would break the voting thresholds.
+fn validate_authority_weights<H, N>(set: &AuthoritySet<H, N>) -> Result<(), GrandpaAuxError> {
+	let mut total_weight = 0u64;
+	for (authority, weight) in set.authorities() {
+		if *weight == 0 {
+			return Err(GrandpaAuxError::ZeroWeight(format!("{:?}", authority)));
+		}
+
+		total_weight = total_weight.checked_add(*weight).ok_or(GrandpaAuxError::WeightOverflow)?;
+	}
+
+	Ok(())
+}
+
+/// Check that no two pending forced changes of the authority set are signaled
+/// at the same height and that none of them activates past the highest block
+/// number. Returns all violations found.
+fn forced_change_violations<Block: BlockT>(
 	set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
-	new_set: Option<&NewAuthoritySet<Block::Hash, NumberFor<Block>>>,
-	write_aux: F
-) -> R where
-	F: FnOnce(&[(&'static [u8], &[u8])]) -> R,
+) -> Vec<String> {
+	let forced_changes = set.pending_forced_changes();
+	let mut violations = Vec::new();
+
+	for (i, change) in forced_changes.iter().enumerate() {
+		if forced_changes[..i].iter().any(|other| other.canon_height == change.canon_height) {
+			violations.push(format!(
+				"forced change signaled at block {} ({:?}) has the same height as another forced change",
+				change.canon_height,
+				change.canon_hash,
+			));
+		}
+
+		if change.canon_height.checked_add(&change.delay).is_none() {
+			violations.push(format!(
+				"forced change signaled at block {} ({:?}) has an overflowing delay of {} blocks",
+				change.canon_height,
+				change.canon_hash,
+				change.delay,
+			));
+		}
+	}
+
+	violations
+}
+
+/// Check the pending forced changes of the authority set on load, see
+/// `forced_change_violations`. Violations are logged, or fail the load if
+/// `strict`.
+fn validate_forced_changes<Block: BlockT>(
+	set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+	strict: bool,
+) -> Result<(), GrandpaAuxError> {
+	let violations = forced_change_violations::<Block>(set);
+	if violations.is_empty() {
+		return Ok(());
+	}
+
+	if strict {
+		return Err(GrandpaAuxError::InvalidForcedChanges(violations.join("; ")));
+	}
+
+	for violation in violations {
+		warn!(target: "afg", "Invalid GRANDPA pending forced changes: {}.", violation);
+	}
+
+	Ok(())
+}
+
+/// Copy the raw authority set and voter set state entries to backup keys
+/// before they are overwritten by a migration from the given version. Backups
+/// left by a previously attempted migration are never overwritten.
+///
+/// This is the first write of every migration. It also stamps the version
+/// migrated from, so that it is never empty and a backend rejecting writes is
+/// reported before anything is changed.
+fn backup_before_migration<B: AuxStore>(backend: &B, version: u32) -> ClientResult<()> {
+	let mut backups = Vec::new();
+	for key in &[AUTHORITY_SET_KEY, SET_STATE_KEY] {
+		let backup = backup_key(key, version);
+		if backend.get_aux(&backup)?.is_some() {
+			continue;
+		}
+
+		if let Some(value) = backend.get_aux(key)? {
+			backups.push((backup, value));
+		}
+	}
+
+	let encoded_version = version.encode();
+	let insert = backups.iter()
+		.map(|(key, value)| (&key[..], &value[..]))
+		.chain(std::iter::once((VERSION_KEY, &encoded_version[..])))
+		.collect::<Vec<_>>();
+
+	backend.insert_aux(&insert, &[]).map_err(|err| {
+		warn!(target: "afg", "Failed to back up GRANDPA DB before migration: {}", err);
+		GrandpaAuxError::ReadOnlyBackend(version).into()
+	})
+}
+
+/// The entries introduced after version 2, by the schema version they were
+/// introduced in.
+const ENTRIES_SINCE: &[(&[u8], u32)] = &[
+	(BEST_FINALIZED_KEY, 3),
+	(LAST_JUSTIFICATION_KEY, 5),
+	(EQUIVOCATIONS_KEY, 5),
+	(MAX_SET_ID_KEY, 5),
+	(GENESIS_KEY, 5),
+	(PARTICIPATION_KEY, 5),
+	(LAST_HANDOFF_KEY, 5),
+	(INIT_KEY, 5),
+	(HANDOFF_LOG_KEY, 5),
+];
+
+/// Remove the backups taken before migrating from the given version, once the
+/// migration succeeded.
+fn remove_backups<B: AuxStore>(backend: &B, version: u32) -> ClientResult<()> {
+	let authority_set = backup_key(AUTHORITY_SET_KEY, version);
+	let set_state = backup_key(SET_STATE_KEY, version);
+
+	backend.insert_aux(&[], &[&authority_set[..], &set_state[..]])
+}
+
+/// Run the given migration from the given version. If it fails after taking
+/// the backups, the backed up entries are restored, see `restore_from_backup`.
+/// Once it succeeds, the backups are removed.
+fn run_migration<Block: BlockT, B, G>(
+	backend: &B,
+	version: u32,
+	migrate: Migration<Block, B, G>,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where
+	B: AuxStore,
+	G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	let migrated = match migrate(backend, genesis_round, cipher) {
+		Ok(migrated) => migrated,
+		Err(err) => {
+			// nothing is backed up if the backend rejects the first write.
+			if let Ok(Some(_)) = backend.get_aux(&backup_key(AUTHORITY_SET_KEY, version)) {
+				if let Err(restore_err) = restore_from_backup(backend, version) {
+					warn!(target: "afg", "Failed to restore GRANDPA DB backup of version {}: {}", version, restore_err);
+				}
+			}
+
+			return Err(err);
+		},
+	};
+
+	if let Err(err) = remove_backups(backend, version) {
+		warn!(target: "afg", "Failed to remove GRANDPA DB backup of version {}: {}", version, err);
+	}
+
+	Ok(migrated)
+}
+
+/// Restore the authority set and voter set state entries backed up before
+/// migrating from the given version, and restamp the schema version
+/// accordingly. Entries introduced after that version are removed, see
+/// `ENTRIES_SINCE`.
+pub(crate) fn restore_from_backup<B: AuxStore>(backend: &B, version: u32) -> ClientResult<()> {
+	let authority_set = backend.get_aux(&backup_key(AUTHORITY_SET_KEY, version))?
+		.ok_or_else(|| ClientError::Backend(
+			format!("No GRANDPA backup found for DB version {}", version)
+		))?;
+	let set_state = backend.get_aux(&backup_key(SET_STATE_KEY, version))?;
+	let encoded_version = version.encode();
+
+	let mut insert = vec![(AUTHORITY_SET_KEY, &authority_set[..])];
+	let mut delete = ENTRIES_SINCE.iter()
+		.filter(|(_, since)| *since > version)
+		.map(|(key, _)| *key)
+		.collect::<Vec<_>>();
+
+	match set_state {
+		Some(ref set_state) => insert.push((SET_STATE_KEY, &set_state[..])),
+		None => delete.push(SET_STATE_KEY),
+	}
+
+	if version == 0 {
+		delete.push(VERSION_KEY);
+	} else {
+		insert.push((VERSION_KEY, &encoded_version[..]));
+	}
+
+	backend.insert_aux(&insert, &delete)
+}
+
+fn migrate_from_version0<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	backup_before_migration(backend, 0)?;
+
+	if let Some(old_set) = load_decode_at::<_, V0AuthoritySet<Block::Hash, NumberFor<Block>>>(
+		backend,
+		0,
+		AUTHORITY_SET_KEY,
+	)? {
+		let new_set = old_set.into_authority_set().ok_or_else(|| GrandpaAuxError::CorruptEntry {
+			key: String::from_utf8_lossy(AUTHORITY_SET_KEY).into_owned(),
+			reason: "authorities are empty or have a zero weight",
+		})?;
+
+		let (last_round_number, last_round_state) = match load_set_state_at::<V0VoterSetState<Block::Hash, NumberFor<Block>>, _>(
+			backend,
+			0,
+			cipher,
+		)? {
+			Some((number, state)) => (number, state),
+			None => (0, genesis_round()),
+		};
+
+		let set_id = new_set.set_id();
+
+		let base = completed_round_base(last_round_number, &last_round_state)?;
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(last_round_number.saturating_add(1), HasVoted::No);
+
+		let set_state = VoterSetState::Live {
+			completed_rounds: CompletedRounds::new(
+				CompletedRound {
+					number: last_round_number,
+					state: last_round_state,
+					votes: Vec::new(),
+					completed_at: None,
+					set_id: Some(set_id),
+					base,
+				},
+				set_id,
+				&new_set,
+			),
+			current_rounds,
+		};
+
+		complete_migration(backend, Some(&new_set), Some(&set_state), cipher)?;
+
+		return Ok(Some((new_set, set_state)));
+	}
+
+	// an empty DB is initialized from genesis, the version being written along
+	// with the genesis data.
+	Ok(None)
+}
+
+/// The base of a completed round read from the stored voter set state, i.e.
+/// its prevote ghost. Fails instead of panicking if the stored state is
+/// missing it.
+fn completed_round_base<H: Clone, N: Clone>(
+	round: RoundNumber,
+	state: &RoundState<H, N>,
+) -> Result<(H, N), GrandpaAuxError> {
+	state.prevote_ghost.clone().ok_or_else(|| GrandpaAuxError::MissingPrevoteGhost {
+		key: String::from_utf8_lossy(SET_STATE_KEY).into_owned(),
+		round,
+	})
+}
+
+fn migrate_from_version1<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
 {
-	// write new authority set state to disk.
-	let encoded_set = set.encode();
+	backup_before_migration(backend, 1)?;
+
+	if let Some(set) = load_decode_at::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(
+		backend,
+		1,
+		AUTHORITY_SET_KEY,
+	)? {
+		let set_id = set.set_id();
+
+		let completed_rounds = |number, state, base| CompletedRounds::new(
+			CompletedRound {
+				number,
+				state,
+				votes: Vec::new(),
+				completed_at: None,
+				set_id: Some(set_id),
+				base,
+			},
+			set_id,
+			&set,
+		);
+
+		let set_state = match load_set_state_at::<V1VoterSetState<Block::Hash, NumberFor<Block>>, _>(
+			backend,
+			1,
+			cipher,
+		)? {
+			Some(V1VoterSetState::Paused(last_round_number, set_state)) => {
+				let base = completed_round_base(last_round_number, &set_state)?;
+
+				paused_since_last_round(
+					completed_rounds(last_round_number, set_state, base),
+					PauseReason::Unknown,
+				)
+			},
+			Some(V1VoterSetState::Live(last_round_number, set_state)) => {
+				let base = completed_round_base(last_round_number, &set_state)?;
+
+				let mut current_rounds = CurrentRounds::new();
+				current_rounds.insert(last_round_number.saturating_add(1), HasVoted::No);
+
+				VoterSetState::Live {
+					completed_rounds: completed_rounds(last_round_number, set_state, base),
+					current_rounds,
+				}
+			},
+			None => {
+				let set_state = genesis_round();
+				let base = set_state.prevote_ghost
+					.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+
+				VoterSetState::genesis_for(&set, base)
+			},
+		};
+
+		complete_migration(backend, Some(&set), Some(&set_state), cipher)?;
+
+		return Ok(Some((set, set_state)));
+	}
+
+	complete_migration::<Block, _>(backend, None, None, cipher)?;
+	Ok(None)
+}
+
+fn migrate_from_version2<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	backup_before_migration(backend, 2)?;
+
+	// the voter set state format is the same as in version 3, version 3 only
+	// adds the best finalized block which is derived from it.
+	migrate_voter_set_state::<Block, V3VoterSetState<Block>, _, _>(backend, 2, genesis_round, cipher)
+}
+
+/// Like `migrate_from_version2`, but skip completed rounds of the stored voter
+/// set state that fail to decode instead of failing the migration, see
+/// `decode_v3_voter_set_state_skipping_unreadable_rounds`.
+fn migrate_from_version2_skipping_unreadable_rounds<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	backup_before_migration(backend, 2)?;
+
+	if let Some(set) = load_decode_at::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(
+		backend,
+		2,
+		AUTHORITY_SET_KEY,
+	)? {
+		let set_state = match load_set_state_unframed_at(backend, 2, cipher)? {
+			Some(stored) =>
+				decode_v3_voter_set_state_skipping_unreadable_rounds::<Block>(&stored, &set)?.into(),
+			None => {
+				let state = genesis_round();
+				let base = state.prevote_ghost
+					.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+
+				VoterSetState::genesis_for(&set, base)
+			}
+		};
+
+		complete_migration(backend, Some(&set), Some(&set_state), cipher)?;
+
+		return Ok(Some((set, set_state)));
+	}
+
+	complete_migration::<Block, _>(backend, None, None, cipher)?;
+	Ok(None)
+}
+
+/// Decode a voter set state stored in the version 3 format, skipping a
+/// completed round that fails to decode. Rounds aren't length-prefixed, so the
+/// rounds following it are only kept if they and the rest of the state decode
+/// exactly when starting at some later offset. Otherwise they are dropped as
+/// well, and the set id, voters and current rounds are restored from the given
+/// authority set and the latest readable round.
+fn decode_v3_voter_set_state_skipping_unreadable_rounds<Block: BlockT>(
+	stored: &[u8],
+	set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+) -> Result<V3VoterSetState<Block>, GrandpaAuxError> {
+	let corrupt = |reason| GrandpaAuxError::CorruptEntry {
+		key: String::from_utf8_lossy(SET_STATE_KEY).into_owned(),
+		reason,
+	};
+
+	let mut input = stored;
+	let live = match u8::decode(&mut input) {
+		Ok(0) => true,
+		Ok(1) => false,
+		_ => return Err(corrupt("invalid voter set state variant")),
+	};
+	let count = <Compact<u32>>::decode(&mut input).map_err(|e| corrupt(e.what()))?.0 as usize;
+
+	// the given number of completed rounds followed by the set id, voters and,
+	// for a live state, current rounds, spanning the whole input.
+	let decode_rest = |mut input: &[u8], rounds| -> Option<(
+		Vec<V3CompletedRound<Block>>,
+		(SetId, Vec<AuthorityId>),
+		Option<CurrentRounds<Block>>,
+	)> {
+		let rounds = (0..rounds)
+			.map(|_| V3CompletedRound::<Block>::decode(&mut input).ok())
+			.collect::<Option<Vec<_>>>()?;
+		let set_info = Decode::decode(&mut input).ok()?;
+		let current_rounds = match live {
+			true => Some(CurrentRounds::<Block>::decode(&mut input).ok()?),
+			false => None,
+		};
+
+		if !input.is_empty() {
+			return None;
+		}
+
+		Some((rounds, set_info, current_rounds))
+	};
+
+	let mut rounds = Vec::new();
+	let mut rest = None;
+	for index in 0..count {
+		if let Some(decoded) = decode_rest(input, count - index) {
+			rest = Some(decoded);
+			break;
+		}
+
+		let start = input;
+		match V3CompletedRound::<Block>::decode(&mut input) {
+			Ok(round) => {
+				rounds.push(round);
+				continue;
+			},
+			Err(_) => match RoundNumber::decode(&mut &start[..]) {
+				Ok(number) => warn!(target: "afg", "Skipping unreadable completed round {} while migrating GRANDPA DB.", number),
+				Err(_) => warn!(target: "afg", "Skipping unreadable completed round while migrating GRANDPA DB."),
+			},
+		}
+
+		// look for the start of the next round.
+		let remaining = count - index - 1;
+		rest = (1..start.len()).find_map(|offset| decode_rest(&start[offset..], remaining));
+		if rest.is_none() {
+			warn!(target: "afg", "Dropping {} completed rounds following an unreadable one.", remaining);
+		}
+		break;
+	}
+
+	let (set_id, voters, current_rounds) = match rest {
+		Some((later_rounds, (set_id, voters), current_rounds)) => {
+			rounds.extend(later_rounds);
+			(set_id, voters, current_rounds)
+		},
+		None => {
+			let voters = set.authorities().iter().map(|(id, _)| id.clone()).collect();
+			(set.set_id(), voters, None)
+		},
+	};
+
+	let latest = rounds.first().ok_or_else(|| corrupt("no readable completed rounds"))?;
+	let current_rounds = current_rounds.unwrap_or_else(|| {
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(latest.number.saturating_add(1), HasVoted::No);
+		current_rounds
+	});
+
+	let completed_rounds = (rounds, set_id, voters);
+	Ok(match live {
+		true => V3VoterSetState::Live { completed_rounds, current_rounds },
+		false => V3VoterSetState::Paused { completed_rounds },
+	})
+}
+
+fn migrate_from_version3<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	backup_before_migration(backend, 3)?;
+
+	// completed rounds didn't record the time they completed at.
+	migrate_voter_set_state::<Block, V3VoterSetState<Block>, _, _>(backend, 3, genesis_round, cipher)
+}
+
+fn migrate_from_version4<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	backup_before_migration(backend, 4)?;
+
+	// paused voter set states didn't record why they were paused.
+	migrate_voter_set_state::<Block, V4VoterSetState<Block>, _, _>(backend, 4, genesis_round, cipher)
+}
+
+fn migrate_from_version5<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	backup_before_migration(backend, 5)?;
+
+	// completed rounds didn't record the set id they belonged to.
+	migrate_voter_set_state::<Block, V5VoterSetState<Block>, _, _>(backend, 5, genesis_round, cipher)
+}
+
+fn migrate_from_version6<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	backup_before_migration(backend, 6)?;
+
+	// paused voter set states didn't record the block they were paused since.
+	migrate_voter_set_state::<Block, V6VoterSetState<Block>, _, _>(backend, 6, genesis_round, cipher)
+}
+
+fn migrate_from_version7<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	backup_before_migration(backend, 7)?;
+
+	// the authority set and voter set state were stored both plain and framed,
+	// they are always framed now.
+	migrate_voter_set_state::<Block, VoterSetState<Block>, _, _>(backend, 7, genesis_round, cipher)
+}
+
+/// Rewrite a voter set state stored in the legacy format `S` at the given
+/// schema version in the current format, for migrations that leave the
+/// authority set unchanged.
+fn migrate_voter_set_state<Block: BlockT, S, B, G>(
+	backend: &B,
+	version: u32,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<(
+	AuthoritySet<Block::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>> where B: AuxStore,
+		  G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+		  S: Decode + Into<VoterSetState<Block>>,
+{
+	if let Some(set) = load_decode_at::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(
+		backend,
+		version,
+		AUTHORITY_SET_KEY,
+	)? {
+		let set_state = match load_set_state_at::<S, _>(backend, version, cipher)? {
+			Some(state) => {
+				let state: VoterSetState<Block> = state.into();
+				if state.iter_completed_rounds().next().is_none() {
+					return Err(GrandpaAuxError::CorruptEntry {
+						key: String::from_utf8_lossy(SET_STATE_KEY).into_owned(),
+						reason: "voter set state has no completed rounds",
+					}.into());
+				}
+
+				state
+			},
+			None => {
+				let state = genesis_round();
+				let base = state.prevote_ghost
+					.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+
+				VoterSetState::genesis_for(&set, base)
+			}
+		};
+
+		complete_migration(backend, Some(&set), Some(&set_state), cipher)?;
+
+		return Ok(Some((set, set_state)));
+	}
+
+	complete_migration::<Block, _>(backend, None, None, cipher)?;
+	Ok(None)
+}
+
+/// Write the migrated authority set and voter set state, framed, together
+/// with the current version in a single write, the version last. The voter
+/// set state is encrypted with the cipher it was decrypted with, if any. A
+/// migration interrupted before this write leaves the stored version and
+/// entries as they were, so it is re-run cleanly on the next startup.
+fn complete_migration<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	set: Option<&AuthoritySet<Block::Hash, NumberFor<Block>>>,
+	set_state: Option<&VoterSetState<Block>>,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<()> {
+	let encoded_set = set.map(|set| framed(&set.encode()));
+	let encoded_state = set_state.map(|state| encoded_set_state(state, cipher));
+	let encoded_finalized = set_state.and_then(best_finalized).map(|finalized| finalized.encode());
+	let encoded_version = CURRENT_VERSION.encode();
+
+	let mut insert = Vec::new();
+	if let Some(encoded_set) = &encoded_set {
+		insert.push((AUTHORITY_SET_KEY, &encoded_set[..]));
+	}
+	if let Some(encoded_state) = &encoded_state {
+		insert.push((SET_STATE_KEY, &encoded_state[..]));
+	}
+	if let Some(encoded_finalized) = &encoded_finalized {
+		insert.push((BEST_FINALIZED_KEY, &encoded_finalized[..]));
+	}
+	insert.push((VERSION_KEY, &encoded_version[..]));
+
+	backend.insert_aux(&insert, &[])
+}
+
+/// Assign the completed rounds of the given voter set state to the given
+/// authority set. The current rounds of a live state were voted on under
+/// another set id, so they restart after the last completed round.
+fn reassigned_set_state<Block: BlockT>(
+	state: VoterSetState<Block>,
+	set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+) -> VoterSetState<Block> {
+	match state {
+		VoterSetState::Live { mut completed_rounds, .. } => {
+			completed_rounds.reassign(set.set_id(), set);
+
+			let mut current_rounds = CurrentRounds::new();
+			current_rounds.insert(completed_rounds.last_number().saturating_add(1), HasVoted::No);
+
+			VoterSetState::Live { completed_rounds, current_rounds }
+		},
+		VoterSetState::Paused { mut completed_rounds, reason, since } => {
+			completed_rounds.reassign(set.set_id(), set);
+
+			VoterSetState::Paused { completed_rounds, reason, since }
+		},
+	}
+}
+
+/// A migration of the aux-db from some schema version to the current one,
+/// decrypting and re-encrypting the voter set state with the given cipher.
+type Migration<Block, B, G> = fn(&B, &G, Option<&dyn StateCipher>) -> ClientResult<Option<(
+	AuthoritySet<<Block as BlockT>::Hash, NumberFor<Block>>,
+	VoterSetState<Block>,
+)>>;
+
+/// Migrations to the current schema version, by the version they migrate
+/// from. Each migration targets the current version directly, a missing
+/// version is treated as version 0.
+fn migrations<Block: BlockT, B, G>() -> Vec<(u32, Migration<Block, B, G>)> where
+	B: AuxStore,
+	G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	vec![
+		(0, migrate_from_version0::<Block, B, G>),
+		(1, migrate_from_version1::<Block, B, G>),
+		(2, migrate_from_version2::<Block, B, G>),
+		(3, migrate_from_version3::<Block, B, G>),
+		(4, migrate_from_version4::<Block, B, G>),
+		(5, migrate_from_version5::<Block, B, G>),
+		(6, migrate_from_version6::<Block, B, G>),
+		(7, migrate_from_version7::<Block, B, G>),
+	]
+}
+
+/// Migrate the aux-db to the current schema version without loading the
+/// persistent data, returning the `(from, to)` version transitions performed.
+/// Each migration targets the current version directly, so at most one
+/// transition is performed. Nothing is migrated on an uninitialized backend.
+/// A missing version is detected from the stored data, like on load. An
+/// encrypted voter set state is migrated with the given cipher.
+pub fn run_migrations_stepwise<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_round: &G,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Vec<(u32, u32)>> where
+	B: AuxStore,
+	G: Fn() -> RoundState<Block::Hash, NumberFor<Block>>,
+{
+	let version = match load_decode::<_, u32>(backend, VERSION_KEY)? {
+		Some(version) => version,
+		None if backend.get_aux(AUTHORITY_SET_KEY)?.is_none() => return Ok(Vec::new()),
+		None => detect_version_from_data::<Block, _>(backend, cipher)?.unwrap_or(0),
+	};
+
+	if version == CURRENT_VERSION {
+		return Ok(Vec::new());
+	}
+	if version > CURRENT_VERSION {
+		return Err(GrandpaAuxError::UnsupportedVersion(version).into());
+	}
+
+	let migrate = migrations::<Block, B, G>().into_iter()
+		.find(|(from, _)| *from == version)
+		.map(|(_, migrate)| migrate)
+		.ok_or(GrandpaAuxError::UnsupportedVersion(version))?;
+
+	run_migration(backend, version, migrate, genesis_round, cipher)?;
+	info!(target: "afg", "Migrated GRANDPA DB from version {} to {}.", version, CURRENT_VERSION);
+
+	Ok(vec![(version, CURRENT_VERSION)])
+}
+
+/// Prometheus metrics for aux-db schema migrations.
+#[derive(Clone)]
+pub(crate) struct MigrationMetrics {
+	migration_duration: HistogramVec,
+}
+
+impl MigrationMetrics {
+	pub(crate) fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			migration_duration: register(
+				HistogramVec::new(
+					HistogramOpts {
+						common_opts: Opts::new(
+							"grandpa_migration_duration_seconds",
+							"Time taken to migrate the GRANDPA DB to the current schema version.",
+						),
+						buckets: vec![0.001, 0.01, 0.1, 1.0, 10.0, 60.0],
+					},
+					&["from_version"],
+				)?,
+				registry,
+			)?,
+		})
+	}
+
+	/// Note the time taken by a migration from the given schema version.
+	fn note_migration_duration(&self, version: u32, duration: std::time::Duration) {
+		self.migration_duration
+			.with_label_values(&[&version.to_string()])
+			.observe(duration.as_secs_f64());
+	}
+}
+
+impl Debug for MigrationMetrics {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("MigrationMetrics").finish()
+	}
+}
+
+/// Prometheus metrics counting the entries decoded from the aux-db, by key.
+#[derive(Clone)]
+pub(crate) struct DecodeMetrics {
+	decode_total: CounterVec<U64>,
+	decode_failures_total: CounterVec<U64>,
+}
+
+impl DecodeMetrics {
+	pub(crate) fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			decode_total: register(
+				CounterVec::new(
+					Opts::new(
+						"grandpa_aux_decode_total",
+						"Number of GRANDPA aux-db entries decoded.",
+					),
+					&["key"],
+				)?,
+				registry,
+			)?,
+			decode_failures_total: register(
+				CounterVec::new(
+					Opts::new(
+						"grandpa_aux_decode_failures_total",
+						"Number of GRANDPA aux-db entries that failed to decode.",
+					),
+					&["key"],
+				)?,
+				registry,
+			)?,
+		})
+	}
+
+	/// Note an attempt to decode the entry stored under the given key.
+	fn note_decode(&self, key: &[u8], success: bool) {
+		let key = String::from_utf8_lossy(key);
+		self.decode_total.with_label_values(&[&*key]).inc();
+		if !success {
+			self.decode_failures_total.with_label_values(&[&*key]).inc();
+		}
+	}
+}
+
+impl Debug for DecodeMetrics {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("DecodeMetrics").finish()
+	}
+}
+
+/// Options controlling how `load_persistent` initializes and handles
+/// unexpected data.
+#[derive(Debug, Clone)]
+pub(crate) struct LoadOptions {
+	/// Reset a corrupted voter set state instead of failing, and reassign a
+	/// voter set state of another set id to the stored authority set.
+	pub(crate) recover_set_state: bool,
+	/// Maximum number of completed rounds kept by a freshly created voter set
+	/// state, see `CompletedRounds::with_retention`. The default retention
+	/// applies if not given.
+	pub(crate) completed_rounds_retention: Option<usize>,
+	/// Fail instead of initializing from genesis if the schema version is
+	/// stored but the authority set is missing, and fail if the voter set
+	/// state has another set id than the authority set.
+	pub(crate) strict: bool,
+	/// Metrics recording the duration of schema migrations.
+	pub(crate) migration_metrics: Option<MigrationMetrics>,
+	/// Skip completed rounds that fail to decode when migrating from version 2
+	/// instead of failing. Dangerous, since the skipped rounds are lost.
+	pub(crate) skip_unreadable_rounds: bool,
+	/// Cipher decrypting the voter set state if it was stored encrypted, and
+	/// encrypting it when written on load.
+	pub(crate) state_cipher: Option<Arc<dyn StateCipher>>,
+	/// Metrics counting the decoding of the entries read on load, before any
+	/// migration.
+	pub(crate) decode_metrics: Option<DecodeMetrics>,
+}
+
+impl Default for LoadOptions {
+	fn default() -> Self {
+		LoadOptions {
+			recover_set_state: false,
+			completed_rounds_retention: None,
+			strict: false,
+			migration_metrics: None,
+			skip_unreadable_rounds: false,
+			state_cipher: None,
+			decode_metrics: None,
+		}
+	}
+}
+
+/// Load or initialize persistent data from backend.
+pub(crate) fn load_persistent<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_hash: Block::Hash,
+	genesis_number: NumberFor<Block>,
+	genesis_authorities: G,
+)
+	-> ClientResult<PersistentData<Block>>
+	where
+		B: AuxStore,
+		G: FnOnce() -> ClientResult<AuthorityList>,
+{
+	load_persistent_with_options(
+		backend,
+		genesis_hash,
+		genesis_number,
+		genesis_authorities,
+		LoadOptions::default(),
+	)
+}
+
+/// Load or initialize persistent data from backend, recovering from a
+/// corrupted voter set state. If the stored voter set state fails to decode
+/// but the authority set is intact, the voter set state is reset to a live
+/// state at round 0 of the current authority set. The authority set itself is
+/// never reset, and like `load_persistent_no_genesis` nothing is initialized
+/// from genesis, so this fails if no authority set is stored. The other
+/// options are applied as given.
+pub(crate) fn load_persistent_with_recovery<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	genesis_hash: Block::Hash,
+	genesis_number: NumberFor<Block>,
+	options: LoadOptions,
+) -> ClientResult<PersistentData<Block>> {
+	load_persistent_no_genesis(
+		backend,
+		genesis_hash,
+		genesis_number,
+		LoadOptions { recover_set_state: true, ..options },
+	)?.ok_or_else(|| GrandpaAuxError::Uninitialized.into())
+}
+
+/// Load or initialize persistent data from backend with the given options.
+pub(crate) fn load_persistent_with_options<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_hash: Block::Hash,
+	genesis_number: NumberFor<Block>,
+	genesis_authorities: G,
+	options: LoadOptions,
+)
+	-> ClientResult<PersistentData<Block>>
+	where
+		B: AuxStore,
+		G: FnOnce() -> ClientResult<AuthorityList>,
+{
+	// migrations read the same entries repeatedly.
+	load_persistent_cached(
+		&CachingAuxStore::new(backend),
+		genesis_hash,
+		genesis_number,
+		genesis_authorities,
+		options,
+		CURRENT_VERSION,
+	)
+}
+
+/// Load or initialize persistent data from backend as if the given version
+/// was the current schema version, to simulate other schema versions.
+#[cfg(test)]
+fn load_persistent_with_version<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_hash: Block::Hash,
+	genesis_number: NumberFor<Block>,
+	genesis_authorities: G,
+	current_version: u32,
+)
+	-> ClientResult<PersistentData<Block>>
+	where
+		B: AuxStore,
+		G: FnOnce() -> ClientResult<AuthorityList>,
+{
+	load_persistent_cached(
+		&CachingAuxStore::new(backend),
+		genesis_hash,
+		genesis_number,
+		genesis_authorities,
+		LoadOptions::default(),
+		current_version,
+	)
+}
+
+fn load_persistent_cached<Block: BlockT, B, G>(
+	backend: &B,
+	genesis_hash: Block::Hash,
+	genesis_number: NumberFor<Block>,
+	genesis_authorities: G,
+	options: LoadOptions,
+	current_version: u32,
+)
+	-> ClientResult<PersistentData<Block>>
+	where
+		B: AuxStore,
+		G: FnOnce() -> ClientResult<AuthorityList>,
+{
+	let decode_metrics = options.decode_metrics.as_ref();
+	let genesis = (genesis_hash, genesis_number);
+	let stored_genesis = load_decode_with_metrics::<_, (Block::Hash, NumberFor<Block>)>(
+		backend,
+		GENESIS_KEY,
+		decode_metrics,
+	)?;
+	if let Some(stored_genesis) = stored_genesis {
+		if stored_genesis != genesis {
+			return Err(GrandpaAuxError::GenesisMismatch {
+				stored: format!("{:?}", stored_genesis),
+				given: format!("{:?}", genesis),
+			}.into());
+		}
+	}
+
+	// DBs initialized before the genesis was recorded are assumed to belong to
+	// the given chain.
+	let record_genesis = || match stored_genesis {
+		Some(_) => Ok(()),
+		None => backend.insert_aux(&[(GENESIS_KEY, genesis.encode().as_slice())], &[]),
+	};
+
+	let stored_version: Option<u32> = load_decode_with_metrics(backend, VERSION_KEY, decode_metrics)?;
+	// the version key may have been lost rather than never written, so don't
+	// take data of a newer version for version 0 data and mangle it.
+	let version = match stored_version {
+		Some(version) => Some(version),
+		None => {
+			let detected = detect_version_from_data::<Block, _>(backend, options.state_cipher.as_deref())?;
+			if let Some(detected) = detected.filter(|detected| *detected != 0) {
+				warn!(target: "afg", "GRANDPA DB version is missing, the stored data is of version {}.", detected);
+			}
+			detected
+		},
+	};
+	let consensus_changes = load_consensus_changes::<Block, _>(backend, decode_metrics)?;
+
+	let make_genesis_round = move || RoundState::genesis((genesis_hash, genesis_number));
+
+	match version {
+		Some(version) if version == current_version => {
+			if let Some(set) = load_decode_checked::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(
+				backend,
+				AUTHORITY_SET_KEY,
+			)? {
+				validate_authority_weights(&set)?;
+				validate_forced_changes::<Block>(&set, options.strict)?;
+
+				let genesis_set_state = || {
+					let state = make_genesis_round();
+					let base = state.prevote_ghost
+						.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+
+					VoterSetState::genesis_for_with_retention(
+						&set,
+						base,
+						options.completed_rounds_retention,
+					)
+				};
+
+				let cipher = options.state_cipher.as_deref();
+				let set_state = match load_set_state::<Block, _>(backend, cipher) {
+					Ok(Some(state)) => state,
+					Ok(None) => genesis_set_state(),
+					Err(GrandpaAuxError::CorruptEntry { reason, .. }) if options.recover_set_state => {
+						warn!(target: "afg", "GRANDPA voter set state is corrupted: {}.", reason);
+						warn!(target: "afg", "Resetting voter set state to round 0 of authority set {}.", set.set_id());
+
+						let state = genesis_set_state();
+						write_voter_set_state_encrypted(backend, &state, cipher)?;
+						state
+					},
+					Err(err) => return Err(err.into()),
+				};
+
+				let state_set_id = set_state.completed_rounds().set_info().0;
+				let set_state = if state_set_id != set.set_id() {
+					let mismatch = GrandpaAuxError::SetIdMismatch {
+						set_state: state_set_id,
+						authority_set: set.set_id(),
+					};
+
+					if options.strict {
+						return Err(mismatch.into());
+					}
+
+					warn!(target: "afg", "{}.", mismatch);
+					if options.recover_set_state {
+						warn!(target: "afg", "Reassigning voter set state to authority set {}.", set.set_id());
+
+						let state = reassigned_set_state(set_state, &set);
+						write_voter_set_state_encrypted(backend, &state, cipher)?;
+						state
+					} else {
+						set_state
+					}
+				} else {
+					set_state
+				};
+
+				record_genesis()?;
+				if stored_version.is_none() {
+					backend.insert_aux(&[(VERSION_KEY, current_version.encode().as_slice())], &[])?;
+				}
+
+				return Ok(PersistentData {
+					authority_set: set.into(),
+					consensus_changes: Arc::new(consensus_changes.into()),
+					set_state: set_state.into(),
+					state_cipher: options.state_cipher.clone(),
+				});
+			}
+		},
+		// neither a version nor any data, a fresh DB is initialized from genesis
+		// below rather than migrated.
+		None => {},
+		Some(version) => {
+			if version > current_version {
+				return Err(GrandpaAuxError::UnsupportedVersion(version).into());
+			}
+
+			let migrate: Migration<Block, B, _> = match version {
+				2 if options.skip_unreadable_rounds =>
+					migrate_from_version2_skipping_unreadable_rounds::<Block, B, _>,
+				_ => migrations::<Block, B, _>().into_iter()
+					.find(|(from, _)| *from == version)
+					.map(|(_, migrate)| migrate)
+					.ok_or(GrandpaAuxError::UnsupportedVersion(version))?,
+			};
+
+			let started = std::time::Instant::now();
+			let migrated = run_migration(
+				backend,
+				version,
+				migrate,
+				&make_genesis_round,
+				options.state_cipher.as_deref(),
+			)?;
+			let duration = started.elapsed();
+
+			info!(target: "afg", "Migrated GRANDPA DB from version {} in {:?}.", version, duration);
+			if let Some(metrics) = &options.migration_metrics {
+				metrics.note_migration_duration(version, duration);
+			}
+
+			if let Some((set, set_state)) = migrated {
+				validate_authority_weights(&set)?;
+				validate_forced_changes::<Block>(&set, options.strict)?;
+				record_genesis()?;
+
+				return Ok(PersistentData {
+					authority_set: set.into(),
+					consensus_changes: Arc::new(consensus_changes.into()),
+					set_state: set_state.into(),
+					state_cipher: options.state_cipher.clone(),
+				});
+			}
+		},
+	}
+
+	// the authority set of an initialized DB is never deleted by us.
+	if backend.get_aux(INIT_KEY)?.is_some() {
+		return Err(GrandpaAuxError::AuthoritySetDeleted.into());
+	}
+
+	// a stored version without an authority set points to a partially wiped DB
+	// rather than a first startup.
+	if let Some(version) = version {
+		if options.strict {
+			return Err(GrandpaAuxError::MissingAuthoritySet(version).into());
+		}
+
+		warn!(target: "afg", "GRANDPA DB version {} is stored but the authority set is missing.", version);
+		warn!(target: "afg", "The DB may have been partially wiped, re-initializing GRANDPA state from genesis.");
+	}
+
+	// genesis.
+	info!(target: "afg", "👴 Loading GRANDPA authority set \
+		from genesis on what appears to be first startup.");
+
+	let genesis_authorities = genesis_authorities().map_err(GrandpaAuxError::GenesisAuthorities)?;
+	let genesis_set = AuthoritySet::genesis(genesis_authorities)
+		.ok_or(GrandpaAuxError::InvalidGenesisAuthorities)?;
+	let state = make_genesis_round();
+	let base = state.prevote_ghost
+		.expect("state is for completed round; completed rounds must have a prevote ghost; qed.");
+
+	let genesis_state = VoterSetState::<Block>::genesis_for_with_retention(
+		&genesis_set,
+		base,
+		options.completed_rounds_retention,
+	);
+
+	let genesis_finalized = best_finalized(&genesis_state)
+		.expect("genesis round state is finalized at the genesis block; qed.");
+	let encoded_state = encoded_set_state(&genesis_state, options.state_cipher.as_deref());
+
+	// a crash can't leave the version stored without the authority set.
+	backend.insert_aux(
+		&[
+			(AUTHORITY_SET_KEY, framed(&genesis_set.encode()).as_slice()),
+			(SET_STATE_KEY, encoded_state.as_slice()),
+			(BEST_FINALIZED_KEY, genesis_finalized.encode().as_slice()),
+			(GENESIS_KEY, genesis.encode().as_slice()),
+			(INIT_KEY, CURRENT_VERSION.encode().as_slice()),
+			(VERSION_KEY, current_version.encode().as_slice()),
+		],
+		&[],
+	)?;
+
+	Ok(PersistentData {
+		authority_set: genesis_set.into(),
+		set_state: genesis_state.into(),
+		consensus_changes: Arc::new(consensus_changes.into()),
+		state_cipher: options.state_cipher.clone(),
+	})
+}
+
+/// Load persistent data from backend without ever initializing it from
+/// genesis. Returns `None` without writing anything if no authority set is
+/// stored, so that an absent DB can be told apart from a fresh one. The
+/// genesis is still needed to check the stored one and to migrate old
+/// schema versions. Loads with the given options.
+pub(crate) fn load_persistent_no_genesis<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	genesis_hash: Block::Hash,
+	genesis_number: NumberFor<Block>,
+	options: LoadOptions,
+) -> ClientResult<Option<PersistentData<Block>>> {
+	if backend.get_aux(AUTHORITY_SET_KEY)?.is_none() {
+		if backend.get_aux(INIT_KEY)?.is_some() {
+			return Err(GrandpaAuxError::AuthoritySetDeleted.into());
+		}
+
+		return Ok(None);
+	}
+
+	load_persistent_with_options(
+		backend,
+		genesis_hash,
+		genesis_number,
+		|| Err(ClientError::Backend("Initializing GRANDPA state from genesis is disabled".into())),
+		options,
+	).map(Some)
+}
+
+/// Load only the voter set state, without the authority set. Returns `None`
+/// if the DB hasn't been initialized yet. Unlike `load_persistent` this never
+/// migrates, so it fails if the DB is stored with an older schema version.
+/// An encrypted voter set state is decrypted with the given cipher.
+pub(crate) fn load_voter_set_state_only<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<VoterSetState<Block>>> {
+	match load_decode::<_, u32>(backend, VERSION_KEY)? {
+		Some(CURRENT_VERSION) => {},
+		Some(version) if version > CURRENT_VERSION =>
+			return Err(GrandpaAuxError::UnsupportedVersion(version).into()),
+		Some(version) => return Err(GrandpaAuxError::OutdatedVersion(version).into()),
+		// version 0 didn't store a version.
+		None => match backend.get_aux(SET_STATE_KEY)? {
+			Some(_) => return Err(GrandpaAuxError::OutdatedVersion(0).into()),
+			None => return Ok(None),
+		},
+	}
+
+	load_set_state(backend, cipher).map_err(Into::into)
+}
+
+/// Load only the authority set, tolerating a DB stored with a future schema
+/// version, e.g. when an older binary is started against a newer DB. The
+/// authority set rarely changes layout, so it is decoded on a best-effort
+/// basis to let operators inspect it. Never writes, since the entries written
+/// by this node couldn't be read by the newer one. Returns `None` if the DB
+/// hasn't been initialized yet.
+pub(crate) fn load_authority_set_forward_compat<Block: BlockT, B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Option<AuthoritySet<Block::Hash, NumberFor<Block>>>> {
+	match load_decode::<_, u32>(backend, VERSION_KEY)? {
+		Some(CURRENT_VERSION) => {},
+		Some(version) if version > CURRENT_VERSION => {
+			warn!(target: "afg", "GRANDPA DB version {} is newer than the supported version {}.", version, CURRENT_VERSION);
+			warn!(target: "afg", "Reading the authority set in degraded forward-compatible mode, no writes will be made.");
+		},
+		Some(version) => return Err(GrandpaAuxError::OutdatedVersion(version).into()),
+		// version 0 didn't store a version.
+		None => match backend.get_aux(AUTHORITY_SET_KEY)? {
+			Some(_) => return Err(GrandpaAuxError::OutdatedVersion(0).into()),
+			None => return Ok(None),
+		},
+	}
+
+	Ok(load_decode_checked(backend, AUTHORITY_SET_KEY)?)
+}
+
+/// Check the internal consistency of loaded persistent data: the voter set
+/// state must be for the current authority set, the base of each completed
+/// round must be its prevote ghost and completed rounds must be ordered by
+/// round number. Returns all violations found.
+pub(crate) fn validate_persistent<Block: BlockT>(
+	data: &PersistentData<Block>,
+) -> Result<(), Vec<String>> {
+	let mut violations = Vec::new();
+
+	let authority_set_id = data.authority_set.inner().read().set_id();
+	let set_state = data.set_state.read();
+	let completed_rounds = set_state.completed_rounds();
+
+	let set_id = completed_rounds.set_info().0;
+	if set_id != authority_set_id {
+		violations.push(format!(
+			"voter set state is for set id {} but the authority set id is {}",
+			set_id,
+			authority_set_id,
+		));
+	}
+
+	let mut previous: Option<RoundNumber> = None;
+	for round in completed_rounds.iter() {
+		if let Some(ghost) = round.state.prevote_ghost {
+			if ghost != round.base {
+				violations.push(format!(
+					"completed round {} has base {:?} but prevote ghost {:?}",
+					round.number,
+					round.base,
+					ghost,
+				));
+			}
+		}
+
+		if let Some(previous) = previous {
+			if round.number <= previous {
+				violations.push(format!(
+					"completed round {} follows completed round {}",
+					round.number,
+					previous,
+				));
+			}
+		}
+		previous = Some(round.number);
+	}
+
+	if violations.is_empty() {
+		Ok(())
+	} else {
+		Err(violations)
+	}
+}
+
+/// A scheduled change tracked by only one of the authority set and the
+/// consensus changes, see `cross_check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Inconsistency<H, N> {
+	/// A pending standard change of the authority set has no matching
+	/// consensus change.
+	MissingConsensusChange { canon_height: N, canon_hash: H },
+	/// A consensus change has no matching pending standard change in the
+	/// authority set.
+	MissingPendingChange { canon_height: N, canon_hash: H },
+}
+
+/// Check that the pending standard changes of the authority set and the
+/// consensus changes reference the same scheduled changes, by canon height
+/// and hash. Returns the changes found in only one of them, those of the
+/// authority set first.
+pub(crate) fn cross_check<Block: BlockT>(
+	set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+	changes: &ConsensusChanges<Block::Hash, NumberFor<Block>>,
+) -> Vec<Inconsistency<Block::Hash, NumberFor<Block>>> {
+	let scheduled = set.pending_standard_changes_iter()
+		.map(|change| (change.canon_height, change.canon_hash))
+		.collect::<Vec<_>>();
+
+	let missing_consensus_changes = scheduled.iter()
+		.filter(|change| !changes.pending_changes().contains(change))
+		.map(|&(canon_height, canon_hash)| Inconsistency::MissingConsensusChange { canon_height, canon_hash });
+
+	let missing_pending_changes = changes.pending_changes().iter()
+		.filter(|change| !scheduled.contains(change))
+		.map(|&(canon_height, canon_hash)| Inconsistency::MissingPendingChange { canon_height, canon_hash });
+
+	missing_consensus_changes.chain(missing_pending_changes).collect()
+}
+
+/// Status of a single aux-db entry as seen by `inspect_persistent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStatus {
+	/// The entry is not stored.
+	Missing,
+	/// The entry is stored and decodes in the detected schema version.
+	Valid,
+	/// The entry is stored but fails to decode in the detected schema version.
+	Corrupted,
+}
+
+/// Report on the stored GRANDPA schema, describing the migration
+/// `load_persistent` would perform without performing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaReport {
+	/// The detected schema version, `0` if no version is stored.
+	pub detected: u32,
+	/// The schema version `load_persistent` would migrate to.
+	pub target: u32,
+	/// Status of the authority set entry.
+	pub authority_set: EntryStatus,
+	/// Status of the voter set state entry.
+	pub set_state: EntryStatus,
+	/// Status of the consensus changes entry.
+	pub consensus_changes: EntryStatus,
+}
+
+fn entry_status<B: AuxStore, T: Decode>(
+	backend: &B,
+	version: u32,
+	key: &[u8],
+) -> ClientResult<EntryStatus> {
+	Ok(match backend.get_aux(key)? {
+		None => EntryStatus::Missing,
+		Some(t) => match unframe_at(version, key, &t).map(|mut value| T::decode(&mut value)) {
+			Ok(Ok(_)) => EntryStatus::Valid,
+			_ => EntryStatus::Corrupted,
+		},
+	})
+}
+
+/// Like `entry_status` for the voter set state, decrypting it with the cipher
+/// first. A voter set state that can't be decrypted is reported as corrupted.
+fn set_state_status<B: AuxStore, S: Decode>(
+	backend: &B,
+	version: u32,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<EntryStatus> {
+	Ok(match load_set_state_at::<S, _>(backend, version, cipher) {
+		Ok(Some(_)) => EntryStatus::Valid,
+		Ok(None) => EntryStatus::Missing,
+		Err(GrandpaAuxError::Backend(err)) => return Err(err),
+		Err(_) => EntryStatus::Corrupted,
+	})
+}
+
+/// Inspect the persistent data in the backend and report which migration
+/// `load_persistent` would take. An encrypted voter set state is decrypted
+/// with the given cipher. Nothing is written to the backend.
+pub fn inspect_persistent<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<SchemaReport> {
+	let version: Option<u32> = load_decode(backend, VERSION_KEY)?;
+	let stored_version = version.unwrap_or(0);
+
+	let (authority_set, set_state) = match version {
+		None => (
+			entry_status::<_, V0AuthoritySet<Block::Hash, NumberFor<Block>>>(backend, stored_version, AUTHORITY_SET_KEY)?,
+			set_state_status::<_, V0VoterSetState<Block::Hash, NumberFor<Block>>>(backend, stored_version, cipher)?,
+		),
+		Some(1) => (
+			entry_status::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(backend, stored_version, AUTHORITY_SET_KEY)?,
+			set_state_status::<_, V1VoterSetState<Block::Hash, NumberFor<Block>>>(backend, stored_version, cipher)?,
+		),
+		Some(2) | Some(3) => (
+			entry_status::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(backend, stored_version, AUTHORITY_SET_KEY)?,
+			set_state_status::<_, V3VoterSetState<Block>>(backend, stored_version, cipher)?,
+		),
+		Some(4) => (
+			entry_status::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(backend, stored_version, AUTHORITY_SET_KEY)?,
+			set_state_status::<_, V4VoterSetState<Block>>(backend, stored_version, cipher)?,
+		),
+		Some(5) => (
+			entry_status::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(backend, stored_version, AUTHORITY_SET_KEY)?,
+			set_state_status::<_, V5VoterSetState<Block>>(backend, stored_version, cipher)?,
+		),
+		Some(6) => (
+			entry_status::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(backend, stored_version, AUTHORITY_SET_KEY)?,
+			set_state_status::<_, V6VoterSetState<Block>>(backend, stored_version, cipher)?,
+		),
+		Some(_) => (
+			entry_status::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(backend, stored_version, AUTHORITY_SET_KEY)?,
+			set_state_status::<_, VoterSetState<Block>>(backend, stored_version, cipher)?,
+		),
+	};
+
+	// the consensus changes are never framed.
+	let consensus_changes = match backend.get_aux(CONSENSUS_CHANGES_KEY)? {
+		None => EntryStatus::Missing,
+		Some(stored) => match ConsensusChanges::<Block::Hash, NumberFor<Block>>::decode(&mut &stored[..]) {
+			Ok(_) => EntryStatus::Valid,
+			Err(_) => EntryStatus::Corrupted,
+		},
+	};
+
+	Ok(SchemaReport {
+		detected: version.unwrap_or(0),
+		target: CURRENT_VERSION,
+		authority_set,
+		set_state,
+		consensus_changes,
+	})
+}
+
+/// Whether the stored value of the given schema version decodes as `T`
+/// without leaving any input.
+fn decodes_exactly<T: Decode>(version: u32, key: &[u8], stored: &[u8]) -> bool {
+	match unframe_at(version, key, stored) {
+		Ok(mut value) => T::decode(&mut value).is_ok() && value.is_empty(),
+		Err(_) => false,
+	}
+}
+
+/// Detect the schema version of the stored authority set and voter set state
+/// from their shape, ignoring the stored version, e.g. to restamp a missing or
+/// wrong version before loading. The entries are decoded with the types of
+/// each version, newest first, and the first version both decode with exactly
+/// is returned. Versions 2 and 3 share a layout and are told apart by the best
+/// finalized block, which is stored since version 3. An encrypted voter set
+/// state is decrypted with the given cipher first. Returns `None` if no
+/// authority set is stored or no version matches.
+pub(crate) fn detect_version_from_data<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<u32>> {
+	let authority_set = match backend.get_aux(AUTHORITY_SET_KEY)? {
+		Some(authority_set) => authority_set,
+		None => return Ok(None),
+	};
+	let set_state = match backend.get_aux(SET_STATE_KEY)? {
+		Some(set_state) => Some(decrypted(set_state, cipher)?),
+		None => None,
+	};
+
+	let authority_set_fits = |version: u32| match version {
+		0 => decodes_exactly::<V0AuthoritySet<Block::Hash, NumberFor<Block>>>(version, AUTHORITY_SET_KEY, &authority_set),
+		_ => decodes_exactly::<AuthoritySet<Block::Hash, NumberFor<Block>>>(version, AUTHORITY_SET_KEY, &authority_set),
+	};
+	// a missing voter set state is initialized on load in every version.
+	let set_state_fits = |version: u32| set_state.as_ref().map_or(true, |set_state| match version {
+		0 => decodes_exactly::<V0VoterSetState<Block::Hash, NumberFor<Block>>>(version, SET_STATE_KEY, set_state),
+		1 => decodes_exactly::<V1VoterSetState<Block::Hash, NumberFor<Block>>>(version, SET_STATE_KEY, set_state),
+		2 | 3 => decodes_exactly::<V3VoterSetState<Block>>(version, SET_STATE_KEY, set_state),
+		4 => decodes_exactly::<V4VoterSetState<Block>>(version, SET_STATE_KEY, set_state),
+		5 => decodes_exactly::<V5VoterSetState<Block>>(version, SET_STATE_KEY, set_state),
+		6 => decodes_exactly::<V6VoterSetState<Block>>(version, SET_STATE_KEY, set_state),
+		_ => decodes_exactly::<VoterSetState<Block>>(version, SET_STATE_KEY, set_state),
+	});
+
+	let detected = (0..=CURRENT_VERSION).rev()
+		.find(|version| authority_set_fits(*version) && set_state_fits(*version));
+
+	Ok(match detected {
+		Some(3) if backend.get_aux(BEST_FINALIZED_KEY)?.is_none() => Some(2),
+		detected => detected,
+	})
+}
+
+/// Downgrade the aux-db schema back to version 2, so that the database can be
+/// read by an older node binary. The best finalized block entry is dropped,
+/// completed rounds lose their completion time and set id and paused voter
+/// set states lose their pause reason and the block they were paused since.
+/// An encrypted voter set state is decrypted with the given cipher and
+/// written in plain, since version 2 nodes can't decrypt it.
+pub fn downgrade_to_version2<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<()> {
+	// version 2 nodes don't understand frames.
+	let version = load_decode::<_, u32>(backend, VERSION_KEY)?;
+	let set_state = match version {
+		Some(stored @ 7..=8) => load_set_state_at::<VoterSetState<Block>, _>(backend, stored, cipher)?
+			.map(|state| V3VoterSetState::from(&state).encode()),
+		Some(6) => load_set_state_at::<V6VoterSetState<Block>, _>(backend, 6, cipher)?
+			.map(|state| {
+				let state: VoterSetState<Block> = state.into();
+				V3VoterSetState::from(&state).encode()
+			}),
+		Some(5) => load_set_state_at::<V5VoterSetState<Block>, _>(backend, 5, cipher)?
+			.map(|state| {
+				let state: VoterSetState<Block> = state.into();
+				V3VoterSetState::from(&state).encode()
+			}),
+		Some(4) => load_set_state_at::<V4VoterSetState<Block>, _>(backend, 4, cipher)?
+			.map(|state| {
+				let state: VoterSetState<Block> = state.into();
+				V3VoterSetState::from(&state).encode()
+			}),
+		Some(3) => load_set_state_unframed_at(backend, 3, cipher)?,
+		Some(2) => return Ok(()),
+		other => return Err(ClientError::Backend(
+			format!("Unsupported GRANDPA DB version for downgrade: {:?}", other)
+		).into()),
+	};
+
+	if backend.get_aux(BEST_FINALIZED_KEY)?.is_some() {
+		warn!(target: "afg", "Dropping GRANDPA best finalized block while downgrading DB to version 2.");
+	}
+
+	let authority_set = load_unframed_at(backend, version.unwrap_or(0), AUTHORITY_SET_KEY)?;
+	let encoded_version = 2u32.encode();
+
+	let mut insert = vec![(VERSION_KEY, &encoded_version[..])];
+	if let Some(ref authority_set) = authority_set {
+		insert.push((AUTHORITY_SET_KEY, &authority_set[..]));
+	}
+	if let Some(ref set_state) = set_state {
+		insert.push((SET_STATE_KEY, &set_state[..]));
+	}
+
+	backend.insert_aux(&insert, &[BEST_FINALIZED_KEY])
+}
+
+/// How `check_set_id_guard` treats an authority set with a lower set
+/// id than the stored one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SetIdGuard {
+	/// Fail without writing.
+	Strict,
+	/// Log a warning and write anyway.
+	Warn,
+	/// Write without checking, e.g. for an authority set imported on a fork
+	/// which legitimately lags behind the stored one.
+	Bypass,
+}
+
+/// Check that the set id of the given authority set doesn't go backwards
+/// compared to the stored authority set, before writing it with
+/// `commit_grandpa_state`.
+pub(crate) fn check_set_id_guard<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+	guard: SetIdGuard,
+) -> ClientResult<()> {
+	if guard == SetIdGuard::Bypass {
+		return Ok(());
+	}
+
+	match current_set_id::<Block, _>(backend)? {
+		Some(stored) if set.set_id() < stored => {
+			let error = GrandpaAuxError::SetIdRegression { stored, set_id: set.set_id() };
+			if guard == SetIdGuard::Strict {
+				return Err(error.into());
+			}
+
+			warn!(target: "afg", "Writing GRANDPA authority set anyway: {}.", error);
+			warn!(target: "afg", "Node is in a potentially inconsistent state.");
+		},
+		_ => {},
+	}
+
+	Ok(())
+}
+
+/// Load the handoff log and append the given handoff to it, dropping the
+/// oldest handoffs beyond `limit`. Returns the encoded log to be written.
+fn appended_handoff_log<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	handoff: (SetId, Block::Hash, NumberFor<Block>),
+	limit: usize,
+) -> ClientResult<Vec<u8>> {
+	let mut handoff_log = load_handoff_log::<Block, _>(backend)?;
+	handoff_log.push(handoff);
+	if handoff_log.len() > limit {
+		handoff_log.drain(..handoff_log.len() - limit);
+	}
+
+	Ok(handoff_log.encode())
+}
+
+/// The encoded highest set id ever observed, to be written along with an
+/// authority set of the given set id, if it changes. Only the highest set id
+/// ever observed is kept, a lower set id (e.g. after a rollback) is logged but
+/// otherwise ignored.
+fn updated_max_set_id<B: AuxStore>(backend: &B, set_id: SetId) -> ClientResult<Option<Vec<u8>>> {
+	Ok(match load_max_set_id(backend)? {
+		Some(max_set_id) if set_id < max_set_id => {
+			warn!(target: "afg", "GRANDPA set id regressed from {} to {}.", max_set_id, set_id);
+			None
+		},
+		Some(max_set_id) if set_id == max_set_id => None,
+		_ => Some(set_id.encode()),
+	})
+}
+
+/// Load the highest set id ever observed by `commit_grandpa_state`.
+pub(crate) fn load_max_set_id<B: AuxStore>(backend: &B) -> ClientResult<Option<SetId>> {
+	load_decode(backend, MAX_SET_ID_KEY).map_err(Into::into)
+}
+
+/// The best block finalized by the latest completed round of the given voter
+/// set state, if any.
+fn best_finalized<Block: BlockT>(
+	state: &VoterSetState<Block>,
+) -> Option<(Block::Hash, NumberFor<Block>)> {
+	state.last_round_state().finalized
+}
+
+/// Whether finality appears to progress, see `finality_health`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinalityHealth<N> {
+	/// The voter is live and finality is within the lag threshold.
+	Healthy,
+	/// The voter is live but finality lags behind by the given number of
+	/// blocks, more than the lag threshold.
+	Lagging(N),
+	/// The voter is paused.
+	Paused,
+}
+
+/// Decide whether finality appears stalled from the given voter set state,
+/// comparing the block finalized by its last completed round, or that
+/// round's base if it finalized none, with the given current block number.
+pub fn finality_health<Block: BlockT>(
+	state: &VoterSetState<Block>,
+	current: NumberFor<Block>,
+	lag_threshold: NumberFor<Block>,
+) -> FinalityHealth<NumberFor<Block>> {
+	if state.is_paused() {
+		return FinalityHealth::Paused;
+	}
+
+	let last_round = state.completed_rounds().last();
+	let finalized = last_round.state.finalized.as_ref().map_or(last_round.base.1, |(_, number)| *number);
+
+	let gap = current.saturating_sub(finalized);
+	if gap > lag_threshold {
+		FinalityHealth::Lagging(gap)
+	} else {
+		FinalityHealth::Healthy
+	}
+}
+
+/// Write voter set state, framed with a checksum. The best finalized block of
+/// the latest completed round is written alongside it. Like
+/// `write_voter_set_state_returning`, fails without writing if the stored
+/// state can't be decoded.
+pub(crate) fn write_voter_set_state<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	state: &VoterSetState<Block>,
+) -> ClientResult<()> {
+	write_voter_set_state_returning(backend, state, None).map(|_| ())
+}
+
+/// Write voter set state like `write_voter_set_state`, returning the voter set
+/// state it overwrote, if any. Both are decrypted and encrypted with the given
+/// cipher if any. Fails without writing if the stored state can't be decoded.
+pub(crate) fn write_voter_set_state_returning<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	state: &VoterSetState<Block>,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<Option<VoterSetState<Block>>> {
+	let previous = load_set_state(backend, cipher)?;
+
+	write_set_state(backend, state, None, cipher, &[])?;
+
+	Ok(previous)
+}
+
+/// Write voter set state like `write_voter_set_state`, encrypting it with the
+/// given cipher if any and updating the number of stored completed rounds in
+/// the given metrics once written.
+pub(crate) fn write_voter_set_state_with_metrics<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	state: &VoterSetState<Block>,
+	metrics: Option<&Metrics>,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<()> {
+	write_set_state(backend, state, metrics, cipher, &[])
+}
+
+/// Write voter set state like `write_voter_set_state`, encrypting it with the
+/// given cipher if any, see `StateCipher`.
+pub(crate) fn write_voter_set_state_encrypted<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	state: &VoterSetState<Block>,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<()> {
+	write_set_state(backend, state, None, cipher, &[])
+}
+
+/// Write voter set state like `write_voter_set_state_with_metrics` after a
+/// round was completed, counting the voters of the last completed round
+/// towards their participation in the same write, see `load_participation`.
+pub(crate) fn write_voter_set_state_completing_round<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	state: &VoterSetState<Block>,
+	metrics: Option<&Metrics>,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<()> {
+	let participation = round_participation(backend, state.completed_rounds().last())?.encode();
+
+	write_set_state(backend, state, metrics, cipher, &[(PARTICIPATION_KEY, &participation[..])])
+}
+
+fn write_set_state<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	state: &VoterSetState<Block>,
+	metrics: Option<&Metrics>,
+	cipher: Option<&dyn StateCipher>,
+	extra: &[(&'static [u8], &[u8])],
+) -> ClientResult<()> {
+	let encoded_state = encoded_set_state(state, cipher);
+	let encoded_finalized = best_finalized(state).map(|finalized| finalized.encode());
+
+	let mut insert = vec![(SET_STATE_KEY, &encoded_state[..])];
+	if let Some(encoded_finalized) = &encoded_finalized {
+		insert.push((BEST_FINALIZED_KEY, &encoded_finalized[..]));
+	}
+	insert.extend_from_slice(extra);
+
+	let res = backend.insert_aux(&insert, &[]);
+
+	if let (Ok(()), Some(metrics)) = (&res, metrics) {
+		metrics.note_completed_rounds(state.iter_completed_rounds().count());
+	}
+
+	res
+}
+
+/// Load the best block finalized by GRANDPA, as recorded by the last write of
+/// the voter set state.
+pub fn load_best_finalized<Block: BlockT, B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Option<(Block::Hash, NumberFor<Block>)>> {
+	load_decode(backend, BEST_FINALIZED_KEY).map_err(Into::into)
+}
+
+/// Write the last imported GRANDPA justification. It is cleared when the
+/// authority set is handed off, see `commit_grandpa_state`.
+pub(crate) fn write_last_justification<B: AuxStore>(
+	backend: &B,
+	justification: &Justification,
+) -> ClientResult<()> {
+	backend.insert_aux(&[(LAST_JUSTIFICATION_KEY, Some(justification).encode().as_slice())], &[])
+}
+
+/// Load the last imported GRANDPA justification of the current authority set.
+pub fn load_last_justification<B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Option<Justification>> {
+	load_decode::<_, Option<Justification>>(backend, LAST_JUSTIFICATION_KEY)
+		.map(Option::flatten)
+		.map_err(Into::into)
+}
+
+/// Equivocation evidence observed during a round, kept until it is reported.
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub(crate) struct StoredEquivocation<Block: BlockT> {
+	/// The set id of the equivocating authority.
+	pub(crate) set_id: SetId,
+	/// The round in which the authority equivocated.
+	pub(crate) round: RoundNumber,
+	/// The equivocating authority.
+	pub(crate) authority: AuthorityId,
+	/// The first of the two conflicting votes.
+	pub(crate) first: SignedMessage<Block>,
+	/// The second of the two conflicting votes.
+	pub(crate) second: SignedMessage<Block>,
+}
+
+/// Write the equivocation evidence observed by the current authority set,
+/// replacing what was stored. It is cleared when the authority set is handed
+/// off, see `commit_grandpa_state`.
+pub(crate) fn write_equivocations<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	equivocations: &[StoredEquivocation<Block>],
+) -> ClientResult<()> {
+	backend.insert_aux(&[(EQUIVOCATIONS_KEY, equivocations.encode().as_slice())], &[])
+}
+
+/// Load the equivocation evidence observed by the current authority set.
+pub(crate) fn load_equivocations<Block: BlockT, B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Vec<StoredEquivocation<Block>>> {
+	Ok(load_decode(backend, EQUIVOCATIONS_KEY)?.unwrap_or_default())
+}
+
+/// Count the authorities that voted in the given completed round towards their
+/// stored participation, each at most once per round. The counts are reset
+/// when the authority set is handed off, see `commit_grandpa_state`.
+fn round_participation<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	round: &CompletedRound<Block>,
+) -> ClientResult<BTreeMap<AuthorityId, u64>> {
+	let mut participation = load_participation(backend)?;
+
+	let voters = round.votes.iter().map(|vote| &vote.id).collect::<BTreeSet<_>>();
+	for voter in voters {
+		*participation.entry(voter.clone()).or_insert(0) += 1;
+	}
+
+	Ok(participation)
+}
+
+/// Load the number of completed rounds each authority of the current set
+/// voted in.
+pub(crate) fn load_participation<B: AuxStore>(
+	backend: &B,
+) -> ClientResult<BTreeMap<AuthorityId, u64>> {
+	Ok(load_decode(backend, PARTICIPATION_KEY)?.unwrap_or_default())
+}
+
+/// Write concluded round.
+pub(crate) fn write_concluded_round<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	round_data: &CompletedRound<Block>,
+) -> ClientResult<()> {
+	let mut key = CONCLUDED_ROUNDS.to_vec();
+	let round_number = round_data.number;
+	round_number.using_encoded(|n| key.extend(n));
+
+	backend.insert_aux(&[(&key[..], round_data.encode().as_slice())], &[])
+}
+
+/// Read the current authority set id from the backend, without loading the
+/// rest of the persistent data. Returns `None` if GRANDPA hasn't been
+/// initialized yet.
+pub fn current_set_id<Block: BlockT, B: AuxStore>(backend: &B) -> ClientResult<Option<SetId>> {
+	let set = read_authority_set_cached::<Block, _>(backend, None)?;
+
+	Ok(set.map(|set| set.set_id()))
+}
+
+/// Read the current authority set id and authorities from the backend,
+/// decoding the authority set once. Returns `None` if GRANDPA hasn't been
+/// initialized yet.
+pub fn current_authority_list<Block: BlockT, B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Option<(SetId, AuthorityList)>> {
+	let set = read_authority_set_cached::<Block, _>(backend, None)?;
+
+	Ok(set.map(|set| (set.set_id(), set.current_authorities)))
+}
+
+/// Export the authorities of the current authority set as the SCALE encoded
+/// `AuthorityList` expected by the runtime, e.g. to bootstrap a light client.
+/// Returns `None` if GRANDPA hasn't been initialized yet.
+pub fn export_current_authorities_scale<Block: BlockT, B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Option<Vec<u8>>> {
+	let set = read_authority_set_cached::<Block, _>(backend, None)?;
+
+	Ok(set.map(|set| set.current().1.encode()))
+}
+
+/// Whether loading the persistent data from the backend would migrate the
+/// GRANDPA aux-db. Doesn't write anything. An uninitialized backend isn't
+/// considered pending a migration.
+pub fn migration_pending<B: AuxStore>(backend: &B) -> ClientResult<bool> {
+	match load_decode::<_, u32>(backend, VERSION_KEY)? {
+		Some(version) => Ok(version < CURRENT_VERSION),
+		None => Ok(backend.get_aux(AUTHORITY_SET_KEY)?.is_some()),
+	}
+}
+
+/// All the fixed keys of the GRANDPA aux-db entries. Concluded rounds and
+/// migration backups are stored under derived keys and aren't included.
+pub fn schema_keys() -> &'static [&'static [u8]] {
+	&[
+		VERSION_KEY,
+		SET_STATE_KEY,
+		AUTHORITY_SET_KEY,
+		CONSENSUS_CHANGES_KEY,
+		BEST_FINALIZED_KEY,
+		LAST_JUSTIFICATION_KEY,
+		EQUIVOCATIONS_KEY,
+		MAX_SET_ID_KEY,
+		GENESIS_KEY,
+		PARTICIPATION_KEY,
+		LAST_HANDOFF_KEY,
+		INIT_KEY,
+		HANDOFF_LOG_KEY,
+	]
+}
+
+/// Copy the raw GRANDPA aux-db entries stored under `schema_keys`. Absent
+/// entries are included as `None`.
+pub fn snapshot<B: AuxStore>(backend: &B) -> ClientResult<Vec<(Vec<u8>, Option<Vec<u8>>)>> {
+	schema_keys().iter()
+		.map(|key| backend.get_aux(key).map(|value| (key.to_vec(), value)))
+		.collect()
+}
+
+/// The stored length in bytes of each GRANDPA aux-db entry under
+/// `schema_keys`, keyed by name. Absent entries have a length of 0.
+pub fn storage_footprint<B: AuxStore>(backend: &B) -> ClientResult<BTreeMap<&'static str, usize>> {
+	schema_keys().iter()
+		.map(|key| {
+			let name = std::str::from_utf8(key).expect("schema keys are ASCII; qed");
+			backend.get_aux(key).map(|value| (name, value.map_or(0, |value| value.len())))
+		})
+		.collect()
+}
+
+/// Restore GRANDPA aux-db entries copied by `snapshot` in a single write.
+/// Entries that were absent when the snapshot was taken are deleted.
+pub fn restore<B: AuxStore>(
+	backend: &B,
+	snapshot: &[(Vec<u8>, Option<Vec<u8>>)],
+) -> ClientResult<()> {
+	let insert = snapshot.iter()
+		.filter_map(|(key, value)| value.as_ref().map(|value| (&key[..], &value[..])))
+		.collect::<Vec<_>>();
+	let delete = snapshot.iter()
+		.filter(|(_, value)| value.is_none())
+		.map(|(key, _)| &key[..])
+		.collect::<Vec<_>>();
+
+	backend.insert_aux(&insert, &delete)
+}
+
+/// Read the number of authorities of the current set and their total weight
+/// from the backend, without loading the rest of the persistent data. Returns
+/// `None` if GRANDPA hasn't been initialized yet.
+pub fn authority_stats<Block: BlockT, B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Option<(usize, u64)>> {
+	let set = match read_authority_set_cached::<Block, _>(backend, None)? {
+		Some(set) => set,
+		None => return Ok(None),
+	};
+
+	let total_weight = set.total_weight().ok_or(GrandpaAuxError::WeightOverflow)?;
+
+	Ok(Some((set.authorities().len(), total_weight)))
+}
+
+/// Update the weights of authorities of the stored authority set in place,
+/// keeping its set id, membership and pending changes. Unlike a handoff this
+/// leaves the voter set state untouched. Fails without writing anything if an
+/// authority isn't in the current set or is given a zero weight. Returns the
+/// updated authorities.
+pub fn update_authority_weights<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	new_weights: &[(AuthorityId, u64)],
+) -> ClientResult<AuthorityList> {
+	let mut set = read_authority_set_cached::<Block, _>(backend, None)?
+		.ok_or(GrandpaAuxError::Uninitialized)?;
+
+	for (id, weight) in new_weights {
+		let invalid = |reason| GrandpaAuxError::InvalidWeightUpdate {
+			authority: format!("{:?}", id),
+			reason,
+		};
+
+		if *weight == 0 {
+			return Err(invalid("weight must not be zero").into());
+		}
+
+		match set.current_authorities.iter_mut().find(|(authority, _)| authority == id) {
+			Some((_, current_weight)) => *current_weight = *weight,
+			None => return Err(invalid("not in the current authority set").into()),
+		}
+	}
+
+	backend.insert_aux(&[(AUTHORITY_SET_KEY, &framed(&set.encode())[..])], &[])?;
+
+	Ok(set.current_authorities)
+}
+
+/// Get the pending authority set changes signaled at or before the given
+/// block. Standard changes are only returned if they were signaled on the
+/// chain of the given block, forced changes are always returned.
+///
+/// `is_descendent_of` should return `true` if the second hash (target) is a
+/// descendent of the first hash (base).
+pub fn pending_changes_at<Block: BlockT, B, F>(
+	backend: &B,
+	hash: Block::Hash,
+	number: NumberFor<Block>,
+	is_descendent_of: &F,
+) -> ClientResult<Vec<PendingChange<Block::Hash, NumberFor<Block>>>> where
+	B: AuxStore,
+	F: Fn(&Block::Hash, &Block::Hash) -> ClientResult<bool>,
+{
+	let set = match read_authority_set_cached::<Block, _>(backend, None)? {
+		Some(set) => set,
+		None => return Ok(Vec::new()),
+	};
+
+	let mut changes = Vec::new();
+	for change in set.pending_changes() {
+		if change.canon_height > number {
+			continue;
+		}
+
+		let on_chain = match change.delay_kind {
+			DelayKind::Best { .. } => true,
+			DelayKind::Finalized =>
+				change.canon_hash == hash || is_descendent_of(&change.canon_hash, &hash)?,
+		};
+
+		if on_chain {
+			changes.push(change.clone());
+		}
+	}
+
+	Ok(changes)
+}
+
+/// Read the authority set, preferring the one already loaded by
+/// `load_persistent` and only decoding it from the backend on cold reads,
+/// e.g. by the standalone readers which run without a loaded set.
+///
+/// The shared authority set is only a valid cache as long as it is updated in
+/// memory whenever `commit_grandpa_state` writes a new set, which is what the
+/// block import and finality paths do while holding its lock.
+pub(crate) fn read_authority_set_cached<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	cached: Option<&SharedAuthoritySet<Block::Hash, NumberFor<Block>>>,
+) -> ClientResult<Option<AuthoritySet<Block::Hash, NumberFor<Block>>>> {
+	if let Some(cached) = cached {
+		return Ok(Some(cached.inner().read().clone()));
+	}
+
+	load_decode_checked(backend, AUTHORITY_SET_KEY).map_err(Into::into)
+}
+
+/// Load the set id, hash and number of the block that enacted the last
+/// authority set handoff. `None` until the first handoff after genesis.
+pub fn load_last_handoff<Block: BlockT, B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Option<(SetId, Block::Hash, NumberFor<Block>)>> {
+	load_decode(backend, LAST_HANDOFF_KEY).map_err(Into::into)
+}
+
+/// Load the set id, hash and number of the blocks that enacted the logged
+/// authority set handoffs, from oldest to newest. Only the latest
+/// `DEFAULT_HANDOFF_LOG_LIMIT` handoffs are kept.
+pub(crate) fn load_handoff_log<Block: BlockT, B: AuxStore>(
+	backend: &B,
+) -> ClientResult<Vec<(SetId, Block::Hash, NumberFor<Block>)>> {
+	Ok(load_decode(backend, HANDOFF_LOG_KEY)?.unwrap_or_default())
+}
+
+/// Load the consensus changes, empty if none are stored. The decoding is
+/// counted in the given metrics, if any.
+pub(crate) fn load_consensus_changes<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	decode_metrics: Option<&DecodeMetrics>,
+) -> ClientResult<ConsensusChanges<Block::Hash, NumberFor<Block>>> {
+	Ok(load_decode_with_metrics(backend, CONSENSUS_CHANGES_KEY, decode_metrics)?
+		.unwrap_or_else(ConsensusChanges::empty))
+}
+
+/// Update the consensus changes. Duplicate pending changes, e.g. accumulated
+/// by an older node, are dropped.
+pub(crate) fn update_consensus_changes<H, N, F, R>(
+	set: &ConsensusChanges<H, N>,
+	write_aux: F
+) -> R where
+	H: Encode + Copy + PartialEq,
+	N: Encode + Copy + Ord,
+	F: FnOnce(&[(&'static [u8], &[u8])]) -> R,
+{
+	let mut set = set.clone();
+	let dropped = set.dedup();
+	if dropped > 0 {
+		warn!(target: "afg", "Dropping {} duplicate pending consensus changes.", dropped);
+	}
+
+	write_aux(&[(CONSENSUS_CHANGES_KEY, set.encode().as_slice())])
+}
+
+/// Drop the stored consensus changes above the given block number, e.g. after
+/// reverting blocks. Nothing is written if no changes are above it.
+pub fn revert_consensus_changes<H, N, B>(
+	backend: &B,
+	number: N,
+) -> ClientResult<()> where
+	H: Encode + Decode + Copy + PartialEq,
+	N: Encode + Decode + Copy + Ord,
+	B: AuxStore,
+{
+	let mut changes = match load_decode::<_, ConsensusChanges<H, N>>(backend, CONSENSUS_CHANGES_KEY)? {
+		Some(changes) => changes,
+		None => return Ok(()),
+	};
+
+	if changes.revert(number) {
+		update_consensus_changes(&changes, |values| backend.insert_aux(values, &[]))?;
+	}
+
+	Ok(())
+}
+
+/// The completed rounds kept by a handoff written with `commit_grandpa_state`.
+pub(crate) enum HandoffHistory<'a, Block: BlockT> {
+	/// Start the new set from a blank slate.
+	Wipe,
+	/// Keep the completed rounds of the given voter set state, e.g. to audit
+	/// rounds preceding a forced change. If a block number is given, kept
+	/// rounds that finalized a block below it are dropped.
+	Preserve(&'a VoterSetState<Block>, Option<NumberFor<Block>>),
+}
+
+/// Write the authority set, voter set state and consensus changes in a single
+/// write, so that a crash can't leave them out of sync. The values are passed
+/// to `write_aux`, e.g. to be committed along with a block import or
+/// finality operation.
+///
+/// `set` should reflect the current authority set, with all changes and
+/// handoffs applied. If there has just been a handoff `new_set` must describe
+/// it and the voter set state is replaced with a blank slate for the new set,
+/// because from the perspective of the finality gadget the chain has reset,
+/// unless `history` preserves the previous completed rounds. The new set id
+/// and base are applied either way.
+/// A justification, equivocations and participation of the previous set
+/// aren't useful anymore and are cleared. Otherwise the voter set state and
+/// consensus changes are only written if given. The voter set state is
+/// encrypted with `cipher` if any. Callers must keep the in-memory
+/// `SharedAuthoritySet` in sync with what is written here, see
+/// `read_authority_set_cached`.
+pub(crate) fn commit_grandpa_state<Block: BlockT, B, F>(
+	backend: &B,
+	set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+	new_set: Option<&NewAuthoritySet<Block::Hash, NumberFor<Block>>>,
+	history: HandoffHistory<Block>,
+	voter_state: Option<&VoterSetState<Block>>,
+	consensus_changes: Option<&ConsensusChanges<Block::Hash, NumberFor<Block>>>,
+	cipher: Option<&dyn StateCipher>,
+	write_aux: F,
+) -> ClientResult<()> where
+	B: AuxStore,
+	F: FnOnce(&[(&'static [u8], &[u8])]) -> ClientResult<()>,
+{
+	let reset_state;
+	let voter_state = match new_set {
+		Some(new_set) => {
+			let base = (new_set.canon_hash, new_set.canon_number);
+			reset_state = match history {
+				HandoffHistory::Wipe => VoterSetState::<Block>::live(new_set.set_id, &set, base),
+				HandoffHistory::Preserve(previous, prune_below) => {
+					let mut state = previous.hand_off(new_set.set_id, &set, base);
+					if let (Some(number), VoterSetState::Live { completed_rounds, .. }) = (prune_below, &mut state) {
+						completed_rounds.prune_below(number);
+					}
+					state
+				},
+			};
+			Some(&reset_state)
+		},
+		None => voter_state,
+	};
+
+	let encoded_set = framed(&set.encode());
+	let encoded_state = voter_state.map(|state| encoded_set_state(state, cipher));
+	let encoded_changes = consensus_changes.map(|changes| changes.encode());
+	let encoded_finalized = voter_state
+		.and_then(|state| best_finalized(state))
+		.map(|finalized| finalized.encode());
+	let no_justification = None::<Justification>.encode();
+	let no_equivocations = Vec::<StoredEquivocation<Block>>::new().encode();
+	let no_participation = BTreeMap::<AuthorityId, u64>::new().encode();
+	let max_set_id = updated_max_set_id(backend, set.set_id())?;
+	let handoff = new_set.map(|new_set| (new_set.set_id, new_set.canon_hash, new_set.canon_number).encode());
+	let handoff_log = match new_set {
+		Some(new_set) => Some(appended_handoff_log::<Block, _>(
+			backend,
+			(new_set.set_id, new_set.canon_hash, new_set.canon_number),
+			DEFAULT_HANDOFF_LOG_LIMIT,
+		)?),
+		None => None,
+	};
+
+	let mut insert = vec![(AUTHORITY_SET_KEY, &encoded_set[..])];
+	if let Some(max_set_id) = &max_set_id {
+		insert.push((MAX_SET_ID_KEY, &max_set_id[..]));
+	}
+	if let Some(encoded_state) = &encoded_state {
+		insert.push((SET_STATE_KEY, &encoded_state[..]));
+	}
+	if let Some(encoded_changes) = &encoded_changes {
+		insert.push((CONSENSUS_CHANGES_KEY, &encoded_changes[..]));
+	}
+	if let Some(encoded_finalized) = &encoded_finalized {
+		insert.push((BEST_FINALIZED_KEY, &encoded_finalized[..]));
+	}
+	if new_set.is_some() {
+		insert.push((LAST_JUSTIFICATION_KEY, &no_justification[..]));
+		insert.push((EQUIVOCATIONS_KEY, &no_equivocations[..]));
+		insert.push((PARTICIPATION_KEY, &no_participation[..]));
+	}
+	if let Some(handoff) = &handoff {
+		insert.push((LAST_HANDOFF_KEY, &handoff[..]));
+	}
+	if let Some(handoff_log) = &handoff_log {
+		insert.push((HANDOFF_LOG_KEY, &handoff_log[..]));
+	}
+
+	write_aux(&insert)
+}
+
+/// Write the authority set after applying standard changes together with the
+/// consensus changes in a single write, so that a crash can't leave a change
+/// half-applied. The values are written like `commit_grandpa_state` does,
+/// handing off to `new_set` if given, with the consensus changes updated
+/// like `update_consensus_changes` does.
+pub(crate) fn apply_standard_change<Block: BlockT, B, F>(
+	backend: &B,
+	set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+	new_set: Option<&NewAuthoritySet<Block::Hash, NumberFor<Block>>>,
+	consensus_changes: &ConsensusChanges<Block::Hash, NumberFor<Block>>,
+	cipher: Option<&dyn StateCipher>,
+	write_aux: F,
+) -> ClientResult<()> where
+	B: AuxStore,
+	F: FnOnce(&[(&'static [u8], &[u8])]) -> ClientResult<()>,
+{
+	update_consensus_changes(consensus_changes, |changes| {
+		commit_grandpa_state::<Block, _, _>(
+			backend,
+			set,
+			new_set,
+			HandoffHistory::Wipe,
+			None,
+			None,
+			cipher,
+			|insert| {
+				let insert = insert.iter().chain(changes).cloned().collect::<Vec<_>>();
+				write_aux(&insert)
+			},
+		)
+	})
+}
+
+/// The status of the voter in a `StateDiff`.
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub enum DiffStatus<Block: BlockT> {
+	/// The voter is live with the given current rounds.
+	Live(CurrentRounds<Block>),
+	/// The voter is paused for the given reason, since the given block.
+	Paused(PauseReason, NumberFor<Block>),
+}
+
+/// The changes between two voter set states of the same authority set, see
+/// `diff_voter_set_state`. Completed rounds are append-only, so only the
+/// rounds completed since the older state are included.
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub struct StateDiff<Block: BlockT> {
+	/// The set id of both states.
+	set_id: SetId,
+	/// The number of the last completed round of the older state.
+	base: RoundNumber,
+	/// The rounds completed since, from oldest to newest. Includes the base
+	/// round itself if it changed.
+	appended: Vec<CompletedRound<Block>>,
+	/// The status of the newer state.
+	status: DiffStatus<Block>,
+}
+
+/// Compute the diff from the `old` to the `new` voter set state. Returns
+/// `None` if `new` doesn't extend `old`, e.g. after a handoff, in which case
+/// the full state must be used instead.
+pub fn diff_voter_set_state<Block: BlockT>(
+	old: &VoterSetState<Block>,
+	new: &VoterSetState<Block>,
+) -> Option<StateDiff<Block>> {
+	let set_id = old.completed_rounds().set_info().0;
+	let base = old.completed_rounds().last();
+	if new.completed_rounds().set_info().0 != set_id || new.completed_rounds().last_number() < base.number {
+		return None;
+	}
+
+	let appended = new.iter_completed_rounds()
+		.filter(|round| round.number > base.number || (round.number == base.number && *round != base))
+		.cloned()
+		.collect();
+
+	let status = match new {
+		VoterSetState::Live { current_rounds, .. } => DiffStatus::Live(current_rounds.clone()),
+		VoterSetState::Paused { reason, since, .. } => DiffStatus::Paused(*reason, *since),
+	};
+
+	Some(StateDiff { set_id, base: base.number, appended, status })
+}
+
+/// Apply a diff computed by `diff_voter_set_state` to the older voter set
+/// state. Fails if the diff was computed against another state.
+pub fn apply_diff<Block: BlockT>(
+	old: &VoterSetState<Block>,
+	diff: &StateDiff<Block>,
+) -> ClientResult<VoterSetState<Block>> {
+	let mut completed_rounds = old.completed_rounds().clone();
+	if completed_rounds.set_info().0 != diff.set_id {
+		return Err(GrandpaAuxError::InvalidDiff("set id mismatch").into());
+	}
+	if completed_rounds.last_number() != diff.base {
+		return Err(GrandpaAuxError::InvalidDiff("base round mismatch").into());
+	}
+
+	for round in &diff.appended {
+		completed_rounds.push(round.clone());
+	}
+
+	Ok(match &diff.status {
+		DiffStatus::Live(current_rounds) =>
+			VoterSetState::Live { completed_rounds, current_rounds: current_rounds.clone() },
+		DiffStatus::Paused(reason, since) =>
+			VoterSetState::Paused { completed_rounds, reason: *reason, since: *since },
+	})
+}
+
+#[cfg(feature = "state-json")]
+fn authorities_json(authorities: &[(AuthorityId, u64)]) -> serde_json::Value {
+	authorities.iter().map(|(id, weight)| serde_json::json!({
+		"id": sp_core::bytes::to_hex(&id.encode(), false),
+		"weight": weight,
+	})).collect()
+}
+
+#[cfg(feature = "state-json")]
+fn block_json<H: AsRef<[u8]>, N: std::fmt::Display>(block: &(H, N)) -> serde_json::Value {
+	serde_json::json!({
+		"hash": sp_core::bytes::to_hex(block.0.as_ref(), false),
+		"number": block.1.to_string(),
+	})
+}
+
+/// Export the GRANDPA state stored in the aux-db as JSON, for debugging.
+///
+/// Hashes are hex-encoded and block numbers are rendered as decimal strings.
+/// Alongside the readable fields, each entry carries its hex-encoded SCALE
+/// representation under `encoded`, which is what the state can be restored
+/// from. An encrypted voter set state is decrypted with the given cipher and
+/// exported in plain.
+#[cfg(feature = "state-json")]
+pub fn export_state_json<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<serde_json::Value> {
+	// older versions must be migrated by `load_persistent` first, version 0
+	// didn't store a version.
+	match load_decode::<_, u32>(backend, VERSION_KEY)? {
+		Some(CURRENT_VERSION) => {},
+		Some(version) if version > CURRENT_VERSION =>
+			return Err(GrandpaAuxError::UnsupportedVersion(version).into()),
+		Some(version) => return Err(GrandpaAuxError::OutdatedVersion(version).into()),
+		None => return Err(GrandpaAuxError::OutdatedVersion(0).into()),
+	}
+
+	let authority_set = load_decode_checked::<_, AuthoritySet<Block::Hash, NumberFor<Block>>>(
+		backend,
+		AUTHORITY_SET_KEY,
+	)?;
+	let set_state = load_set_state::<Block, _>(backend, cipher)?;
+	let genesis = load_decode::<_, (Block::Hash, NumberFor<Block>)>(backend, GENESIS_KEY)?;
+
+	let genesis = genesis.map(|genesis| {
+		let mut json = block_json(&genesis);
+		json["encoded"] = serde_json::json!(sp_core::bytes::to_hex(&genesis.encode(), false));
+		json
+	});
+
+	let authority_set = authority_set.map(|set| {
+		let (set_id, authorities) = set.current();
+		let pending_changes = set.pending_changes().map(|change| serde_json::json!({
+			"next_authorities": authorities_json(&change.next_authorities),
+			"delay": change.delay.to_string(),
+			"canon": block_json(&(change.canon_hash, change.canon_height)),
+			"forced": match change.delay_kind {
+				DelayKind::Finalized => false,
+				DelayKind::Best { .. } => true,
+			},
+		})).collect::<Vec<_>>();
+
+		serde_json::json!({
+			"set_id": set_id,
+			"authorities": authorities_json(authorities),
+			"pending_changes": pending_changes,
+			"encoded": sp_core::bytes::to_hex(&set.encode(), false),
+		})
+	});
+
+	let set_state = set_state.map(|state| {
+		let (status, set_id) = match &state {
+			VoterSetState::Live { completed_rounds, .. } => ("live", completed_rounds.set_info().0),
+			VoterSetState::Paused { completed_rounds, .. } => ("paused", completed_rounds.set_info().0),
+		};
+		let completed_rounds = state.iter_completed_rounds().map(|round| serde_json::json!({
+			"number": round.number,
+			"base": block_json(&round.base),
+			"finalized": round.state.finalized.as_ref().map(block_json),
+			"completed_at": round.completed_at,
+		})).collect::<Vec<_>>();
+
+		serde_json::json!({
+			"status": status,
+			"set_id": set_id,
+			"completed_rounds": completed_rounds,
+			"encoded": sp_core::bytes::to_hex(&state.encode(), false),
+		})
+	});
+
+	Ok(serde_json::json!({
+		"version": CURRENT_VERSION,
+		"genesis": genesis,
+		"authority_set": authority_set,
+		"voter_set_state": set_state,
+	}))
+}
+
+/// Import GRANDPA state exported by `export_state_json` into the aux-db,
+/// writing every entry written when initializing from genesis in a single
+/// write. Documents exported by a different schema version are rejected. The
+/// voter set state is encrypted with the given cipher if any.
+#[cfg(feature = "state-json")]
+pub fn import_state_json<Block: BlockT, B: AuxStore>(
+	backend: &B,
+	value: &serde_json::Value,
+	cipher: Option<&dyn StateCipher>,
+) -> ClientResult<()> {
+	let invalid = |reason: &str| ClientError::Backend(format!("Invalid GRANDPA state JSON: {}", reason));
+
+	let version = value["version"].as_u64().ok_or_else(|| invalid("missing version"))?;
+	if version != CURRENT_VERSION as u64 {
+		return Err(GrandpaAuxError::UnsupportedVersion(version as u32).into());
+	}
+
+	let encoded = |entry: &str| -> ClientResult<Vec<u8>> {
+		let hex = value[entry]["encoded"].as_str()
+			.ok_or_else(|| invalid(&format!("missing encoded {}", entry)))?;
+		sp_core::bytes::from_hex(hex).map_err(|e| invalid(&format!("{}: {:?}", entry, e)))
+	};
+
+	let authority_set: AuthoritySet<Block::Hash, NumberFor<Block>> =
+		decode_entry(AUTHORITY_SET_KEY, &encoded("authority_set")?)?;
+	let set_state: VoterSetState<Block> =
+		decode_entry(SET_STATE_KEY, &encoded("voter_set_state")?)?;
+	let genesis: (Block::Hash, NumberFor<Block>) = decode_entry(GENESIS_KEY, &encoded("genesis")?)?;
+
+	let encoded_set = framed(&authority_set.encode());
+	let encoded_state = encoded_set_state(&set_state, cipher);
+	let encoded_version = CURRENT_VERSION.encode();
+	let encoded_finalized = best_finalized(&set_state).map(|finalized| finalized.encode());
+
+	let encoded_genesis = genesis.encode();
+
+	let mut insert = vec![
+		(AUTHORITY_SET_KEY, &encoded_set[..]),
+		(SET_STATE_KEY, &encoded_state[..]),
+		(GENESIS_KEY, &encoded_genesis[..]),
+		(INIT_KEY, &encoded_version[..]),
+		(VERSION_KEY, &encoded_version[..]),
+	];
+	let mut delete = Vec::new();
+	match &encoded_finalized {
+		Some(encoded_finalized) => insert.push((BEST_FINALIZED_KEY, &encoded_finalized[..])),
+		None => delete.push(BEST_FINALIZED_KEY),
+	}
+
+	backend.insert_aux(&insert, &delete)
+}
+
+#[cfg(test)]
+pub(crate) fn load_authorities<B: AuxStore, H: Decode, N: Decode>(backend: &B)
+	-> Option<AuthoritySet<H, N>> {
+	load_decode_checked::<_, AuthoritySet<H, N>>(backend, AUTHORITY_SET_KEY)
+		.expect("backend error")
+}
+
+#[cfg(test)]
+mod test {
+	use sp_finality_grandpa::AuthorityId;
+	use sp_core::H256;
+	use substrate_test_runtime_client;
+	use super::*;
+
+	/// In-memory aux store, optionally failing writes to a given key.
+	#[derive(Default)]
+	struct MockAuxStore {
+		entries: parking_lot::Mutex<std::collections::HashMap<Vec<u8>, Vec<u8>>>,
+		failing_key: parking_lot::Mutex<Option<Vec<u8>>>,
+		read_only: parking_lot::Mutex<bool>,
+		reads: parking_lot::Mutex<usize>,
+	}
+
+	impl MockAuxStore {
+		/// Make any later write touching `key` fail.
+		fn fail_writes_to(&self, key: &[u8]) {
+			*self.failing_key.lock() = Some(key.to_vec());
+		}
+
+		/// Make any later write fail.
+		fn make_read_only(&self) {
+			*self.read_only.lock() = true;
+		}
+	}
+
+	impl AuxStore for MockAuxStore {
+		fn insert_aux<
+			'a,
+			'b: 'a,
+			'c: 'a,
+			I: IntoIterator<Item=&'a(&'c [u8], &'c [u8])>,
+			D: IntoIterator<Item=&'a &'b [u8]>,
+		>(&self, insert: I, delete: D) -> ClientResult<()> {
+			let insert = insert.into_iter().collect::<Vec<_>>();
+			let delete = delete.into_iter().collect::<Vec<_>>();
+
+			if *self.read_only.lock() {
+				return Err(ClientError::Backend("Injected write failure: read-only".into()));
+			}
+
+			if let Some(failing_key) = &*self.failing_key.lock() {
+				let mut keys = insert.iter().map(|(k, _)| *k).chain(delete.iter().map(|k| **k));
+				if keys.any(|key| key == &failing_key[..]) {
+					return Err(ClientError::Backend(
+						format!("Injected write failure for {:?}", String::from_utf8_lossy(failing_key)),
+					));
+				}
+			}
+
+			let mut entries = self.entries.lock();
+			for (key, value) in insert {
+				entries.insert(key.to_vec(), value.to_vec());
+			}
+			for key in delete {
+				entries.remove(*key);
+			}
+
+			Ok(())
+		}
+
+		fn get_aux(&self, key: &[u8]) -> ClientResult<Option<Vec<u8>>> {
+			*self.reads.lock() += 1;
+			Ok(self.entries.lock().get(key).cloned())
+		}
+	}
+
+	/// Load the given raw authority set and voter set state entries stored
+	/// with the given schema version, running the corresponding migration.
+	/// This is the entry point for fuzzing the decoders, it must never panic.
+	fn try_load_persistent_from_bytes(
+		version: Option<u32>,
+		authority_bytes: &[u8],
+		state_bytes: &[u8],
+	) -> ClientResult<PersistentData<substrate_test_runtime_client::runtime::Block>> {
+		let backend = MockAuxStore::default();
+
+		let encoded_version = version.map(|version| version.encode());
+		let mut insert = vec![
+			(AUTHORITY_SET_KEY, authority_bytes),
+			(SET_STATE_KEY, state_bytes),
+		];
+		if let Some(encoded_version) = &encoded_version {
+			insert.push((VERSION_KEY, &encoded_version[..]));
+		}
+		backend.insert_aux(&insert, &[])?;
+
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&backend,
+			H256::zero(),
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		)
+	}
+
+	#[test]
+	fn load_decode_from_v0_migrates_data_format() {
+		let genesis_hash = H256::random();
+		let client = MockAuxStore::default();
+
+		let authorities = vec![(AuthorityId::default(), 100)];
+		let set_id = 3;
+		let round_number: RoundNumber = 42;
+		let round_state = RoundState::<H256, u64> {
+			prevote_ghost: Some((H256::random(), 32)),
+			finalized: None,
+			estimate: None,
+			completable: false,
+		};
+
+		{
+			let authority_set = V0AuthoritySet::<H256, u64> {
+				current_authorities: authorities.clone(),
+				pending_changes: Vec::new(),
+				set_id,
+			};
+
+			let voter_set_state = (round_number, round_state.clone());
+
+			client.insert_aux(
+				&[
+					(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+					(SET_STATE_KEY, voter_set_state.encode().as_slice()),
+				],
+				&[],
+			).unwrap();
+		}
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			None,
+		);
+
+		// should perform the migration
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+
+		let PersistentData { authority_set, set_state, .. } = load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			*authority_set.inner().read(),
+			AuthoritySet::new(
+				authorities.clone(),
+				set_id,
+				ForkTree::new(),
+				Vec::new(),
+			).unwrap(),
+		);
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(round_number + 1, HasVoted::No);
+
+		assert_eq!(
+			&*set_state.read(),
+			&VoterSetState::Live {
+				completed_rounds: CompletedRounds::new(
+					CompletedRound {
+						number: round_number,
+						state: round_state.clone(),
+						base: round_state.prevote_ghost.unwrap(),
+						votes: vec![],
+						completed_at: None,
+						set_id: Some(set_id),
+					},
+					set_id,
+					&*authority_set.inner().read(),
+				),
+				current_rounds,
+			},
+		);
+	}
+
+	#[test]
+	fn migration_from_v0_propagates_backend_errors() {
+		let backend = MockAuxStore::default();
+
+		let authority_set = V0AuthoritySet::<H256, u64> {
+			current_authorities: vec![(AuthorityId::default(), 100)],
+			pending_changes: Vec::new(),
+			set_id: 3,
+		};
+
+		backend.insert_aux(
+			&[(AUTHORITY_SET_KEY, authority_set.encode().as_slice())],
+			&[],
+		).unwrap();
+
+		backend.fail_writes_to(AUTHORITY_SET_KEY);
+
+		let err = load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&backend,
+			H256::random(),
+			0,
+			|| unreachable!(),
+		).err().unwrap();
+
+		assert!(err.to_string().contains("Injected write failure"));
+	}
+
+	#[test]
+	fn load_decode_from_v1_migrates_data_format() {
+		let genesis_hash = H256::random();
+		let client = substrate_test_runtime_client::new();
+
+		let authorities = vec![(AuthorityId::default(), 100)];
+		let set_id = 3;
+		let round_number: RoundNumber = 42;
+		let round_state = RoundState::<H256, u64> {
+			prevote_ghost: Some((H256::random(), 32)),
+			finalized: None,
+			estimate: None,
+			completable: false,
+		};
+
+		{
+			let authority_set = AuthoritySet::<H256, u64>::new(
+				authorities.clone(),
+				set_id,
+				ForkTree::new(),
+				Vec::new(),
+			).unwrap();
+
+			let voter_set_state = V1VoterSetState::Live(round_number, round_state.clone());
+
+			client.insert_aux(
+				&[
+					(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+					(SET_STATE_KEY, voter_set_state.encode().as_slice()),
+					(VERSION_KEY, 1u32.encode().as_slice()),
+				],
+				&[],
+			).unwrap();
+		}
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(1),
+		);
+
+		// should perform the migration
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+
+		let PersistentData { authority_set, set_state, .. } = load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			*authority_set.inner().read(),
+			AuthoritySet::new(
+				authorities.clone(),
+				set_id,
+				ForkTree::new(),
+				Vec::new(),
+			).unwrap(),
+		);
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(round_number + 1, HasVoted::No);
+
+		assert_eq!(
+			&*set_state.read(),
+			&VoterSetState::Live {
+				completed_rounds: CompletedRounds::new(
+					CompletedRound {
+						number: round_number,
+						state: round_state.clone(),
+						base: round_state.prevote_ghost.unwrap(),
+						votes: vec![],
+						completed_at: None,
+						set_id: Some(set_id),
+					},
+					set_id,
+					&*authority_set.inner().read(),
+				),
+				current_rounds,
+			},
+		);
+	}
+
+	#[test]
+	fn load_decode_from_v2_migrates_data_format() {
+		let genesis_hash = H256::random();
+		let client = substrate_test_runtime_client::new();
+
+		let authorities = vec![(AuthorityId::default(), 100)];
+		let set_id = 3;
+		let round_number: RoundNumber = 42;
+		let finalized = (H256::random(), 32);
+		let round_state = RoundState::<H256, u64> {
+			prevote_ghost: Some(finalized),
+			finalized: Some(finalized),
+			estimate: Some(finalized),
+			completable: true,
+		};
+
+		{
+			let authority_set = AuthoritySet::<H256, u64>::new(
+				authorities.clone(),
+				set_id,
+				ForkTree::new(),
+				Vec::new(),
+			).unwrap();
+
+			let mut current_rounds = CurrentRounds::new();
+			current_rounds.insert(round_number + 1, HasVoted::No);
+
+			let voter_set_state = VoterSetState::<substrate_test_runtime_client::runtime::Block>::Live {
+				completed_rounds: CompletedRounds::new(
+					CompletedRound {
+						number: round_number,
+						state: round_state.clone(),
+						base: round_state.prevote_ghost.unwrap(),
+						votes: vec![],
+						completed_at: None,
+						set_id: Some(set_id),
+					},
+					set_id,
+					&authority_set,
+				),
+				current_rounds,
+			};
+
+			client.insert_aux(
+				&[
+					(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+					(SET_STATE_KEY, V3VoterSetState::from(&voter_set_state).encode().as_slice()),
+					(VERSION_KEY, 2u32.encode().as_slice()),
+				],
+				&[],
+			).unwrap();
+		}
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(2),
+		);
+
+		assert_eq!(
+			load_best_finalized::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			None,
+		);
+
+		// should perform the migration
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+
+		assert_eq!(
+			load_best_finalized::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			Some(finalized),
+		);
+
+		let PersistentData { authority_set, .. } = load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			*authority_set.inner().read(),
+			AuthoritySet::new(
+				authorities.clone(),
+				set_id,
+				ForkTree::new(),
+				Vec::new(),
+			).unwrap(),
+		);
+	}
+
+	#[test]
+	fn load_decode_from_v2_preserves_precommit_vote() {
+		use crate::environment::Vote;
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let genesis_hash = H256::random();
+		let client = substrate_test_runtime_client::new();
+
+		let authorities = vec![(AuthorityId::default(), 100)];
+		let set_id = 3;
+		let round_number: RoundNumber = 42;
+		let round_state = RoundState::genesis((H256::random(), 32));
+
+		let has_voted = HasVoted::<Block>::Yes(
+			AuthorityId::default(),
+			Vote::Precommit(
+				Some(finality_grandpa::PrimaryPropose { target_hash: H256::random(), target_number: 33 }),
+				finality_grandpa::Prevote { target_hash: H256::random(), target_number: 34 },
+				finality_grandpa::Precommit { target_hash: H256::random(), target_number: 35 },
+			),
+		);
+
+		{
+			let authority_set = AuthoritySet::<H256, u64>::new(
+				authorities.clone(),
+				set_id,
+				ForkTree::new(),
+				Vec::new(),
+			).unwrap();
+
+			let mut current_rounds = CurrentRounds::new();
+			current_rounds.insert(round_number + 1, has_voted.clone());
+
+			let voter_set_state = VoterSetState::<Block>::Live {
+				completed_rounds: CompletedRounds::new(
+					CompletedRound {
+						number: round_number,
+						state: round_state.clone(),
+						base: round_state.prevote_ghost.unwrap(),
+						votes: vec![],
+						completed_at: None,
+						set_id: Some(set_id),
+					},
+					set_id,
+					&authority_set,
+				),
+				current_rounds,
+			};
+
+			client.insert_aux(
+				&[
+					(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+					(SET_STATE_KEY, V3VoterSetState::from(&voter_set_state).encode().as_slice()),
+					(VERSION_KEY, 2u32.encode().as_slice()),
+				],
+				&[],
+			).unwrap();
+		}
+
+		// should perform the migration
+		let PersistentData { set_state, .. } = load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+
+		match &*set_state.read() {
+			VoterSetState::Live { current_rounds, .. } =>
+				assert_eq!(current_rounds.get(&(round_number + 1)), Some(&has_voted)),
+			VoterSetState::Paused { .. } => panic!("expected live voter set state"),
+		}
+
+		// and the precommit must survive reloading the migrated state.
+		let PersistentData { set_state, .. } = load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert!(set_state.has_voted(round_number + 1).has_precommitted());
+	}
+
+	// authenticated by a leading key byte, good enough for a test.
+	struct XorCipher(u8);
+
+	impl StateCipher for XorCipher {
+		fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+			std::iter::once(self.0).chain(plaintext.iter().map(|byte| byte ^ self.0)).collect()
+		}
+
+		fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+			match ciphertext.split_first() {
+				Some((key, ciphertext)) if *key == self.0 =>
+					Some(ciphertext.iter().map(|byte| byte ^ self.0).collect()),
+				_ => None,
+			}
+		}
+	}
+
+	// builds completed rounds with the given round numbers, bypassing the
+	// retention limit of `CompletedRounds::push`.
+	fn completed_rounds_from(
+		set_id: SetId,
+		numbers: &[RoundNumber],
+	) -> CompletedRounds<substrate_test_runtime_client::runtime::Block> {
+		// rounds are stored from newest to oldest
+		let rounds = numbers.iter().rev().map(|&number| {
+			let state = RoundState::genesis((H256::random(), number));
+			CompletedRound::<substrate_test_runtime_client::runtime::Block> {
+				number,
+				base: state.prevote_ghost.unwrap(),
+				state,
+				votes: vec![],
+				completed_at: None,
+				set_id: Some(set_id),
+			}
+		}).collect::<Vec<_>>();
+
+		let encoded = (rounds, set_id, vec![AuthorityId::default()]).encode();
+		CompletedRounds::decode(&mut &encoded[..]).unwrap()
+	}
+
+	#[test]
+	fn iter_completed_rounds_is_oldest_to_newest() {
+		let completed_rounds = completed_rounds_from(0, &[5, 6, 7]);
+
+		let paused = VoterSetState::paused(
+			completed_rounds.clone(),
+			PauseReason::Manual,
+			7,
+		);
+		let live = VoterSetState::Live {
+			completed_rounds,
+			current_rounds: CurrentRounds::new(),
+		};
+
+		for state in &[paused, live] {
+			assert_eq!(
+				state.iter_completed_rounds().map(|round| round.number).collect::<Vec<_>>(),
+				vec![5, 6, 7],
+			);
+		}
+	}
+
+	#[test]
+	fn last_completed_round_is_newest() {
+		let completed_rounds = completed_rounds_from(0, &[5, 6, 7]);
+
+		assert_eq!(completed_rounds.last_number(), 7);
+		assert_eq!(completed_rounds.last().number, 7);
+	}
+
+	#[test]
+	fn current_set_id_reads_stored_authority_set() {
+		let client = substrate_test_runtime_client::new();
+
+		assert_eq!(
+			current_set_id::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			None,
+		);
+
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		assert_eq!(
+			current_set_id::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			Some(0),
+		);
+	}
+
+	#[test]
+	fn framed_entries_are_verified_on_read() {
+		let client = MockAuxStore::default();
+		let value = (42u64, H256::random());
+
+		client.insert_aux(&[(&b"framed"[..], &framed(&value.encode())[..])], &[]).unwrap();
+		assert_eq!(client.get_aux(b"framed").unwrap().unwrap()[0], VALUE_FORMAT);
+		assert_eq!(
+			load_decode_checked::<_, (u64, H256)>(&client, b"framed").unwrap(),
+			Some(value),
+		);
+
+		let mut corrupted = client.get_aux(b"framed").unwrap().unwrap();
+		*corrupted.last_mut().unwrap() ^= 1;
+		client.insert_aux(&[(&b"framed"[..], &corrupted[..])], &[]).unwrap();
+
+		match load_decode_checked::<_, (u64, H256)>(&client, b"framed") {
+			Err(GrandpaAuxError::ChecksumMismatch { key }) => assert_eq!(key, "framed"),
+			other => panic!("expected checksum mismatch, got {:?}", other),
+		}
+
+		// an unknown format or a plain value is rejected rather than misread.
+		let mut unknown = framed(&value.encode());
+		unknown[0] = VALUE_FORMAT + 1;
+		for stored in vec![unknown, value.encode()] {
+			client.insert_aux(&[(&b"framed"[..], &stored[..])], &[]).unwrap();
+			match load_decode_checked::<_, (u64, H256)>(&client, b"framed") {
+				Err(GrandpaAuxError::CorruptEntry { key, .. }) => assert_eq!(key, "framed"),
+				other => panic!("expected corrupt entry error, got {:?}", other),
+			}
+		}
+	}
+
+	#[test]
+	fn legacy_framing_is_only_read_before_framed_versions() {
+		let client = MockAuxStore::default();
+		let authority_set = AuthoritySet::<H256, u64>::genesis(
+			vec![(AuthorityId::default(), 100)],
+		).unwrap();
+		let encoded = authority_set.encode();
+
+		let mut legacy = LEGACY_FORMAT_MAGIC.to_vec();
+		legacy.push(VALUE_FORMAT);
+		legacy.extend_from_slice(LEGACY_CHECKSUM_MAGIC);
+		legacy.extend_from_slice(&crc32fast::hash(&encoded).to_le_bytes());
+		legacy.extend_from_slice(&encoded);
+
+		for stored in vec![legacy, encoded.clone()] {
+			client.insert_aux(&[(AUTHORITY_SET_KEY, &stored[..])], &[]).unwrap();
+			assert_eq!(
+				load_decode_at::<_, AuthoritySet<H256, u64>>(&client, FRAMED_SINCE - 1, AUTHORITY_SET_KEY).unwrap(),
+				Some(authority_set.clone()),
+			);
+			assert!(load_decode_checked::<_, AuthoritySet<H256, u64>>(&client, AUTHORITY_SET_KEY).is_err());
+		}
+	}
+
+	#[test]
+	fn entries_are_framed_by_the_migration_to_version_8() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+		let set_state = VoterSetState::<Block>::live(
+			3,
+			&authority_set,
+			(H256::random(), 32),
+		);
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, set_state.encode().as_slice()),
+				(VERSION_KEY, 7u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let PersistentData { authority_set: loaded, set_state: loaded_state, .. } =
+			load_persistent::<Block, _, _>(
+				&client,
+				H256::random(),
+				0,
+				|| unreachable!(),
+			).unwrap();
+
+		assert_eq!(*loaded.inner().read(), authority_set);
+		assert_eq!(*loaded_state.read(), set_state);
+
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(CURRENT_VERSION));
+		assert_eq!(client.get_aux(AUTHORITY_SET_KEY).unwrap(), Some(framed(&authority_set.encode())));
+		assert_eq!(client.get_aux(SET_STATE_KEY).unwrap(), Some(framed(&set_state.encode())));
+	}
+
+	#[test]
+	fn load_with_recovery_resets_corrupted_set_state() {
+		let genesis_hash = H256::random();
+		let client = substrate_test_runtime_client::new();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, framed(&authority_set.encode()).as_slice()),
+				(SET_STATE_KEY, &[4u8, 5, 6][..]),
+				(VERSION_KEY, CURRENT_VERSION.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		assert!(load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).is_err());
+
+		let PersistentData { authority_set: loaded, set_state, .. } =
+			load_persistent_with_recovery::<substrate_test_runtime_client::runtime::Block, _>(
+				&client,
+				genesis_hash,
+				0,
+				LoadOptions::default(),
+			).unwrap();
+
+		assert_eq!(*loaded.inner().read(), authority_set);
+
+		match &*set_state.read() {
+			VoterSetState::Live { completed_rounds, current_rounds } => {
+				assert_eq!(completed_rounds.set_info().0, 3);
+				assert_eq!(completed_rounds.last().number, 0);
+				assert_eq!(completed_rounds.last().base, (genesis_hash, 0));
+				assert_eq!(current_rounds.get(&1), Some(&HasVoted::No));
+			},
+			VoterSetState::Paused { .. } => panic!("expected live voter set state"),
+		}
+
+		// the recovered state is persisted
+		assert!(load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).is_ok());
+	}
+
+	#[test]
+	fn corrupt_entry_error_carries_key_name() {
+		let genesis_hash = H256::random();
+		let client = substrate_test_runtime_client::new();
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, &[1u8, 2, 3][..]),
+				(VERSION_KEY, CURRENT_VERSION.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		match load_decode::<_, AuthoritySet<H256, u64>>(&client, AUTHORITY_SET_KEY) {
+			Err(GrandpaAuxError::CorruptEntry { key, .. }) => assert_eq!(key, "grandpa_voters"),
+			other => panic!("expected corrupt entry error, got {:?}", other),
+		}
+
+		match load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		) {
+			Err(ClientError::Backend(msg)) => assert!(msg.contains("grandpa_voters")),
+			Err(other) => panic!("expected backend error, got {:?}", other),
+			Ok(_) => panic!("expected backend error"),
+		}
+
+		client.insert_aux(&[(VERSION_KEY, 99u32.encode().as_slice())], &[]).unwrap();
+
+		match load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		) {
+			Err(ClientError::Backend(msg)) =>
+				assert_eq!(msg, GrandpaAuxError::UnsupportedVersion(99).to_string()),
+			Err(other) => panic!("expected backend error, got {:?}", other),
+			Ok(_) => panic!("expected backend error"),
+		}
+	}
+
+	#[test]
+	fn migration_backup_is_removed_or_restored() {
+		let genesis_hash = H256::random();
+		let client = substrate_test_runtime_client::new();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let round_state = RoundState::<H256, u64> {
+			prevote_ghost: Some((H256::random(), 32)),
+			finalized: None,
+			estimate: None,
+			completable: false,
+		};
+		let voter_set_state = V1VoterSetState::Live(42, round_state);
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, voter_set_state.encode().as_slice()),
+				(VERSION_KEY, 1u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		// the backups are removed once the migration succeeded.
+		assert_eq!(client.get_aux(&backup_key(AUTHORITY_SET_KEY, 1)).unwrap(), None);
+		assert_eq!(client.get_aux(&backup_key(SET_STATE_KEY, 1)).unwrap(), None);
+		assert!(client.get_aux(BEST_FINALIZED_KEY).unwrap().is_some());
+		assert!(client.get_aux(GENESIS_KEY).unwrap().is_some());
+
+		// simulate a failed migration attempt from v1 on corrupted data
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, &[1u8, 2, 3][..]),
+				(VERSION_KEY, 1u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+		let set_state_bytes = client.get_aux(SET_STATE_KEY).unwrap();
+
+		assert!(load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).is_err());
+
+		// the entries are restored as they were before the migration, without
+		// the entries introduced after version 1.
+		assert_eq!(client.get_aux(AUTHORITY_SET_KEY).unwrap(), Some(vec![1u8, 2, 3]));
+		assert_eq!(client.get_aux(SET_STATE_KEY).unwrap(), set_state_bytes);
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(1));
+		for (key, _) in ENTRIES_SINCE {
+			assert_eq!(client.get_aux(key).unwrap(), None);
+		}
+	}
+
+	#[test]
+	fn inspect_persistent_does_not_migrate() {
+		let client = substrate_test_runtime_client::new();
+
+		let round_state = RoundState::<H256, u64> {
+			prevote_ghost: Some((H256::random(), 32)),
+			finalized: None,
+			estimate: None,
+			completable: false,
+		};
+
+		let authority_set = V0AuthoritySet::<H256, u64> {
+			current_authorities: vec![(AuthorityId::default(), 100)],
+			pending_changes: Vec::new(),
+			set_id: 3,
+		};
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, (42 as RoundNumber, round_state).encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let report = inspect_persistent::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+			.unwrap();
+
+		assert_eq!(
+			report,
+			SchemaReport {
+				detected: 0,
+				target: CURRENT_VERSION,
+				authority_set: EntryStatus::Valid,
+				set_state: EntryStatus::Valid,
+				consensus_changes: EntryStatus::Missing,
+			},
+		);
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			None,
+		);
+	}
+
+	#[test]
+	fn downgrade_restores_v2_data_format() {
+		let client = substrate_test_runtime_client::new();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let voter_set_state = VoterSetState::<substrate_test_runtime_client::runtime::Block>::live(
+			3,
+			&authority_set,
+			(H256::random(), 32),
+		);
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, V3VoterSetState::from(&voter_set_state).encode().as_slice()),
+				(VERSION_KEY, 2u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let authority_set_bytes = client.get_aux(AUTHORITY_SET_KEY).unwrap();
+		let set_state_bytes = client.get_aux(SET_STATE_KEY).unwrap();
+
+		// migrate to the current version
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+		assert!(client.get_aux(BEST_FINALIZED_KEY).unwrap().is_some());
+
+		downgrade_to_version2::<substrate_test_runtime_client::runtime::Block, _>(&client, None).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(2),
+		);
+		assert_eq!(client.get_aux(BEST_FINALIZED_KEY).unwrap(), None);
+		assert_eq!(client.get_aux(AUTHORITY_SET_KEY).unwrap(), authority_set_bytes);
+		assert_eq!(client.get_aux(SET_STATE_KEY).unwrap(), set_state_bytes);
+	}
+
+	#[test]
+	fn load_decode_from_v3_migrates_data_format() {
+		let client = substrate_test_runtime_client::new();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let round_state = RoundState::genesis((H256::random(), 32));
+		let v3_round = V3CompletedRound::<substrate_test_runtime_client::runtime::Block> {
+			number: 42,
+			base: round_state.prevote_ghost.unwrap(),
+			state: round_state.clone(),
+			votes: vec![],
+		};
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(43, HasVoted::No);
+
+		let v3_state = V3VoterSetState::Live {
+			completed_rounds: (vec![v3_round], 3, vec![AuthorityId::default()]),
+			current_rounds: current_rounds.clone(),
+		};
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, v3_state.encode().as_slice()),
+				(VERSION_KEY, 3u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let PersistentData { set_state, .. } =
+			load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+				&client,
+				H256::random(),
+				0,
+				|| unreachable!(),
+			).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+
+		assert_eq!(
+			&*set_state.read(),
+			&VoterSetState::Live {
+				completed_rounds: CompletedRounds::new(
+					CompletedRound {
+						number: 42,
+						base: round_state.prevote_ghost.unwrap(),
+						state: round_state,
+						votes: vec![],
+						completed_at: None,
+						set_id: Some(3),
+					},
+					3,
+					&authority_set,
+				),
+				current_rounds,
+			},
+		);
+	}
+
+	#[test]
+	fn load_decode_from_v4_migrates_pause_reason() {
+		let client = MockAuxStore::default();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let completed_rounds = completed_rounds_from(3, &[41, 42]);
+		let v4_state = V4VoterSetState::Paused {
+			completed_rounds: V5CompletedRounds(completed_rounds.clone()),
+		};
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, v4_state.encode().as_slice()),
+				(VERSION_KEY, 4u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let PersistentData { set_state, .. } =
+			load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+				&client,
+				H256::random(),
+				0,
+				|| unreachable!(),
+			).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+		assert_eq!(
+			&*set_state.read(),
+			&paused_since_last_round(completed_rounds, PauseReason::Unknown),
+		);
+	}
+
+	#[test]
+	fn load_decode_from_v5_migrates_round_set_ids() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		// rounds 5 and 6 of set 2 are kept as history after the handoff.
+		let state = VoterSetState::<Block>::Live {
+			completed_rounds: completed_rounds_from(2, &[5, 6]),
+			current_rounds: CurrentRounds::new(),
+		}.hand_off(3, &authority_set, (H256::random(), 10));
+
+		let v5_state = match &state {
+			VoterSetState::Live { completed_rounds, current_rounds } => V5VoterSetState::Live {
+				completed_rounds: V5CompletedRounds(completed_rounds.clone()),
+				current_rounds: current_rounds.clone(),
+			},
+			VoterSetState::Paused { .. } => unreachable!(),
+		};
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, v5_state.encode().as_slice()),
+				(VERSION_KEY, 5u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let PersistentData { set_state, .. } = load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+
+		// the set id of the history wasn't stored, only the current set's is known.
+		let set_state = set_state.read();
+		assert_eq!(
+			set_state.iter_completed_rounds()
+				.map(|round| (round.number, round.set_id))
+				.collect::<Vec<_>>(),
+			vec![(5, None), (6, None), (0, Some(3))],
+		);
+	}
+
+	#[test]
+	fn set_id_of_completed_rounds_is_looked_up() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let state = VoterSetState::<Block>::Live {
+			completed_rounds: completed_rounds_from(2, &[5, 6]),
+			current_rounds: CurrentRounds::new(),
+		}.hand_off(3, &authority_set, (H256::random(), 10));
+
+		let completed_rounds = state.completed_rounds();
+		assert_eq!(completed_rounds.round_set_id(0), Some(3));
+		assert_eq!(completed_rounds.round_set_id(6), Some(2));
+		assert_eq!(completed_rounds.round_set_id(7), None);
+
+		// the set ids survive writing the voter set state.
+		let client = MockAuxStore::default();
+		write_voter_set_state(&client, &state).unwrap();
+		assert_eq!(
+			load_decode_checked::<_, VoterSetState<Block>>(&client, SET_STATE_KEY).unwrap().as_ref(),
+			Some(&state),
+		);
+	}
+
+	#[test]
+	fn paused_voter_set_state_keeps_reason() {
+		let client = MockAuxStore::default();
+
+		let paused = VoterSetState::paused(
+			completed_rounds_from(0, &[5]),
+			PauseReason::ForcedChange,
+			5,
+		);
+
+		write_voter_set_state(&client, &paused).unwrap();
+
+		match load_decode_checked::<_, VoterSetState<substrate_test_runtime_client::runtime::Block>>(
+			&client,
+			SET_STATE_KEY,
+		).unwrap() {
+			Some(VoterSetState::Paused { reason, .. }) => assert_eq!(reason, PauseReason::ForcedChange),
+			other => panic!("expected paused voter set state, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn resuming_paused_voter_set_keeps_completed_rounds() {
+		let client = MockAuxStore::default();
+
+		let completed_rounds = completed_rounds_from(2, &[7, 8]);
+		let paused = VoterSetState::<substrate_test_runtime_client::runtime::Block>::paused(
+			completed_rounds.clone(),
+			PauseReason::Manual,
+			8,
+		);
+
+		let resumed = paused.resume();
+		write_voter_set_state(&client, &resumed).unwrap();
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(9, HasVoted::No);
+
+		let expected = VoterSetState::Live { completed_rounds, current_rounds };
+		assert_eq!(resumed, expected);
+		assert_eq!(
+			load_decode_checked::<_, VoterSetState<substrate_test_runtime_client::runtime::Block>>(
+				&client,
+				SET_STATE_KEY,
+			).unwrap(),
+			Some(expected.clone()),
+		);
+
+		// resuming a live voter set is a no-op
+		assert_eq!(expected.clone().resume(), expected);
+	}
+
+	#[test]
+	fn last_justification_is_cleared_on_handoff() {
+		let client = MockAuxStore::default();
+
+		assert_eq!(load_last_justification(&client).unwrap(), None);
+
+		let justification: Justification = vec![1, 2, 3, 4];
+		write_last_justification(&client, &justification).unwrap();
+		assert_eq!(load_last_justification(&client).unwrap(), Some(justification));
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			1,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let new_set = NewAuthoritySet {
+			canon_number: 10,
+			canon_hash: H256::random(),
+			set_id: 1,
+			authorities: vec![(AuthorityId::default(), 100)],
+		};
+
+		commit_grandpa_state::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			&authority_set,
+			Some(&new_set),
+			HandoffHistory::Wipe,
+			None,
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
+		).unwrap();
+
+		assert_eq!(load_last_justification(&client).unwrap(), None);
+	}
+
+	#[cfg(feature = "compressed-rounds")]
+	#[test]
+	fn compressed_completed_rounds_are_smaller() {
+		use sp_core::{ed25519, Pair};
+
+		let target_hash = H256::random();
+		let votes = (0..200u8).map(|i| {
+			let pair = ed25519::Pair::from_seed(&[i; 32]);
+			let message = finality_grandpa::Message::Prevote(finality_grandpa::Prevote {
+				target_hash,
+				target_number: 42,
+			});
+
+			finality_grandpa::SignedMessage {
+				signature: pair.sign(&message.encode()).into(),
+				id: pair.public().into(),
+				message,
+			}
+		}).collect::<Vec<_>>();
+
+		let state = RoundState::genesis((target_hash, 42));
+		let round = CompletedRound::<substrate_test_runtime_client::runtime::Block> {
+			number: 1,
+			base: state.prevote_ghost.unwrap(),
+			state,
+			votes,
+			completed_at: None,
+			set_id: None,
+		};
+		let voters = vec![AuthorityId::default()];
+
+		let plain = (vec![round.clone()], 0u64, voters.clone()).encode();
+		let completed_rounds = CompletedRounds::from_rounds(vec![round], 0, voters);
+		let compressed = completed_rounds.encode_compressed();
+
+		assert!(compressed.len() < plain.len());
+
+		// both the compressed and the legacy plain encoding decode
+		assert_eq!(CompletedRounds::decode(&mut &compressed[..]).unwrap(), completed_rounds);
+		assert_eq!(CompletedRounds::decode(&mut &plain[..]).unwrap(), completed_rounds);
+	}
+
+	#[test]
+	fn authority_stats_sum_weights() {
+		use sp_keyring::Ed25519Keyring;
+
+		let client = MockAuxStore::default();
+
+		assert_eq!(
+			authority_stats::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			None,
+		);
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![
+				(Ed25519Keyring::Alice.public().into(), 100),
+				(Ed25519Keyring::Bob.public().into(), 200),
+				(Ed25519Keyring::Charlie.public().into(), 300),
+			],
+			0,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		client.insert_aux(&[(AUTHORITY_SET_KEY, framed(&authority_set.encode()).as_slice())], &[]).unwrap();
+
+		assert_eq!(
+			authority_stats::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			Some((3, 600)),
+		);
+
+		let overflowing_set = AuthoritySet::<H256, u64>::new(
+			vec![
+				(Ed25519Keyring::Alice.public().into(), u64::max_value()),
+				(Ed25519Keyring::Bob.public().into(), 1),
+			],
+			0,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		client.insert_aux(&[(AUTHORITY_SET_KEY, framed(&overflowing_set.encode()).as_slice())], &[]).unwrap();
+
+		assert!(authority_stats::<substrate_test_runtime_client::runtime::Block, _>(&client).is_err());
+	}
+
+	#[test]
+	fn has_voted_inspection() {
+		use crate::environment::Vote;
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let propose = finality_grandpa::PrimaryPropose { target_hash: H256::random(), target_number: 10 };
+		let prevote = finality_grandpa::Prevote { target_hash: H256::random(), target_number: 11 };
+		let precommit = finality_grandpa::Precommit { target_hash: H256::random(), target_number: 12 };
+
+		let not_voted = HasVoted::<Block>::No;
+		assert!(!not_voted.has_prevoted());
+		assert!(!not_voted.has_precommitted());
+		assert_eq!(not_voted.propose_target(), None);
+
+		let proposed = HasVoted::<Block>::Yes(AuthorityId::default(), Vote::Propose(propose.clone()));
+		assert!(!proposed.has_prevoted());
+		assert!(!proposed.has_precommitted());
+		assert_eq!(proposed.propose_target(), Some((propose.target_hash, 10)));
+
+		let prevoted = HasVoted::<Block>::Yes(
+			AuthorityId::default(),
+			Vote::Prevote(None, prevote.clone()),
+		);
+		assert!(prevoted.has_prevoted());
+		assert!(!prevoted.has_precommitted());
+		assert_eq!(prevoted.propose_target(), None);
+
+		let precommitted = HasVoted::<Block>::Yes(
+			AuthorityId::default(),
+			Vote::Precommit(Some(propose.clone()), prevote, precommit),
+		);
+		assert!(precommitted.has_prevoted());
+		assert!(precommitted.has_precommitted());
+		assert_eq!(precommitted.propose_target(), Some((propose.target_hash, 10)));
+	}
+
+	#[test]
+	fn equivocations_round_trip_and_clear_on_handoff() {
+		use sp_core::{ed25519, Pair};
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		assert!(load_equivocations::<Block, _>(&client).unwrap().is_empty());
+
+		let equivocation = |seed: u8, round: RoundNumber| {
+			let pair = ed25519::Pair::from_seed(&[seed; 32]);
+			let vote = |target_number| {
+				let message = finality_grandpa::Message::Prevote(finality_grandpa::Prevote {
+					target_hash: H256::random(),
+					target_number,
+				});
+				finality_grandpa::SignedMessage {
+					signature: pair.sign(&message.encode()).into(),
+					id: pair.public().into(),
+					message,
+				}
+			};
+
+			StoredEquivocation::<Block> {
+				set_id: 0,
+				round,
+				authority: pair.public().into(),
+				first: vote(10),
+				second: vote(11),
+			}
+		};
+
+		let equivocations = vec![equivocation(1, 5), equivocation(2, 6)];
+		write_equivocations(&client, &equivocations).unwrap();
+		assert_eq!(load_equivocations::<Block, _>(&client).unwrap(), equivocations);
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			1,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let new_set = NewAuthoritySet {
+			canon_number: 10,
+			canon_hash: H256::random(),
+			set_id: 1,
+			authorities: vec![(AuthorityId::default(), 100)],
+		};
+
+		commit_grandpa_state::<Block, _, _>(
+			&client,
+			&authority_set,
+			Some(&new_set),
+			HandoffHistory::Wipe,
+			None,
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
+		).unwrap();
+
+		assert!(load_equivocations::<Block, _>(&client).unwrap().is_empty());
+	}
+
+	#[test]
+	fn migration_from_v1_without_prevote_ghost_fails() {
+		let client = MockAuxStore::default();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let round_state = RoundState::<H256, u64> {
+			prevote_ghost: None,
+			finalized: None,
+			estimate: None,
+			completable: false,
+		};
+		let voter_set_state = V1VoterSetState::Live(42, round_state);
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, voter_set_state.encode().as_slice()),
+				(VERSION_KEY, 1u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let expected = GrandpaAuxError::MissingPrevoteGhost {
+			key: "grandpa_completed_round".into(),
+			round: 42,
+		};
+
+		match load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| unreachable!(),
+		) {
+			Err(ClientError::Backend(msg)) => assert_eq!(msg, expected.to_string()),
+			Err(err) => panic!("unexpected error: {:?}", err),
+			Ok(_) => panic!("loading a round without a prevote ghost should fail"),
+		}
+	}
+
+	#[test]
+	fn genesis_for_uses_set_id_of_authority_set() {
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			7,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let set_state = VoterSetState::<substrate_test_runtime_client::runtime::Block>::genesis_for(
+			&authority_set,
+			(H256::random(), 10),
+		);
+
+		assert!(matches!(set_state, VoterSetState::Live { .. }));
+		assert_eq!(set_state.completed_rounds().set_info().0, 7);
+		assert_eq!(set_state.last_completed_round().number, 0);
+	}
+
+	#[test]
+	fn schema_keys_with_different_prefixes_do_not_overlap() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let default_keys = SchemaKeys::default();
+		let other_keys = SchemaKeys::new(&b"grandpa2_"[..]);
+
+		// the default prefix leaves the keys unchanged
+		assert_eq!(default_keys.key(AUTHORITY_SET_KEY), AUTHORITY_SET_KEY.to_vec());
+		assert_eq!(other_keys.key(AUTHORITY_SET_KEY), b"grandpa2_voters".to_vec());
+
+		let keys_written_by = |keys: &SchemaKeys| {
+			let before = client.entries.lock().keys().cloned().collect::<Vec<_>>();
+
+			let backend = PrefixedAuxStore::new(&client, keys);
+			load_persistent::<Block, _, _>(
+				&backend,
+				H256::random(),
+				0,
+				|| Ok(vec![(AuthorityId::default(), 100)]),
+			).unwrap();
+
+			update_consensus_changes(
+				&ConsensusChanges::<H256, u64>::empty(),
+				|insert| {
+					let entries = keys.entries(insert);
+					let entries = entries.iter()
+						.map(|(key, value)| (&key[..], &value[..]))
+						.collect::<Vec<_>>();
+					client.insert_aux(&entries, &[])
+				},
+			).unwrap();
+
+			client.entries.lock().keys()
+				.filter(|key| !before.contains(key))
+				.cloned()
+				.collect::<Vec<_>>()
+		};
+
+		let default_written = keys_written_by(&default_keys);
+		let other_written = keys_written_by(&other_keys);
+
+		assert!(default_written.contains(&AUTHORITY_SET_KEY.to_vec()));
+		assert!(default_written.contains(&CONSENSUS_CHANGES_KEY.to_vec()));
+		assert!(!other_written.is_empty());
+		assert!(other_written.iter().all(|key| key.starts_with(b"grandpa2_")));
+		assert!(other_written.iter().all(|key| !default_written.contains(key)));
+
+		// each instance reads back its own state
+		assert!(load_authorities::<_, H256, u64>(&PrefixedAuxStore::new(&client, &other_keys)).is_some());
+		assert_eq!(
+			load_decode::<_, u32>(&PrefixedAuxStore::new(&client, &other_keys), VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+	}
+
+	#[test]
+	fn snapshot_and_restore_round_trip() {
+		let genesis_hash = H256::random();
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+
+		let PersistentData { authority_set, set_state, .. } = load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		let taken = snapshot(&client).unwrap();
+		assert_eq!(taken.len(), schema_keys().len());
+
+		// entries which aren't stored are kept as absent
+		assert!(taken.iter().any(|(key, value)| key == LAST_JUSTIFICATION_KEY && value.is_none()));
+
+		let keys = schema_keys().to_vec();
+		client.insert_aux(&[], &keys).unwrap();
+		assert!(snapshot(&client).unwrap().iter().all(|(_, value)| value.is_none()));
+
+		restore(&client, &taken).unwrap();
+		assert_eq!(snapshot(&client).unwrap(), taken);
+		assert!(client.get_aux(LAST_JUSTIFICATION_KEY).unwrap().is_none());
+
+		let PersistentData { authority_set: restored_set, set_state: restored_state, .. } =
+			load_persistent::<Block, _, _>(
+				&client,
+				genesis_hash,
+				0,
+				|| unreachable!(),
+			).unwrap();
+
+		assert_eq!(*restored_set.inner().read(), *authority_set.inner().read());
+		assert_eq!(*restored_state.read(), *set_state.read());
+	}
+
+	#[test]
+	fn max_set_id_survives_rollback() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		assert_eq!(load_max_set_id(&client).unwrap(), None);
+
+		let write_set = |set_id| {
+			let authority_set = AuthoritySet::<H256, u64>::new(
+				vec![(AuthorityId::default(), 100)],
+				set_id,
+				ForkTree::new(),
+				Vec::new(),
+			).unwrap();
+
+			commit_grandpa_state::<Block, _, _>(
+				&client,
+				&authority_set,
+				None,
+				HandoffHistory::Wipe,
+				None,
+				None,
+				None,
+				|values| client.insert_aux(values, &[]),
+			).unwrap();
+		};
+
+		write_set(5);
+		assert_eq!(load_max_set_id(&client).unwrap(), Some(5));
+
+		// rolling back persists the lower set but keeps the max
+		write_set(3);
+		assert_eq!(load_max_set_id(&client).unwrap(), Some(5));
+		assert_eq!(current_set_id::<Block, _>(&client).unwrap(), Some(3));
+	}
+
+	#[test]
+	fn validate_persistent_reports_all_violations() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			1,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let persistent_data = |completed_rounds| PersistentData::<Block> {
+			authority_set: authority_set.clone().into(),
+			consensus_changes: Arc::new(ConsensusChanges::empty().into()),
+			set_state: VoterSetState::Live {
+				completed_rounds,
+				current_rounds: CurrentRounds::new(),
+			}.into(),
+			state_cipher: None,
+		};
+
+		assert_eq!(validate_persistent(&persistent_data(completed_rounds_from(1, &[3, 4]))), Ok(()));
+
+		assert_eq!(
+			validate_persistent(&persistent_data(completed_rounds_from(2, &[3, 4]))),
+			Err(vec!["voter set state is for set id 2 but the authority set id is 1".to_string()]),
+		);
+
+		// rounds are given from oldest to newest
+		assert_eq!(
+			validate_persistent(&persistent_data(completed_rounds_from(2, &[5, 3, 3]))),
+			Err(vec![
+				"voter set state is for set id 2 but the authority set id is 1".to_string(),
+				"completed round 3 follows completed round 5".to_string(),
+				"completed round 3 follows completed round 3".to_string(),
+			]),
+		);
+	}
+
+	#[test]
+	fn genesis_authorities_errors_are_reported() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+
+		let load = |genesis_authorities: ClientResult<AuthorityList>| {
+			match load_persistent::<Block, _, _>(&client, H256::random(), 0, || genesis_authorities) {
+				Err(err) => err.to_string(),
+				Ok(_) => panic!("loading with invalid genesis authorities should fail"),
+			}
+		};
+
+		assert!(
+			load(Err(ClientError::Backend("runtime call failed".into())))
+				.contains("failed to fetch GRANDPA genesis authorities: runtime call failed"),
+		);
+		assert!(
+			load(Ok(Vec::new()))
+				.contains("GRANDPA genesis authorities are empty or have a zero weight"),
+		);
+
+		// nothing was initialized
+		assert!(client.get_aux(AUTHORITY_SET_KEY).unwrap().is_none());
+	}
+
+	#[test]
+	fn prune_completed_rounds_below_finalized_block() {
+		// rounds finalize the block with their round number
+		let mut completed_rounds = completed_rounds_from(0, &[10, 20, 30]);
+		completed_rounds.prune_below(25);
+		assert_eq!(completed_rounds.iter().map(|round| round.number).collect::<Vec<_>>(), vec![30]);
+
+		// the latest round is kept regardless
+		let mut completed_rounds = completed_rounds_from(0, &[10, 20]);
+		completed_rounds.prune_below(25);
+		assert_eq!(completed_rounds.iter().map(|round| round.number).collect::<Vec<_>>(), vec![20]);
+	}
+
+	#[test]
+	fn loading_with_a_different_genesis_fails() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let genesis_hash = H256::random();
+		let authorities = || Ok(vec![(AuthorityId::default(), 100)]);
+
+		load_persistent::<Block, _, _>(&client, genesis_hash, 0, authorities).unwrap();
+		assert!(load_persistent::<Block, _, _>(&client, genesis_hash, 0, || unreachable!()).is_ok());
+
+		match load_persistent::<Block, _, _>(&client, H256::random(), 0, || unreachable!()) {
+			Err(err) => assert!(err.to_string().contains("GRANDPA DB belongs to a different chain")),
+			Ok(_) => panic!("loading with a different genesis should fail"),
+		}
+	}
+
+	#[test]
+	fn loading_malformed_bytes_does_not_panic() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let authorities = vec![(AuthorityId::default(), 100)];
+		let round_state = RoundState::<H256, u64> {
+			prevote_ghost: Some((H256::random(), 32)),
+			finalized: None,
+			estimate: None,
+			completable: false,
+		};
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			authorities.clone(),
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let v0_authority_set = V0AuthoritySet::<H256, u64> {
+			current_authorities: authorities.clone(),
+			set_id: 3,
+			pending_changes: Vec::new(),
+		};
+		let voter_set_state = VoterSetState::<Block>::genesis_for(&authority_set, (H256::random(), 0));
+
+		// each of these used to panic during the migration.
+		let empty_authorities = V0AuthoritySet::<H256, u64> {
+			current_authorities: Vec::new(),
+			set_id: 3,
+			pending_changes: Vec::new(),
+		};
+		let last_round = V1VoterSetState::Live(RoundNumber::max_value(), round_state.clone());
+		let no_v3_rounds = V3VoterSetState::<Block>::Live {
+			completed_rounds: (Vec::new(), 3, Vec::new()),
+			current_rounds: CurrentRounds::new(),
+		};
+		let no_rounds = VoterSetState::<Block>::Live {
+			completed_rounds: CompletedRounds::from_rounds(Vec::new(), 3, Vec::new()),
+			current_rounds: CurrentRounds::new(),
+		};
+
+		assert!(try_load_persistent_from_bytes(
+			None,
+			&empty_authorities.encode(),
+			&(0u64, round_state.clone()).encode(),
+		).is_err());
+		assert!(try_load_persistent_from_bytes(
+			Some(1),
+			&authority_set.encode(),
+			&last_round.encode(),
+		).is_ok());
+		assert!(try_load_persistent_from_bytes(
+			Some(3),
+			&authority_set.encode(),
+			&no_v3_rounds.encode(),
+		).is_err());
+		assert!(try_load_persistent_from_bytes(
+			Some(CURRENT_VERSION),
+			&framed(&authority_set.encode()),
+			&framed(&no_rounds.encode()),
+		).is_err());
+
+		// truncations of valid encodings of each version.
+		#[allow(unused_mut)]
+		let mut seeds = vec![
+			(None, v0_authority_set.encode(), (42u64, round_state.clone()).encode()),
+			(Some(1), authority_set.encode(), V1VoterSetState::Live(42, round_state).encode()),
+			(Some(3), authority_set.encode(), V3VoterSetState::from(&voter_set_state).encode()),
+			(Some(CURRENT_VERSION), framed(&authority_set.encode()), framed(&voter_set_state.encode())),
+		];
+
+		// a live voter set state with compressed completed rounds.
+		#[cfg(feature = "compressed-rounds")]
+		{
+			let mut compressed_state = vec![0u8];
+			compressed_state.extend(voter_set_state.completed_rounds().encode_compressed());
+			CurrentRounds::<Block>::new().encode_to(&mut compressed_state);
+			seeds.push((Some(CURRENT_VERSION), framed(&authority_set.encode()), framed(&compressed_state)));
+		}
+
+		for (version, authority_bytes, state_bytes) in seeds {
+			for len in 0..authority_bytes.len() {
+				let _ = try_load_persistent_from_bytes(version, &authority_bytes[..len], &state_bytes);
+			}
+			for len in 0..state_bytes.len() {
+				let _ = try_load_persistent_from_bytes(version, &authority_bytes, &state_bytes[..len]);
+			}
+		}
+	}
+
+	#[test]
+	fn migration_duration_is_recorded() {
+		let client = MockAuxStore::default();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(VERSION_KEY, 1u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let registry = Registry::new();
+		let metrics = MigrationMetrics::register(&registry).unwrap();
+
+		load_persistent_with_options::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| unreachable!(),
+			LoadOptions { migration_metrics: Some(metrics.clone()), ..Default::default() },
+		).unwrap();
+
+		let histogram = metrics.migration_duration.with_label_values(&["1"]);
+		assert_eq!(histogram.get_sample_count(), 1);
+		assert!(histogram.get_sample_sum() >= 0.0);
+
+		// only the version migrated from is recorded.
+		assert_eq!(metrics.migration_duration.with_label_values(&["0"]).get_sample_count(), 0);
+	}
+
+	#[test]
+	fn genesis_init_is_not_a_migration() {
+		let client = MockAuxStore::default();
+		let registry = Registry::new();
+		let metrics = MigrationMetrics::register(&registry).unwrap();
+
+		load_persistent_with_options::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(vec![(AuthorityId::default(), 1)]),
+			LoadOptions { migration_metrics: Some(metrics.clone()), ..Default::default() },
+		).unwrap();
+
+		assert_eq!(metrics.migration_duration.with_label_values(&["0"]).get_sample_count(), 0);
+		assert_eq!(client.get_aux(&backup_key(AUTHORITY_SET_KEY, 0)).unwrap(), None);
+		assert_eq!(client.get_aux(VERSION_KEY).unwrap(), Some(CURRENT_VERSION.encode()));
+	}
+
+	#[test]
+	fn load_voter_set_state_only_at_current_version() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		assert!(load_voter_set_state_only::<Block, _>(&client, None).unwrap().is_none());
+
+		let PersistentData { set_state, .. } = load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		assert_eq!(
+			load_voter_set_state_only::<Block, _>(&client, None).unwrap(),
+			Some(set_state.read().clone()),
+		);
+
+		// an outdated version must be migrated first.
+		client.insert_aux(&[(VERSION_KEY, 1u32.encode().as_slice())], &[]).unwrap();
+		assert!(
+			load_voter_set_state_only::<Block, _>(&client, None)
+				.unwrap_err()
+				.to_string()
+				.contains("must be migrated by loading it with `load_persistent` first"),
+		);
+	}
+
+	#[test]
+	fn participation_accumulates_and_resets_on_handoff() {
+		use sp_core::{ed25519, Pair};
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let pairs = (0..3u8).map(|i| ed25519::Pair::from_seed(&[i; 32])).collect::<Vec<_>>();
+		let id = |i: usize| -> AuthorityId { pairs[i].public().into() };
+
+		let round = |number, voters: &[usize]| {
+			let target_hash = H256::random();
+			let message = finality_grandpa::Message::Prevote(finality_grandpa::Prevote {
+				target_hash,
+				target_number: number,
+			});
+			let votes = voters.iter().map(|i| finality_grandpa::SignedMessage {
+				signature: pairs[*i].sign(&message.encode()).into(),
+				id: id(*i),
+				message: message.clone(),
+			}).collect();
+
+			let state = RoundState::genesis((target_hash, number));
+			CompletedRound::<Block> {
+				number,
+				base: state.prevote_ghost.unwrap(),
+				state,
+				votes,
+				completed_at: None,
+				set_id: None,
+			}
+		};
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(id(0), 1)],
+			1,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		assert!(load_participation(&client).unwrap().is_empty());
+
+		// the second voter votes twice in the first round.
+		let mut completed_rounds = CompletedRounds::new(round(1, &[0, 1, 1]), 1, &authority_set);
+		let write = |completed_rounds: &CompletedRounds<Block>| {
+			let state = VoterSetState::Live {
+				completed_rounds: completed_rounds.clone(),
+				current_rounds: CurrentRounds::new(),
+			};
+			let recording = RecordingAuxStore { inner: &client, writes: Default::default() };
+			write_voter_set_state_completing_round(&recording, &state, None, None).unwrap();
+
+			// the participation is written along with the voter set state.
+			let writes = recording.writes.into_inner();
+			assert_eq!(writes.len(), 1);
+			assert!(writes[0].contains(&SET_STATE_KEY.to_vec()));
+			assert!(writes[0].contains(&PARTICIPATION_KEY.to_vec()));
+		};
+
+		write(&completed_rounds);
+		completed_rounds.push(round(2, &[1, 2]));
+		write(&completed_rounds);
+
+		let participation = load_participation(&client).unwrap();
+		assert_eq!(participation.get(&id(0)), Some(&1));
+		assert_eq!(participation.get(&id(1)), Some(&2));
+		assert_eq!(participation.get(&id(2)), Some(&1));
+		let new_set = NewAuthoritySet {
+			canon_hash: H256::random(),
+			canon_number: 10,
+			set_id: 1,
+			authorities: vec![(id(0), 1)],
+		};
+
+		commit_grandpa_state::<Block, _, _>(
+			&client,
+			&authority_set,
+			Some(&new_set),
+			HandoffHistory::Wipe,
+			None,
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
+		).unwrap();
+
+		assert!(load_participation(&client).unwrap().is_empty());
+	}
+
+	#[test]
+	fn duplicate_consensus_changes_are_dropped() {
+		let client = MockAuxStore::default();
+		let change = (10u64, H256::random());
+
+		let mut consensus_changes = ConsensusChanges::<H256, u64>::empty();
+		assert!(consensus_changes.note_change(change));
+		assert!(!consensus_changes.note_change(change));
+
+		update_consensus_changes(&consensus_changes, |values| client.insert_aux(values, &[])).unwrap();
+		assert_eq!(
+			load_consensus_changes::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+				.unwrap()
+				.pending_changes(),
+			&[change],
+		);
+
+		// duplicates stored by an older node are dropped on the next write.
+		let other = (5u64, H256::random());
+		let stored = vec![other, other, change, change].encode();
+		let consensus_changes = ConsensusChanges::<H256, u64>::decode(&mut &stored[..]).unwrap();
+
+		update_consensus_changes(&consensus_changes, |values| client.insert_aux(values, &[])).unwrap();
+		assert_eq!(
+			load_consensus_changes::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+				.unwrap()
+				.pending_changes(),
+			&[other, change],
+		);
+	}
+
+	#[test]
+	fn last_handoff_is_recorded() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+
+		let PersistentData { authority_set, .. } = load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		assert_eq!(load_last_handoff::<Block, _>(&client).unwrap(), None);
+
+		let new_set = NewAuthoritySet {
+			canon_number: 10,
+			canon_hash: H256::random(),
+			set_id: 1,
+			authorities: vec![(AuthorityId::default(), 100)],
+		};
+
+		commit_grandpa_state::<Block, _, _>(
+			&client,
+			&*authority_set.inner().read(),
+			Some(&new_set),
+			HandoffHistory::Wipe,
+			None,
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
+		).unwrap();
+
+		assert_eq!(
+			load_last_handoff::<Block, _>(&client).unwrap(),
+			Some((1, new_set.canon_hash, 10)),
+		);
+	}
+
+	#[test]
+	fn authority_weights_are_updated_in_place() {
+		use sp_keyring::Ed25519Keyring;
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let alice: AuthorityId = Ed25519Keyring::Alice.public().into();
+		let bob: AuthorityId = Ed25519Keyring::Bob.public().into();
+
+		load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(vec![(alice.clone(), 100), (bob.clone(), 100)]),
+		).unwrap();
+
+		let set_state = client.get_aux(SET_STATE_KEY).unwrap();
+
+		let authorities = update_authority_weights::<Block, _>(&client, &[(bob.clone(), 300)]).unwrap();
+		assert_eq!(authorities, vec![(alice.clone(), 100), (bob.clone(), 300)]);
+
+		let set = load_authorities::<_, H256, u64>(&client).unwrap();
+		assert_eq!(set.authorities(), &authorities[..]);
+		assert_eq!(set.set_id(), 0);
+		assert_eq!(client.get_aux(SET_STATE_KEY).unwrap(), set_state);
+
+		// unknown authorities and zero weights are rejected without writing.
+		let stored = client.get_aux(AUTHORITY_SET_KEY).unwrap();
+		let charlie: AuthorityId = Ed25519Keyring::Charlie.public().into();
+
+		assert!(
+			update_authority_weights::<Block, _>(&client, &[(alice.clone(), 200), (charlie, 100)])
+				.unwrap_err()
+				.to_string()
+				.contains("not in the current authority set"),
+		);
+		assert!(
+			update_authority_weights::<Block, _>(&client, &[(alice, 0)])
+				.unwrap_err()
+				.to_string()
+				.contains("weight must not be zero"),
+		);
+		assert_eq!(client.get_aux(AUTHORITY_SET_KEY).unwrap(), stored);
+	}
+
+	#[test]
+	fn write_voter_set_state_returns_previous_state() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let authority_set = AuthoritySet::<H256, u64>::genesis(
+			vec![(AuthorityId::default(), 100)],
+		).unwrap();
+
+		let first = VoterSetState::<Block>::genesis_for(&authority_set, (H256::random(), 0));
+		let second = VoterSetState::<Block>::live(1, &authority_set, (H256::random(), 10));
+
+		assert_eq!(write_voter_set_state_returning(&client, &first, None).unwrap(), None);
+		assert_eq!(write_voter_set_state_returning(&client, &second, None).unwrap(), Some(first));
+		assert_eq!(write_voter_set_state_returning(&client, &second, None).unwrap(), Some(second));
+
+		// a corrupted stored state isn't silently overwritten.
+		client.insert_aux(&[(SET_STATE_KEY, &b"garbage"[..])], &[]).unwrap();
+		let third = VoterSetState::<Block>::genesis_for(&authority_set, (H256::random(), 0));
+		assert!(write_voter_set_state_returning(&client, &third, None).is_err());
+		assert!(write_voter_set_state(&client, &third).is_err());
+		assert_eq!(client.get_aux(SET_STATE_KEY).unwrap(), Some(b"garbage".to_vec()));
+
+		// an encrypted stored state is decrypted with the given cipher.
+		client.insert_aux(&[(SET_STATE_KEY, &encoded_set_state(&third, Some(&XorCipher(7)))[..])], &[]).unwrap();
+		let fourth = VoterSetState::<Block>::live(2, &authority_set, (H256::random(), 20));
+		assert!(write_voter_set_state(&client, &fourth).is_err());
+		assert_eq!(
+			write_voter_set_state_returning(&client, &fourth, Some(&XorCipher(7))).unwrap(),
+			Some(third),
+		);
+	}
+
+	#[test]
+	fn set_id_going_backwards_is_guarded() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let authority_set = |set_id| AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			set_id,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let write = |set_id, guard| {
+			let set = authority_set(set_id);
+			check_set_id_guard::<Block, _>(&client, &set, guard)?;
+			commit_grandpa_state::<Block, _, _>(
+				&client,
+				&set,
+				None,
+				HandoffHistory::Wipe,
+				None,
+				None,
+				None,
+				|values| client.insert_aux(values, &[]),
+			)
+		};
+
+		write(2, SetIdGuard::Strict).unwrap();
+		write(3, SetIdGuard::Strict).unwrap();
+
+		assert!(
+			write(1, SetIdGuard::Strict)
+				.unwrap_err()
+				.to_string()
+				.contains("GRANDPA set id would go backwards from 3 to 1"),
+		);
+		assert_eq!(current_set_id::<Block, _>(&client).unwrap(), Some(3));
+
+		write(2, SetIdGuard::Warn).unwrap();
+		assert_eq!(current_set_id::<Block, _>(&client).unwrap(), Some(2));
+
+		write(1, SetIdGuard::Bypass).unwrap();
+		assert_eq!(current_set_id::<Block, _>(&client).unwrap(), Some(1));
+	}
+
+	#[test]
+	fn unreadable_completed_rounds_are_skipped_when_opted_in() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let round = |number: u64| {
+			let state = RoundState::genesis((H256::repeat_byte(number as u8), number));
+			V3CompletedRound::<Block> {
+				number,
+				base: state.prevote_ghost.unwrap(),
+				state,
+				votes: Vec::new(),
+			}
+		};
+
+		// the prevote ghost of the middle round has an invalid option discriminant.
+		let mut corrupt_round = round(2).encode();
+		corrupt_round[8] = 2;
+
+		let mut current_rounds = CurrentRounds::<Block>::new();
+		current_rounds.insert(4, HasVoted::No);
+
+		let mut stored = vec![0u8];
+		Compact(3u32).encode_to(&mut stored);
+		round(3).encode_to(&mut stored);
+		stored.extend(corrupt_round);
+		round(1).encode_to(&mut stored);
+		(3u64, vec![AuthorityId::default()]).encode_to(&mut stored);
+		current_rounds.encode_to(&mut stored);
+
+		let client = MockAuxStore::default();
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, &stored[..]),
+				(VERSION_KEY, 2u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let genesis_hash = H256::random();
+		assert!(load_persistent::<Block, _, _>(&client, genesis_hash, 0, || unreachable!()).is_err());
+
+		restore_from_backup(&client, 2).unwrap();
+
+		let PersistentData { set_state, .. } = load_persistent_with_options::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+			LoadOptions { skip_unreadable_rounds: true, ..Default::default() },
+		).unwrap();
+
+		let set_state = set_state.read();
+		assert_eq!(
+			set_state.iter_completed_rounds().map(|round| round.number).collect::<Vec<_>>(),
+			vec![1, 3],
+		);
+		assert_eq!(set_state.completed_rounds().set_info(), (3, &[AuthorityId::default()][..]));
+
+		match &*set_state {
+			VoterSetState::Live { current_rounds, .. } => assert_eq!(current_rounds.get(&4), Some(&HasVoted::No)),
+			VoterSetState::Paused { .. } => panic!("expected live voter set state"),
+		}
+	}
+
+	#[test]
+	fn caching_aux_store_reads_backend_once() {
+		let client = MockAuxStore::default();
+		client.insert_aux(&[(VERSION_KEY, &[1u8][..])], &[]).unwrap();
+
+		let cache = CachingAuxStore::new(&client);
+		assert_eq!(cache.get_aux(VERSION_KEY).unwrap(), Some(vec![1]));
+		assert_eq!(cache.get_aux(VERSION_KEY).unwrap(), Some(vec![1]));
+		assert_eq!(*client.reads.lock(), 1);
+
+		// absent entries are cached as well.
+		assert_eq!(cache.get_aux(AUTHORITY_SET_KEY).unwrap(), None);
+		assert_eq!(cache.get_aux(AUTHORITY_SET_KEY).unwrap(), None);
+		assert_eq!(*client.reads.lock(), 2);
+
+		cache.insert_aux(&[(AUTHORITY_SET_KEY, &[2u8][..])], &[]).unwrap();
+		assert_eq!(cache.get_aux(AUTHORITY_SET_KEY).unwrap(), Some(vec![2]));
+
+		cache.insert_aux(&[], &[VERSION_KEY]).unwrap();
+		assert_eq!(cache.get_aux(VERSION_KEY).unwrap(), None);
+		assert_eq!(*client.reads.lock(), 4);
+	}
+
+	#[test]
+	fn summary_of_genesis_data() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+
+		let persistent_data = load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		let summary = persistent_data.summary();
+		assert_eq!(summary, PersistentSummary {
+			set_id: 0,
+			authority_count: 1,
+			last_round_number: 0,
+			is_paused: false,
+		});
+		assert_eq!(summary.to_string(), "set_id=0 authorities=1 last_round=0 paused=false");
+	}
+
+	#[test]
+	fn migrating_a_read_only_backend_fails_early() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let backend = MockAuxStore::default();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let voter_set_state = V1VoterSetState::Live(42, RoundState::<H256, u64>::genesis((H256::random(), 32)));
+
+		backend.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, voter_set_state.encode().as_slice()),
+				(VERSION_KEY, 1u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		backend.make_read_only();
+
+		let err = load_persistent::<Block, _, _>(
+			&backend,
+			H256::random(),
+			0,
+			|| unreachable!(),
+		).err().unwrap();
+
+		assert_eq!(
+			err.to_string(),
+			ClientError::from(GrandpaAuxError::ReadOnlyBackend(1)).to_string(),
+		);
+
+		// nothing was written, not even a backup.
+		assert_eq!(backend.get_aux(&backup_key(AUTHORITY_SET_KEY, 1)).unwrap(), None);
+		assert_eq!(load_decode::<_, u32>(&backend, VERSION_KEY).unwrap(), Some(1));
+	}
+
+	fn assert_round_trip<T: Encode + Decode + PartialEq + std::fmt::Debug>(value: &T) {
+		assert_eq!(T::decode(&mut &value.encode()[..]).ok().as_ref(), Some(value));
+	}
+
+	#[test]
+	fn schema_types_round_trip() {
+		use sp_core::{ed25519, Pair};
+		use crate::environment::Vote;
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let pending_change = |canon_height, delay_kind| PendingChange {
+			next_authorities: vec![(AuthorityId::default(), 5)],
+			delay: 10,
+			canon_height,
+			canon_hash: H256::random(),
+			delay_kind,
+		};
+
+		let standard_change = pending_change(20, DelayKind::Finalized);
+		let forced_change = pending_change(30, DelayKind::Best { median_last_finalized: 15 });
+		assert_round_trip(&standard_change);
+		assert_round_trip(&forced_change);
+
+		let mut pending_standard_changes = ForkTree::new();
+		pending_standard_changes.import(
+			standard_change.canon_hash,
+			standard_change.canon_height,
+			standard_change.clone(),
+			&|_: &H256, _: &H256| Ok::<_, ClientError>(false),
+		).unwrap();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			1,
+			pending_standard_changes,
+			vec![forced_change],
+		).unwrap();
+		assert_round_trip(&authority_set);
+
+		let mut consensus_changes = ConsensusChanges::<H256, u64>::empty();
+		consensus_changes.note_change((10, H256::random()));
+		consensus_changes.note_change((20, H256::random()));
+		assert_eq!(
+			ConsensusChanges::<H256, u64>::decode(&mut &consensus_changes.encode()[..])
+				.unwrap()
+				.pending_changes(),
+			consensus_changes.pending_changes(),
+		);
+
+		let pair = ed25519::Pair::from_seed(&[1; 32]);
+		let message = finality_grandpa::Message::Prevote(finality_grandpa::Prevote {
+			target_hash: H256::random(),
+			target_number: 32,
+		});
+		let round_state = RoundState::genesis((H256::random(), 32));
+		let completed_round = CompletedRound::<Block> {
+			number: 42,
+			base: round_state.prevote_ghost.unwrap(),
+			state: round_state,
+			votes: vec![finality_grandpa::SignedMessage {
+				signature: pair.sign(&message.encode()).into(),
+				id: pair.public().into(),
+				message,
+			}],
+			completed_at: Some(1_000),
+			set_id: Some(3),
+		};
+		assert_round_trip(&completed_round);
+
+		let completed_rounds = CompletedRounds::new(completed_round, 1, &authority_set);
+		assert_round_trip(&completed_rounds);
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(43, HasVoted::Yes(
+			AuthorityId::default(),
+			Vote::Prevote(None, finality_grandpa::Prevote { target_hash: H256::random(), target_number: 33 }),
+		));
+		assert_round_trip(&VoterSetState::<Block>::Live {
+			completed_rounds: completed_rounds.clone(),
+			current_rounds,
+		});
+		assert_round_trip(&VoterSetState::<Block>::paused(
+			completed_rounds,
+			PauseReason::Manual,
+			0,
+		));
+	}
+
+	#[test]
+	fn finalized_consensus_changes_are_pruned() {
+		let client = MockAuxStore::default();
+		let first = (10u64, H256::random());
+		let second = (20u64, H256::random());
+
+		let mut consensus_changes = ConsensusChanges::<H256, u64>::empty();
+		consensus_changes.note_change(first);
+		consensus_changes.note_change(second);
+
+		assert!(consensus_changes.prune_finalized(15));
+		assert!(!consensus_changes.prune_finalized(15));
+
+		update_consensus_changes(&consensus_changes, |values| client.insert_aux(values, &[])).unwrap();
+		assert_eq!(
+			load_consensus_changes::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+				.unwrap()
+				.pending_changes(),
+			&[second],
+		);
+
+		// a change exactly at the finalized height is settled.
+		assert!(consensus_changes.prune_finalized(20));
+		assert!(consensus_changes.pending_changes().is_empty());
+	}
+
+	#[test]
+	fn completed_rounds_of_live_and_paused_states() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let authority_set = AuthoritySet::<H256, u64>::genesis(
+			vec![(AuthorityId::default(), 100)],
+		).unwrap();
+		let live = VoterSetState::<Block>::live(0, &authority_set, (H256::random(), 0));
+		let paused = VoterSetState::<Block>::paused(
+			live.completed_rounds().clone(),
+			PauseReason::Manual,
+			0,
+		);
+
+		assert_eq!(live.completed_rounds(), paused.completed_rounds());
+		assert!(!live.is_paused());
+		assert!(paused.is_paused());
+	}
+
+	#[test]
+	fn storage_footprint_uses_stored_lengths() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		assert!(storage_footprint(&client).unwrap().values().all(|len| *len == 0));
+
+		load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		let footprint = storage_footprint(&client).unwrap();
+		assert_eq!(footprint.len(), schema_keys().len());
+		assert!(footprint["grandpa_voters"] > 0);
+		assert!(footprint["grandpa_completed_round"] > 0);
+		assert_eq!(footprint["grandpa_consensus_changes"], 0);
+		assert_eq!(footprint["grandpa_equivocations"], 0);
+
+		for key in schema_keys() {
+			assert_eq!(
+				footprint[std::str::from_utf8(key).unwrap()],
+				client.get_aux(key).unwrap().map_or(0, |value| value.len()),
+			);
+		}
+	}
+
+	#[test]
+	fn loading_a_future_version_is_unsupported() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, AuthoritySet::<H256, u64>::genesis(
+					vec![(AuthorityId::default(), 100)],
+				).unwrap().encode().as_slice()),
+				(VERSION_KEY, 100u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let err = load_persistent_with_version::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| unreachable!(),
+			99,
+		).err().unwrap();
+
+		assert_eq!(
+			err.to_string(),
+			ClientError::from(GrandpaAuxError::UnsupportedVersion(100)).to_string(),
+		);
+		assert!(err.to_string().starts_with("Unsupported GRANDPA DB version"));
+
+		// nothing was migrated.
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(100));
+	}
+
+	#[test]
+	fn loading_invalid_authority_weights_fails() {
+		use sp_keyring::Ed25519Keyring;
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let load_with_weights = |weights: &[u64]| {
+			let client = MockAuxStore::default();
+			let mut authority_set = AuthoritySet::<H256, u64>::genesis(
+				vec![(AuthorityId::default(), 1)],
+			).unwrap();
+			authority_set.current_authorities = vec![
+				(Ed25519Keyring::Alice.public().into(), weights[0]),
+				(Ed25519Keyring::Bob.public().into(), weights[1]),
+			];
+
+			commit_grandpa_state::<Block, _, _>(
+				&client,
+				&authority_set,
+				None,
+				HandoffHistory::Wipe,
+				None,
+				None,
+				None,
+				|values| client.insert_aux(values, &[]),
+			).unwrap();
+			client.insert_aux(&[(VERSION_KEY, CURRENT_VERSION.encode().as_slice())], &[]).unwrap();
+
+			load_persistent::<Block, _, _>(
+				&client,
+				H256::random(),
+				0,
+				|| unreachable!(),
+			).err().map(|err| err.to_string())
+		};
+
+		assert_eq!(load_with_weights(&[1, 1]), None);
+		assert_eq!(
+			load_with_weights(&[1, 0]),
+			Some(ClientError::from(GrandpaAuxError::ZeroWeight(
+				format!("{:?}", AuthorityId::from(Ed25519Keyring::Bob.public())),
+			)).to_string()),
+		);
+		assert_eq!(
+			load_with_weights(&[u64::max_value(), 1]),
+			Some(ClientError::from(GrandpaAuxError::WeightOverflow).to_string()),
+		);
+	}
+
+	#[test]
+	fn handoffs_are_logged_in_order() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let authorities = vec![(AuthorityId::default(), 100)];
+		assert!(load_handoff_log::<Block, _>(&client).unwrap().is_empty());
+
+		let mut handoffs = Vec::new();
+		for set_id in 1..4 {
+			let new_set = NewAuthoritySet {
+				canon_number: set_id * 10,
+				canon_hash: H256::random(),
+				set_id,
+				authorities: authorities.clone(),
+			};
+			let authority_set = AuthoritySet::<H256, u64>::new(
+				authorities.clone(),
+				set_id,
+				ForkTree::new(),
+				Vec::new(),
+			).unwrap();
+
+			commit_grandpa_state::<Block, _, _>(
+				&client,
+				&authority_set,
+				Some(&new_set),
+				HandoffHistory::Wipe,
+				None,
+				None,
+				None,
+				|values| client.insert_aux(values, &[]),
+			).unwrap();
+
+			handoffs.push((set_id, new_set.canon_hash, set_id * 10));
+		}
+
+		assert_eq!(load_handoff_log::<Block, _>(&client).unwrap(), handoffs);
+
+		// writes without a handoff leave the log alone.
+		commit_grandpa_state::<Block, _, _>(
+			&client,
+			&AuthoritySet::<H256, u64>::new(authorities, 3, ForkTree::new(), Vec::new()).unwrap(),
+			None,
+			HandoffHistory::Wipe,
+			None,
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
+		).unwrap();
+		assert_eq!(load_handoff_log::<Block, _>(&client).unwrap(), handoffs);
+
+		// the oldest handoffs are dropped beyond the limit.
+		let handoff = (4, H256::random(), 40);
+		let handoff_log = appended_handoff_log::<Block, _>(&client, handoff, 2).unwrap();
+		assert_eq!(
+			Vec::<(SetId, H256, u64)>::decode(&mut &handoff_log[..]).unwrap(),
+			vec![handoffs[2], handoff],
+		);
+	}
+
+	#[test]
+	fn last_round_state_is_of_the_newest_completed_round() {
+		let completed_rounds = completed_rounds_from(0, &[5, 6, 7]);
+		let round_7 = completed_rounds.last().state.clone();
+		assert_eq!(completed_rounds.last().number, 7);
+
+		let live = VoterSetState::Live {
+			completed_rounds: completed_rounds.clone(),
+			current_rounds: CurrentRounds::new(),
+		};
+		let paused = VoterSetState::paused(
+			completed_rounds,
+			PauseReason::Stalled,
+			7,
+		);
+
+		assert_eq!(live.last_round_state(), &round_7);
+		assert_eq!(paused.last_round_state(), &round_7);
+	}
+
+	#[test]
+	fn current_base_is_of_the_newest_completed_round() {
+		let completed_rounds = completed_rounds_from(0, &[5, 6, 7]);
+		let round_7 = completed_rounds.last().base;
+
+		let live = VoterSetState::Live {
+			completed_rounds: completed_rounds.clone(),
+			current_rounds: CurrentRounds::new(),
+		};
+		let paused = VoterSetState::paused(
+			completed_rounds,
+			PauseReason::Stalled,
+			7,
+		);
+
+		assert_eq!(live.current_base(), round_7);
+		assert_eq!(paused.current_base(), round_7);
+	}
+
+	#[test]
+	fn interrupted_migration_is_rerun_cleanly() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let genesis_hash = H256::random();
+		let client = MockAuxStore::default();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+		let round_state = RoundState::<H256, u64>::genesis((H256::random(), 32));
+		let v1_state = V1VoterSetState::Live(42, round_state.clone()).encode();
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, v1_state.as_slice()),
+				(VERSION_KEY, 1u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		// crash after the backups are written, while writing the migrated state.
+		client.fail_writes_to(SET_STATE_KEY);
+		assert!(load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).is_err());
+
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(1));
+		assert_eq!(client.get_aux(SET_STATE_KEY).unwrap(), Some(v1_state));
+
+		*client.failing_key.lock() = None;
+		let PersistentData { set_state, .. } = load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(CURRENT_VERSION));
+		assert_eq!(set_state.read().last_completed_round().number, 42);
+		assert_eq!(set_state.read().last_round_state(), &round_state);
+	}
+
+	#[cfg(feature = "test-helpers")]
+	#[test]
+	fn completed_rounds_built_for_tests_round_trip() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let round = |number| {
+			let state = RoundState::genesis((H256::random(), number));
+			CompletedRound::<Block>::new_for_test(number, state.clone(), state.prevote_ghost.unwrap(), vec![])
+		};
+		let authorities = vec![(AuthorityId::default(), 100)];
+
+		assert!(CompletedRounds::<Block>::from_rounds_for_test(1, &authorities, vec![]).is_err());
+
+		let completed_rounds = CompletedRounds::<Block>::from_rounds_for_test(
+			1,
+			&authorities,
+			vec![round(2), round(1)],
+		).unwrap();
+		assert_eq!(completed_rounds.last_number(), 2);
+		assert_eq!(completed_rounds.set_info(), (1, &[AuthorityId::default()][..]));
+
+		let decoded = CompletedRounds::<Block>::decode(&mut &completed_rounds.encode()[..]).unwrap();
+		assert_eq!(decoded, completed_rounds);
+	}
+
+	#[test]
+	fn set_id_mismatch_is_detected_on_load() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let genesis_hash = H256::random();
+		let client = MockAuxStore::default();
+
+		let PersistentData { authority_set, .. } = load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		// a voter set state left over from set 2 after a botched recovery.
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(8, HasVoted::No);
+		write_voter_set_state(&client, &VoterSetState::<Block>::Live {
+			completed_rounds: completed_rounds_from(2, &[6, 7]),
+			current_rounds,
+		}).unwrap();
+
+		let err = load_persistent_with_options::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+			LoadOptions { strict: true, ..Default::default() },
+		).err().unwrap();
+		assert_eq!(
+			err.to_string(),
+			ClientError::from(GrandpaAuxError::SetIdMismatch { set_state: 2, authority_set: 0 }).to_string(),
+		);
+
+		let PersistentData { set_state, .. } = load_persistent_with_options::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+			LoadOptions { recover_set_state: true, ..Default::default() },
+		).unwrap();
+
+		let expected_voters = authority_set.inner().read().authorities().iter()
+			.map(|(id, _)| id.clone())
+			.collect::<Vec<_>>();
+		let assert_reassigned = |set_state: &VoterSetState<Block>| {
+			assert_eq!(set_state.completed_rounds().set_info(), (0, &expected_voters[..]));
+			assert_eq!(
+				set_state.iter_completed_rounds().map(|round| round.number).collect::<Vec<_>>(),
+				vec![6, 7],
+			);
+		};
+
+		assert_reassigned(&*set_state.read());
+		assert_reassigned(
+			&load_decode_checked::<_, VoterSetState<Block>>(&client, SET_STATE_KEY).unwrap().unwrap(),
+		);
+	}
+
+	#[test]
+	fn voter_set_state_diffs_apply_to_their_base() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let live = |completed_rounds, next_round| {
+			let mut current_rounds = CurrentRounds::new();
+			current_rounds.insert(next_round, HasVoted::No);
+			VoterSetState::<Block>::Live { completed_rounds, current_rounds }
+		};
+
+		let completed_rounds = completed_rounds_from(0, &[1, 2]);
+		let old = live(completed_rounds.clone(), 3);
+
+		let mut new_rounds = completed_rounds;
+		let state = RoundState::genesis((H256::random(), 3));
+		new_rounds.push(CompletedRound {
+			number: 3,
+			base: state.prevote_ghost.unwrap(),
+			state,
+			votes: vec![],
+			completed_at: Some(1_000),
+			set_id: Some(0),
+		});
+		let new = live(new_rounds, 4);
+
+		let diff = diff_voter_set_state(&old, &new).unwrap();
+		assert_eq!(diff.appended.len(), 1);
+		let encoded_diff = diff.encode();
+		assert!(encoded_diff.len() < new.encode().len());
+
+		let decoded = StateDiff::<Block>::decode(&mut &encoded_diff[..]).unwrap();
+		assert_eq!(apply_diff(&old, &decoded).unwrap(), new);
+
+		// a state whose last completed round isn't the base is rejected.
+		let other = live(completed_rounds_from(0, &[1]), 2);
+		assert_eq!(
+			apply_diff(&other, &diff).unwrap_err().to_string(),
+			ClientError::from(GrandpaAuxError::InvalidDiff("base round mismatch")).to_string(),
+		);
+
+		// states of different authority sets can't be diffed.
+		assert!(diff_voter_set_state(&live(completed_rounds_from(1, &[1, 2]), 3), &new).is_none());
+	}
+
+	#[test]
+	fn migration_pending_is_reported_without_writing() {
+		let client = substrate_test_runtime_client::new();
+		let recording = RecordingAuxStore { inner: &client, writes: Default::default() };
+
+		// a fresh database is initialized, not migrated
+		assert!(!migration_pending(&recording).unwrap());
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, AuthoritySet::<H256, u64>::genesis(
+					vec![(AuthorityId::default(), 100)],
+				).unwrap().encode().as_slice()),
+				(VERSION_KEY, 1u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+		assert!(migration_pending(&recording).unwrap());
+
+		client.insert_aux(&[(VERSION_KEY, CURRENT_VERSION.encode().as_slice())], &[]).unwrap();
+		assert!(!migration_pending(&recording).unwrap());
+
+		// the unversioned schema predates version 1
+		client.insert_aux(&[], &[VERSION_KEY]).unwrap();
+		assert!(migration_pending(&recording).unwrap());
+
+		assert!(recording.writes.borrow().is_empty());
+	}
+
+	#[test]
+	fn encrypted_voter_set_state_is_loaded_with_its_key() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let genesis_hash = H256::random();
+		let client = MockAuxStore::default();
+
+		load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(3, HasVoted::No);
+		let state = VoterSetState::<Block>::Live {
+			completed_rounds: completed_rounds_from(0, &[1, 2]),
+			current_rounds,
+		};
+		write_voter_set_state_encrypted(&client, &state, Some(&XorCipher(7))).unwrap();
+
+		let stored = client.get_aux(SET_STATE_KEY).unwrap().unwrap();
+		assert!(stored.starts_with(ENCRYPTED_MAGIC));
+		assert_ne!(stored[ENCRYPTED_MAGIC.len() + 1..], framed(&state.encode())[..]);
+
+		let load = |state_cipher: Option<Arc<dyn StateCipher>>| load_persistent_with_options::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+			LoadOptions { state_cipher, ..Default::default() },
+		);
+
+		let PersistentData { set_state, .. } = load(Some(Arc::new(XorCipher(7)))).unwrap();
+		assert_eq!(*set_state.read(), state);
+
+		let assert_undecryptable = |state_cipher: Option<Arc<dyn StateCipher>>, reason| assert_eq!(
+			load(state_cipher).err().unwrap().to_string(),
+			ClientError::from(GrandpaAuxError::UndecryptableState(reason)).to_string(),
+		);
+		assert_undecryptable(Some(Arc::new(XorCipher(8))), "wrong key or tampered ciphertext");
+		assert_undecryptable(None, "no cipher configured");
+
+		// plaintext states are still loaded while rolling out encryption.
+		write_voter_set_state_encrypted(&client, &state, None).unwrap();
+		let PersistentData { set_state, .. } = load(Some(Arc::new(XorCipher(7)))).unwrap();
+		assert_eq!(*set_state.read(), state);
+	}
+
+	#[test]
+	fn encrypted_voter_set_state_is_migrated_with_its_key() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let genesis_hash = H256::random();
+		let client = MockAuxStore::default();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(3, HasVoted::No);
+		let v5_state = V5VoterSetState::<Block>::Live {
+			completed_rounds: V5CompletedRounds(completed_rounds_from(3, &[1, 2])),
+			current_rounds,
+		}.encode();
+		let state: VoterSetState<Block> = V5VoterSetState::<Block>::decode(&mut &v5_state[..])
+			.unwrap()
+			.into();
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, encrypted(v5_state, Some(&XorCipher(7))).as_slice()),
+				(VERSION_KEY, 5u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let load = |state_cipher: Option<Arc<dyn StateCipher>>| load_persistent_with_options::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+			LoadOptions { state_cipher, ..Default::default() },
+		);
+
+		// the migration is retried once the key is configured.
+		assert!(load(None).is_err());
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(5));
+
+		let PersistentData { set_state, .. } = load(Some(Arc::new(XorCipher(7)))).unwrap();
+		assert_eq!(*set_state.read(), state);
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(CURRENT_VERSION));
+
+		// the migrated state is stored encrypted with the same key.
+		let stored = client.get_aux(SET_STATE_KEY).unwrap().unwrap();
+		assert!(stored.starts_with(ENCRYPTED_MAGIC));
+		assert_eq!(decrypted(stored, Some(&XorCipher(7))).unwrap(), framed(&state.encode()));
+
+		let PersistentData { set_state, .. } = load(Some(Arc::new(XorCipher(7)))).unwrap();
+		assert_eq!(*set_state.read(), state);
+	}
+
+	#[test]
+	fn authority_set_of_a_future_version_is_read_without_writing() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = substrate_test_runtime_client::new();
+		let PersistentData { authority_set, .. } = load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		client.insert_aux(&[(VERSION_KEY, (CURRENT_VERSION + 1).encode().as_slice())], &[]).unwrap();
+		let before = snapshot(&client).unwrap();
+
+		let recording = RecordingAuxStore { inner: &client, writes: Default::default() };
+		assert_eq!(
+			load_authority_set_forward_compat::<Block, _>(&recording).unwrap(),
+			Some(authority_set.inner().read().clone()),
+		);
+
+		assert!(recording.writes.borrow().is_empty());
+		assert_eq!(snapshot(&client).unwrap(), before);
+	}
+
+	#[test]
+	fn decode_metrics_count_attempts_and_failures() {
+		let client = MockAuxStore::default();
+		client.insert_aux(
+			&[
+				(VERSION_KEY, CURRENT_VERSION.encode().as_slice()),
+				(MAX_SET_ID_KEY, &[1u8][..]),
+			],
+			&[],
+		).unwrap();
+
+		let registry = Registry::new();
+		let metrics = DecodeMetrics::register(&registry).unwrap();
+		let count = |counter: &CounterVec<U64>, key: &str| counter.with_label_values(&[key]).get();
+
+		assert_eq!(
+			load_decode_with_metrics::<_, u32>(&client, VERSION_KEY, Some(&metrics)).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+		assert_eq!(count(&metrics.decode_total, "grandpa_schema_version"), 1);
+		assert_eq!(count(&metrics.decode_failures_total, "grandpa_schema_version"), 0);
+
+		assert!(load_decode_with_metrics::<_, SetId>(&client, MAX_SET_ID_KEY, Some(&metrics)).is_err());
+		assert_eq!(count(&metrics.decode_total, "grandpa_max_set_id"), 1);
+		assert_eq!(count(&metrics.decode_failures_total, "grandpa_max_set_id"), 1);
+
+		// absent entries aren't decoded.
+		assert!(load_decode_with_metrics::<_, u32>(&client, GENESIS_KEY, Some(&metrics)).unwrap().is_none());
+		assert_eq!(count(&metrics.decode_total, "grandpa_genesis"), 0);
+	}
+
+	#[test]
+	fn version_is_detected_from_data_shape() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+		let round_state = RoundState::<H256, u64>::genesis((H256::random(), 32));
+
+		let detect = |set_state: Vec<u8>, best_finalized: bool| {
+			let client = MockAuxStore::default();
+			client.insert_aux(
+				&[
+					(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+					(SET_STATE_KEY, set_state.as_slice()),
+				],
+				&[],
+			).unwrap();
+			if best_finalized {
+				client.insert_aux(&[(BEST_FINALIZED_KEY, (H256::random(), 32u64).encode().as_slice())], &[]).unwrap();
+			}
+
+			detect_version_from_data::<Block, _>(&client, None).unwrap()
+		};
+
+		let v1_state = V1VoterSetState::Live(42, round_state.clone()).encode();
+		assert_eq!(detect(v1_state, false), Some(1));
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(43, HasVoted::No);
+		let v3_state = V3VoterSetState::<Block>::Live {
+			completed_rounds: (
+				vec![V3CompletedRound {
+					number: 42,
+					base: round_state.prevote_ghost.unwrap(),
+					state: round_state,
+					votes: vec![],
+				}],
+				3,
+				vec![AuthorityId::default()],
+			),
+			current_rounds,
+		}.encode();
+		assert_eq!(detect(v3_state.clone(), true), Some(3));
+		assert_eq!(detect(v3_state, false), Some(2));
+
+		// the entries are framed since version 8.
+		let current_state = VoterSetState::<Block>::live(3, &authority_set, (H256::random(), 32));
+		assert_eq!(detect(current_state.encode(), true), Some(7));
+
+		let client = MockAuxStore::default();
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, framed(&authority_set.encode()).as_slice()),
+				(SET_STATE_KEY, framed(&current_state.encode()).as_slice()),
+			],
+			&[],
+		).unwrap();
+		assert_eq!(detect_version_from_data::<Block, _>(&client, None).unwrap(), Some(CURRENT_VERSION));
+
+		assert_eq!(detect_version_from_data::<Block, _>(&MockAuxStore::default(), None).unwrap(), None);
+	}
+
+	#[test]
+	fn empty_completed_rounds_fail_to_load_unless_recovering() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let genesis_hash = H256::random();
+		let client = MockAuxStore::default();
+
+		load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		// a truncated voter set state, which can't be written through
+		// `write_voter_set_state`.
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(1, HasVoted::No);
+		let empty = VoterSetState::<Block>::Live {
+			completed_rounds: CompletedRounds::from_rounds(Vec::new(), 0, vec![AuthorityId::default()]),
+			current_rounds,
+		};
+		client.insert_aux(&[(SET_STATE_KEY, framed(&empty.encode()).as_slice())], &[]).unwrap();
+
+		let load = |options| load_persistent_with_options::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+			options,
+		);
+
+		let err = load(LoadOptions { strict: true, ..Default::default() }).err().unwrap();
+		assert!(err.to_string().contains("failed to decode grandpa_completed_round"));
+
+		let PersistentData { set_state, .. } =
+			load(LoadOptions { recover_set_state: true, ..Default::default() }).unwrap();
+		assert_eq!(
+			set_state.read().iter_completed_rounds().map(|round| round.number).collect::<Vec<_>>(),
+			vec![0],
+		);
+
+		// the repaired state was written back.
+		assert!(load(LoadOptions { strict: true, ..Default::default() }).is_ok());
+	}
+
+	#[test]
+	fn current_authorities_are_exported_as_scale() {
+		use sp_keyring::Ed25519Keyring;
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		assert!(export_current_authorities_scale::<Block, _>(&client).unwrap().is_none());
+
+		let authorities: AuthorityList = vec![
+			(Ed25519Keyring::Alice.public().into(), 1),
+			(Ed25519Keyring::Bob.public().into(), 2),
+			(Ed25519Keyring::Charlie.public().into(), 3),
+		];
+		let genesis_authorities = authorities.clone();
+		load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(genesis_authorities),
+		).unwrap();
+
+		let exported = export_current_authorities_scale::<Block, _>(&client).unwrap().unwrap();
+		assert_eq!(AuthorityList::decode(&mut &exported[..]).unwrap(), authorities);
+	}
+
+	#[test]
+	fn load_persistent_no_genesis_does_not_initialize() {
+		let client = substrate_test_runtime_client::new();
+		let recording = RecordingAuxStore { inner: &client, writes: Default::default() };
+
+		assert!(
+			load_persistent_no_genesis::<substrate_test_runtime_client::runtime::Block, _>(
+				&recording,
+				H256::random(),
+				0,
+				LoadOptions::default(),
+			).unwrap().is_none(),
+		);
+		assert!(recording.writes.borrow().is_empty());
+		assert!(client.get_aux(VERSION_KEY).unwrap().is_none());
+		assert!(client.get_aux(AUTHORITY_SET_KEY).unwrap().is_none());
+
+		let genesis_hash = H256::random();
+		let authorities = vec![(AuthorityId::default(), 100)];
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(authorities.clone()),
+		).unwrap();
+
+		let loaded = load_persistent_no_genesis::<substrate_test_runtime_client::runtime::Block, _>(
+			&client,
+			genesis_hash,
+			0,
+			LoadOptions::default(),
+		).unwrap().unwrap();
+		assert_eq!(*loaded.authority_set.inner().read(), AuthoritySet::genesis(authorities).unwrap());
+	}
+
+	#[test]
+	fn cross_check_reports_changes_missing_from_the_authority_set() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let mut authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			0,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let scheduled_hash = H256::random();
+		authority_set.add_pending_change(
+			PendingChange {
+				next_authorities: vec![(AuthorityId::default(), 100)],
+				delay: 0,
+				canon_height: 5,
+				canon_hash: scheduled_hash,
+				delay_kind: DelayKind::Finalized,
+			},
+			&|_: &H256, _: &H256| -> Result<bool, ClientError> { Ok(true) },
+		).unwrap();
+
+		let mut consensus_changes = ConsensusChanges::<H256, u64>::empty();
+		consensus_changes.note_change((5, scheduled_hash));
+		assert!(cross_check::<Block>(&authority_set, &consensus_changes).is_empty());
+
+		let unscheduled_hash = H256::random();
+		consensus_changes.note_change((7, unscheduled_hash));
+		assert_eq!(
+			cross_check::<Block>(&authority_set, &consensus_changes),
+			vec![Inconsistency::MissingPendingChange { canon_height: 7, canon_hash: unscheduled_hash }],
+		);
+	}
+
+	#[test]
+	fn completed_round_without_votes_has_canonical_encoding() {
+		let state = RoundState::genesis((H256::random(), 5));
+		let round = |votes| CompletedRound::<substrate_test_runtime_client::runtime::Block> {
+			number: 5,
+			base: state.prevote_ghost.unwrap(),
+			state: state.clone(),
+			votes,
+			completed_at: None,
+			set_id: Some(0),
+		};
+
+		// an emptied vote list keeps its capacity, which is never encoded.
+		let fresh = round(Vec::new());
+		let emptied = round(Vec::with_capacity(16));
+
+		assert_eq!(fresh.votes.encode(), vec![0]);
+		assert_eq!(fresh.encode(), emptied.encode());
+		assert_eq!(
+			CompletedRound::<substrate_test_runtime_client::runtime::Block>::decode(
+				&mut &emptied.encode()[..],
+			).unwrap(),
+			fresh,
+		);
+	}
+
+	#[test]
+	fn prevote_and_precommit_of_the_current_round_are_both_persisted() {
+		use crate::environment::Vote;
+
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = substrate_test_runtime_client::new();
+		let genesis_hash = H256::random();
+		let load = || load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		let completed_rounds = load().set_state.read().completed_rounds().clone();
+		let write_vote = |vote| {
+			let mut current_rounds = CurrentRounds::<Block>::new();
+			current_rounds.insert(1, HasVoted::Yes(AuthorityId::default(), vote));
+			write_voter_set_state(&client, &VoterSetState::Live {
+				completed_rounds: completed_rounds.clone(),
+				current_rounds,
+			}).unwrap();
+		};
+
+		let prevote = finality_grandpa::Prevote { target_hash: H256::random(), target_number: 11 };
+		let precommit = finality_grandpa::Precommit { target_hash: H256::random(), target_number: 11 };
+
+		write_vote(Vote::Prevote(None, prevote.clone()));
+		write_vote(Vote::Precommit(None, prevote.clone(), precommit.clone()));
+
+		let has_voted = load().set_state.read().has_voted(1);
+		assert_eq!(has_voted.prevote(), Some(&prevote));
+		assert_eq!(has_voted.precommit(), Some(&precommit));
+	}
+
+	#[test]
+	fn current_authority_list_reads_set_id_and_authorities() {
+		use sp_keyring::Ed25519Keyring;
+
+		let client = substrate_test_runtime_client::new();
+		let authorities = vec![
+			(Ed25519Keyring::Alice.public().into(), 100),
+			(Ed25519Keyring::Bob.public().into(), 50),
+		];
+
+		assert_eq!(
+			current_authority_list::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			None,
+		);
+
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| Ok(authorities.clone()),
+		).unwrap();
+
+		assert_eq!(
+			current_authority_list::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			Some((0, authorities)),
+		);
+	}
+
+	#[test]
+	fn load_decode_from_v6_migrates_paused_since() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		// the rounds of the helper finalize the block of their own number.
+		let completed_rounds = completed_rounds_from(3, &[41, 42]);
+		let v6_state = V6VoterSetState::<Block>::Paused {
+			completed_rounds: completed_rounds.clone(),
+			reason: PauseReason::Stalled,
+		};
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, v6_state.encode().as_slice()),
+				(VERSION_KEY, 6u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let PersistentData { set_state, .. } = load_persistent::<Block, _, _>(
+			&client,
+			H256::random(),
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		assert_eq!(
+			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+			Some(CURRENT_VERSION),
+		);
+		assert_eq!(
+			&*set_state.read(),
+			&VoterSetState::Paused { completed_rounds, reason: PauseReason::Stalled, since: 42 },
+		);
+	}
+
+	#[test]
+	fn pausing_records_the_given_last_finalized_block() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+
+		// the last finalized block may be ahead of the last completed round.
+		let paused = VoterSetState::<Block>::paused(completed_rounds_from(0, &[6, 7]), PauseReason::Manual, 42);
+		match &paused {
+			VoterSetState::Paused { since, .. } => assert_eq!(*since, 42),
+			VoterSetState::Live { .. } => panic!("voter set state is paused"),
+		}
+
+		write_voter_set_state(&client, &paused).unwrap();
+		assert_eq!(
+			load_decode_checked::<_, VoterSetState<Block>>(&client, SET_STATE_KEY).unwrap(),
+			Some(paused),
+		);
+	}
+
+	#[test]
+	fn stepwise_migration_reports_each_transition() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let genesis_round = || RoundState::genesis((H256::random(), 0));
+
+		assert!(run_migrations_stepwise::<Block, _, _>(&client, &genesis_round, None).unwrap().is_empty());
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), None);
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			3,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+		let round_state = RoundState::<H256, u64>::genesis((H256::random(), 32));
+
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
+				(SET_STATE_KEY, V1VoterSetState::Live(42, round_state).encode().as_slice()),
+				(VERSION_KEY, 1u32.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		assert_eq!(
+			run_migrations_stepwise::<Block, _, _>(&client, &genesis_round, None).unwrap(),
+			vec![(1, CURRENT_VERSION)],
+		);
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(CURRENT_VERSION));
+
+		// the DB is up to date.
+		assert!(run_migrations_stepwise::<Block, _, _>(&client, &genesis_round, None).unwrap().is_empty());
+	}
+
+	#[test]
+	fn forced_changes_at_the_same_height_are_reported() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let forced_change = |canon_hash| PendingChange {
+			next_authorities: vec![(AuthorityId::default(), 100)],
+			delay: 10,
+			canon_height: 5,
+			canon_hash,
+			delay_kind: DelayKind::Best { median_last_finalized: 0 },
+		};
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			1,
+			ForkTree::new(),
+			vec![forced_change(H256::random()), forced_change(H256::random())],
+		).unwrap();
+
+		let violations = forced_change_violations::<Block>(&authority_set);
+		assert_eq!(violations.len(), 1);
+		assert!(violations[0].contains("has the same height as another forced change"));
+
+		let client = substrate_test_runtime_client::new();
+		let genesis_hash = H256::random();
+		client.insert_aux(
+			&[
+				(AUTHORITY_SET_KEY, framed(&authority_set.encode()).as_slice()),
+				(VERSION_KEY, CURRENT_VERSION.encode().as_slice()),
+			],
+			&[],
+		).unwrap();
+
+		let load = |strict| load_persistent_with_options::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+			LoadOptions { strict, ..Default::default() },
+		);
+
+		assert!(load(true).is_err());
+		assert_eq!(*load(false).unwrap().authority_set.inner().read(), authority_set);
+	}
+
+	#[test]
+	fn finality_health_of_stored_state() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		// the last round of the helper finalizes block 7.
+		let live = VoterSetState::<Block>::Live {
+			completed_rounds: completed_rounds_from(0, &[6, 7]),
+			current_rounds: CurrentRounds::new(),
+		};
+
+		assert_eq!(finality_health(&live, 10, 5), FinalityHealth::Healthy);
+		assert_eq!(finality_health(&live, 12, 5), FinalityHealth::Healthy);
+		assert_eq!(finality_health(&live, 20, 5), FinalityHealth::Lagging(13));
+		assert_eq!(finality_health(&live, 3, 5), FinalityHealth::Healthy);
+
+		let paused = VoterSetState::paused(live.completed_rounds().clone(), PauseReason::Stalled, 0);
+		assert_eq!(finality_health(&paused, 10, 5), FinalityHealth::Paused);
+	}
+
+	#[test]
+	fn missing_version_is_detected_on_load() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let client = MockAuxStore::default();
+		let genesis_hash = H256::random();
+		let load = || load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		let PersistentData { set_state, .. } = load();
+		let mut completed_rounds = set_state.read().completed_rounds().clone();
+		let round_state = RoundState::genesis((H256::random(), 5));
+		completed_rounds.push(CompletedRound {
+			number: 1,
+			base: round_state.prevote_ghost.unwrap(),
+			state: round_state,
+			votes: vec![],
+			completed_at: None,
+			set_id: Some(0),
+		});
+		let state = VoterSetState::<Block>::Live { completed_rounds, current_rounds: CurrentRounds::new() };
+		write_voter_set_state(&client, &state).unwrap();
+
+		// current data isn't taken for unversioned data.
+		client.insert_aux(&[], &[VERSION_KEY]).unwrap();
+		let PersistentData { set_state, .. } = load();
+		assert_eq!(*set_state.read(), state);
+		assert_eq!(load_decode::<_, u32>(&client, VERSION_KEY).unwrap(), Some(CURRENT_VERSION));
+	}
+
+	#[test]
+	fn pending_forced_changes_round_trip() {
+		let client = substrate_test_runtime_client::new();
+
+		let forced_change = PendingChange {
+			next_authorities: vec![(AuthorityId::default(), 5)],
+			delay: 10,
+			canon_height: 20,
+			canon_hash: H256::random(),
+			delay_kind: DelayKind::Best { median_last_finalized: 15 },
+		};
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			1,
+			ForkTree::new(),
+			vec![forced_change.clone()],
+		).unwrap();
+
+		commit_grandpa_state::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			&authority_set,
+			None,
+			HandoffHistory::Wipe,
+			None,
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
+		).unwrap();
+
+		client.insert_aux(
+			&[(VERSION_KEY, CURRENT_VERSION.encode().as_slice())],
+			&[],
+		).unwrap();
+
+		let PersistentData { authority_set: loaded, .. } =
+			load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+				&client,
+				H256::random(),
+				0,
+				|| unreachable!(),
+			).unwrap();
+
+		assert_eq!(*loaded.inner().read(), authority_set);
+		assert_eq!(
+			loaded.inner().read().pending_changes().cloned().collect::<Vec<_>>(),
+			vec![forced_change],
+		);
+	}
+
+	/// Aux store recording the keys inserted by each write.
+	struct RecordingAuxStore<'a, B> {
+		inner: &'a B,
+		writes: std::cell::RefCell<Vec<Vec<Vec<u8>>>>,
+	}
+
+	impl<'a, B: AuxStore> AuxStore for RecordingAuxStore<'a, B> {
+		fn insert_aux<
+			'b,
+			'c: 'b,
+			'd: 'b,
+			I: IntoIterator<Item=&'b(&'d [u8], &'d [u8])>,
+			D: IntoIterator<Item=&'b &'c [u8]>,
+		>(&self, insert: I, delete: D) -> ClientResult<()> {
+			let insert = insert.into_iter().collect::<Vec<_>>();
+			self.writes.borrow_mut().push(insert.iter().map(|(k, _)| k.to_vec()).collect());
+			self.inner.insert_aux(insert, delete)
+		}
+
+		fn get_aux(&self, key: &[u8]) -> ClientResult<Option<Vec<u8>>> {
+			self.inner.get_aux(key)
+		}
+	}
+
+	#[test]
+	fn applied_standard_change_is_a_single_write() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let genesis_hash = H256::random();
+		let client = MockAuxStore::default();
+		let is_descendent_of = |_: &H256, _: &H256| Ok::<_, ClientError>(true);
+
+		let PersistentData { authority_set, .. } = load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 100)]),
+		).unwrap();
+
+		let change_hash = H256::random();
+		let mut set = authority_set.inner().read().clone();
+		set.add_pending_change(
+			PendingChange {
+				next_authorities: vec![(AuthorityId::default(), 5)],
+				delay: 0,
+				canon_height: 10,
+				canon_hash: change_hash,
+				delay_kind: DelayKind::Finalized,
+			},
+			&is_descendent_of,
+		).unwrap();
+
+		commit_grandpa_state::<Block, _, _>(&client, &set, None, HandoffHistory::Wipe, None, None, None, |values| client.insert_aux(values, &[])).unwrap();
+
+		set.apply_standard_changes(change_hash, 10, &is_descendent_of, false).unwrap();
+		let mut consensus_changes = ConsensusChanges::<H256, u64>::empty();
+		consensus_changes.note_change((10, change_hash));
+
+		let backend = RecordingAuxStore { inner: &client, writes: Default::default() };
+		apply_standard_change::<Block, _, _>(
+			&backend,
+			&set,
+			None,
+			&consensus_changes,
+			None,
+			|values| backend.insert_aux(values, &[]),
+		).unwrap();
+		assert_eq!(backend.writes.into_inner().len(), 1);
+
+		let PersistentData { authority_set, .. } = load_persistent::<Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| unreachable!(),
+		).unwrap();
+
+		let authority_set = authority_set.inner().read();
+		assert_eq!(authority_set.set_id(), 1);
+		assert_eq!(authority_set.pending_changes().count(), 0);
+		assert_eq!(
+			load_consensus_changes::<Block, _>(&client, None).unwrap().pending_changes(),
+			&[(10, change_hash)],
+		);
+	}
+
+	#[test]
+	fn aux_write_observer_sees_handoff_writes() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		#[derive(Default)]
+		struct RecordingObserver(parking_lot::Mutex<Vec<(Vec<u8>, usize)>>);
+
+		impl AuxWriteObserver for RecordingObserver {
+			fn on_write(&self, key: &[u8], value_len: usize) {
+				self.0.lock().push((key.to_vec(), value_len));
+			}
+		}
+
+		let client = MockAuxStore::default();
+		let observer = RecordingObserver::default();
+		let backend = ObservedAuxStore::new(&client, Some(&observer));
+
+		let authority_set = AuthoritySet::<H256, u64>::genesis(
+			vec![(AuthorityId::default(), 100)],
+		).unwrap();
+		let new_set = NewAuthoritySet {
+			canon_number: 10,
+			canon_hash: H256::random(),
+			set_id: 1,
+			authorities: vec![(AuthorityId::default(), 100)],
+		};
+
+		commit_grandpa_state::<Block, _, _>(
+			&backend,
+			&authority_set,
+			Some(&new_set),
+			HandoffHistory::Wipe,
+			None,
+			None,
+			None,
+			|values| backend.insert_aux(values, &[]),
+		).unwrap();
+
+		let writes = observer.0.lock().clone();
+		for key in &[AUTHORITY_SET_KEY, SET_STATE_KEY] {
+			let stored_len = client.get_aux(key).unwrap().unwrap().len();
+			assert!(writes.contains(&(key.to_vec(), stored_len)));
+		}
+
+		// without an observer writes are passed through.
+		let backend = ObservedAuxStore::new(&client, None);
+		update_consensus_changes(
+			&ConsensusChanges::<H256, u64>::empty(),
+			|values| backend.insert_aux(values, &[]),
+		).unwrap();
+		assert_eq!(observer.0.lock().len(), writes.len());
+		assert!(client.get_aux(CONSENSUS_CHANGES_KEY).unwrap().is_some());
+	}
+
+	#[test]
+	fn commit_grandpa_state_is_a_single_write() {
+		let client = substrate_test_runtime_client::new();
+		let backend = RecordingAuxStore { inner: &client, writes: Default::default() };
+
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			1,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let stale_state = VoterSetState::<substrate_test_runtime_client::runtime::Block>::live(
+			0,
+			&authority_set,
+			(H256::random(), 0),
+		);
+
+		let new_set = NewAuthoritySet {
+			canon_number: 10,
+			canon_hash: H256::random(),
+			set_id: 1,
+			authorities: vec![(AuthorityId::default(), 100)],
+		};
+
+		commit_grandpa_state(
+			&backend,
+			&authority_set,
+			Some(&new_set),
+			Some(&stale_state),
+			Some(&ConsensusChanges::empty()),
+			None,
+			|values| backend.insert_aux(values, &[]),
+		).unwrap();
+
+		let writes = backend.writes.into_inner();
+		assert_eq!(writes.len(), 1);
+		for key in &[AUTHORITY_SET_KEY, SET_STATE_KEY, CONSENSUS_CHANGES_KEY, MAX_SET_ID_KEY] {
+			assert!(writes[0].contains(&key.to_vec()));
+		}
+
+		let set_state = load_decode_checked::<_, VoterSetState<substrate_test_runtime_client::runtime::Block>>(
+			&client,
+			SET_STATE_KEY,
+		).unwrap().unwrap();
+
+		assert_eq!(set_state.last_completed_round().base, (new_set.canon_hash, 10));
+		assert_eq!(set_state.completed_rounds().set_info().0, 1);
+	}
+
+	#[test]
+	fn cached_authority_set_read_skips_backend() {
+		let client = substrate_test_runtime_client::new();
+
+		let PersistentData { authority_set, .. } =
+			load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+				&client,
+				H256::random(),
+				0,
+				|| Ok(vec![(AuthorityId::default(), 1)]),
+			).unwrap();
+
+		// an empty backend can only be answered from the cache.
+		let empty = substrate_test_runtime_client::new();
+		let cached = read_authority_set_cached::<substrate_test_runtime_client::runtime::Block, _>(
+			&empty,
+			Some(&authority_set),
+		).unwrap().unwrap();
+
+		assert_eq!(cached.current().0, authority_set.set_id());
+		assert_eq!(cached, *authority_set.inner().read());
+
+		assert_eq!(
+			read_authority_set_cached::<substrate_test_runtime_client::runtime::Block, _>(&empty, None)
+				.unwrap(),
+			None,
+		);
+	}
+
+	#[test]
+	fn pending_changes_at_follows_the_given_fork() {
+		let client = substrate_test_runtime_client::new();
+
+		// A (5) - C (10) is the canonical chain, B (6) is on an abandoned fork.
+		let hash_a = H256::repeat_byte(0xa);
+		let hash_b = H256::repeat_byte(0xb);
+		let hash_c = H256::repeat_byte(0xc);
+		let is_descendent_of = |base: &H256, target: &H256| -> ClientResult<bool> {
+			Ok(*base == hash_a && *target == hash_c)
+		};
+
+		let change = |canon_hash, canon_height, delay_kind| PendingChange {
+			next_authorities: vec![(AuthorityId::default(), 1)],
+			delay: 0,
+			canon_height,
+			canon_hash,
+			delay_kind,
+		};
+
+		let mut authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			0,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let canonical = change(hash_a, 5, DelayKind::Finalized);
+		let abandoned = change(hash_b, 6, DelayKind::Finalized);
+		let forced = change(hash_b, 7, DelayKind::Best { median_last_finalized: 0 });
+		let future = change(hash_c, 11, DelayKind::Finalized);
+
+		for pending in vec![canonical.clone(), abandoned, forced.clone(), future] {
+			authority_set.add_pending_change(pending, &is_descendent_of).unwrap();
+		}
+
+		commit_grandpa_state::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			&authority_set,
+			None,
+			HandoffHistory::Wipe,
+			None,
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
+		).unwrap();
+
+		assert_eq!(
+			pending_changes_at::<substrate_test_runtime_client::runtime::Block, _, _>(
+				&client,
+				hash_c,
+				10,
+				&is_descendent_of,
+			).unwrap(),
+			vec![canonical, forced],
+		);
+	}
+
+	#[cfg(feature = "state-json")]
+	#[test]
+	fn export_genesis_state_as_json() {
+		let client = substrate_test_runtime_client::new();
+		let genesis_hash = H256::random();
+
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 7)]),
+		).unwrap();
+
+		let json = export_state_json::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+			.unwrap();
+
+		assert_eq!(json["version"], CURRENT_VERSION);
+		assert_eq!(json["authority_set"]["set_id"], 0);
+		assert_eq!(
+			json["authority_set"]["authorities"],
+			serde_json::json!([{
+				"id": sp_core::bytes::to_hex(&AuthorityId::default().encode(), false),
+				"weight": 7,
+			}]),
+		);
+		assert_eq!(json["voter_set_state"]["status"], "live");
+		assert_eq!(
+			json["voter_set_state"]["completed_rounds"][0]["base"],
+			serde_json::json!({
+				"hash": sp_core::bytes::to_hex(genesis_hash.as_ref(), false),
+				"number": "0",
+			}),
+		);
+
+		// an older or missing version must be migrated first.
+		client.insert_aux(&[(VERSION_KEY, 7u32.encode().as_slice())], &[]).unwrap();
+		assert!(export_state_json::<substrate_test_runtime_client::runtime::Block, _>(&client, None).is_err());
+		client.insert_aux(&[], &[VERSION_KEY]).unwrap();
+		assert!(export_state_json::<substrate_test_runtime_client::runtime::Block, _>(&client, None).is_err());
+	}
+
+	#[cfg(feature = "state-json")]
+	#[test]
+	fn import_exported_state_json() {
+		let client = substrate_test_runtime_client::new();
+		let genesis_hash = H256::random();
+
+		let load = || load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 7)]),
+		).unwrap();
+
+		let PersistentData { authority_set, set_state, .. } = load();
+		let json = export_state_json::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+			.unwrap();
+
+		let entries = [VERSION_KEY, AUTHORITY_SET_KEY, SET_STATE_KEY, BEST_FINALIZED_KEY, GENESIS_KEY, INIT_KEY];
+		let exported = entries.iter().map(|key| client.get_aux(key).unwrap()).collect::<Vec<_>>();
+		client.insert_aux(&[], &entries).unwrap();
+
+		import_state_json::<substrate_test_runtime_client::runtime::Block, _>(&client, &json, None)
+			.unwrap();
+
+		// the same entries are written as when initializing from genesis.
+		assert_eq!(entries.iter().map(|key| client.get_aux(key).unwrap()).collect::<Vec<_>>(), exported);
+
+		let PersistentData { authority_set: imported_set, set_state: imported_state, .. } = load();
+		assert_eq!(*imported_set.inner().read(), *authority_set.inner().read());
+		assert_eq!(*imported_state.read(), *set_state.read());
+
+		let mut newer = json.clone();
+		newer["version"] = serde_json::json!(CURRENT_VERSION + 1);
+		assert!(
+			import_state_json::<substrate_test_runtime_client::runtime::Block, _>(&client, &newer, None)
+				.is_err()
+		);
+	}
+
+	#[test]
+	fn write_voter_set_state_reports_completed_rounds() {
+		let client = substrate_test_runtime_client::new();
+		let registry = prometheus_endpoint::Registry::new();
+		let metrics = Metrics::register(&registry).unwrap();
+
+		let state = VoterSetState::Live {
+			completed_rounds: completed_rounds_from(0, &[1, 2, 3, 4]),
+			current_rounds: CurrentRounds::new(),
+		};
+
+		write_voter_set_state_with_metrics(&client, &state, Some(&metrics), None).unwrap();
+
+		let completed_rounds = registry.gather().into_iter()
+			.find(|family| family.get_name() == "grandpa_completed_rounds_total")
+			.unwrap();
+
+		assert_eq!(completed_rounds.get_metric()[0].get_gauge().get_value(), 4.0);
+	}
 
-	if let Some(new_set) = new_set {
-		// we also overwrite the "last completed round" entry with a blank slate
-		// because from the perspective of the finality gadget, the chain has
-		// reset.
-		let set_state = VoterSetState::<Block>::live(
-			new_set.set_id,
-			&set,
-			(new_set.canon_hash, new_set.canon_number),
+	#[test]
+	fn consensus_changes_finalized_up_to() {
+		let client = substrate_test_runtime_client::new();
+
+		assert!(
+			load_consensus_changes::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+				.unwrap()
+				.finalized_up_to(25)
+				.is_empty()
 		);
-		let encoded = set_state.encode();
 
-		write_aux(&[
-			(AUTHORITY_SET_KEY, &encoded_set[..]),
-			(SET_STATE_KEY, &encoded[..]),
-		])
-	} else {
-		write_aux(&[(AUTHORITY_SET_KEY, &encoded_set[..])])
+		let changes = [(10, H256::random()), (20, H256::random()), (30, H256::random())];
+		let mut consensus_changes = ConsensusChanges::<H256, u64>::empty();
+		for change in changes.iter() {
+			consensus_changes.note_change(*change);
+		}
+
+		update_consensus_changes(&consensus_changes, |values| client.insert_aux(values, &[]))
+			.unwrap();
+
+		assert_eq!(
+			load_consensus_changes::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+				.unwrap()
+				.finalized_up_to(25),
+			changes[..2].to_vec(),
+		);
 	}
-}
 
-/// Write voter set state.
-pub(crate) fn write_voter_set_state<Block: BlockT, B: AuxStore>(
-	backend: &B,
-	state: &VoterSetState<Block>,
-) -> ClientResult<()> {
-	backend.insert_aux(
-		&[(SET_STATE_KEY, state.encode().as_slice())],
-		&[]
-	)
-}
+	#[test]
+	fn revert_consensus_changes_drops_changes_above_block() {
+		let client = MockAuxStore::default();
 
-/// Write concluded round.
-pub(crate) fn write_concluded_round<Block: BlockT, B: AuxStore>(
-	backend: &B,
-	round_data: &CompletedRound<Block>,
-) -> ClientResult<()> {
-	let mut key = CONCLUDED_ROUNDS.to_vec();
-	let round_number = round_data.number;
-	round_number.using_encoded(|n| key.extend(n));
+		// nothing to revert
+		revert_consensus_changes::<H256, u64, _>(&client, 10).unwrap();
+		assert!(client.get_aux(CONSENSUS_CHANGES_KEY).unwrap().is_none());
 
-	backend.insert_aux(&[(&key[..], round_data.encode().as_slice())], &[])
-}
+		let changes = [(5, H256::random()), (15, H256::random()), (25, H256::random())];
+		let mut consensus_changes = ConsensusChanges::<H256, u64>::empty();
+		for change in changes.iter() {
+			consensus_changes.note_change(*change);
+		}
 
-/// Update the consensus changes.
-pub(crate) fn update_consensus_changes<H, N, F, R>(
-	set: &ConsensusChanges<H, N>,
-	write_aux: F
-) -> R where
-	H: Encode + Clone,
-	N: Encode + Clone,
-	F: FnOnce(&[(&'static [u8], &[u8])]) -> R,
-{
-	write_aux(&[(CONSENSUS_CHANGES_KEY, set.encode().as_slice())])
-}
+		update_consensus_changes(&consensus_changes, |values| client.insert_aux(values, &[]))
+			.unwrap();
 
-#[cfg(test)]
-pub(crate) fn load_authorities<B: AuxStore, H: Decode, N: Decode>(backend: &B)
-	-> Option<AuthoritySet<H, N>> {
-	load_decode::<_, AuthoritySet<H, N>>(backend, AUTHORITY_SET_KEY)
-		.expect("backend error")
-}
+		revert_consensus_changes::<H256, u64, _>(&client, 10).unwrap();
+		revert_consensus_changes::<H256, u64, _>(&client, 10).unwrap();
 
-#[cfg(test)]
-mod test {
-	use sp_finality_grandpa::AuthorityId;
-	use sp_core::H256;
-	use substrate_test_runtime_client;
-	use super::*;
+		assert_eq!(
+			load_consensus_changes::<substrate_test_runtime_client::runtime::Block, _>(&client, None)
+				.unwrap()
+				.pending_changes(),
+			&changes[..1],
+		);
+	}
 
 	#[test]
-	fn load_decode_from_v0_migrates_data_format() {
+	fn handoff_wipes_or_preserves_completed_rounds() {
 		let client = substrate_test_runtime_client::new();
 
-		let authorities = vec![(AuthorityId::default(), 100)];
-		let set_id = 3;
-		let round_number: RoundNumber = 42;
-		let round_state = RoundState::<H256, u64> {
-			prevote_ghost: Some((H256::random(), 32)),
-			finalized: None,
-			estimate: None,
-			completable: false,
+		let authority_set = AuthoritySet::<H256, u64>::new(
+			vec![(AuthorityId::default(), 100)],
+			1,
+			ForkTree::new(),
+			Vec::new(),
+		).unwrap();
+
+		let new_set = NewAuthoritySet {
+			canon_number: 100,
+			canon_hash: H256::random(),
+			set_id: 1,
+			authorities: vec![(AuthorityId::default(), 100)],
 		};
 
-		{
-			let authority_set = V0AuthoritySet::<H256, u64> {
-				current_authorities: authorities.clone(),
-				pending_changes: Vec::new(),
-				set_id,
-			};
+		let voter_state = VoterSetState::Live {
+			completed_rounds: completed_rounds_from(0, &[41, 42]),
+			current_rounds: CurrentRounds::new(),
+		};
 
-			let voter_set_state = (round_number, round_state.clone());
+		let load_set_state = || load_decode_checked::<_, VoterSetState<substrate_test_runtime_client::runtime::Block>>(
+			&client,
+			SET_STATE_KEY,
+		).unwrap().unwrap();
 
-			client.insert_aux(
-				&[
-					(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
-					(SET_STATE_KEY, voter_set_state.encode().as_slice()),
-				],
-				&[],
-			).unwrap();
-		}
+		let check_handoff = |state: &VoterSetState<substrate_test_runtime_client::runtime::Block>| {
+			assert_eq!(state.completed_rounds().set_info().0, 1);
+			assert_eq!(state.last_completed_round().number, 0);
+			assert_eq!(state.last_completed_round().base, (new_set.canon_hash, 100));
+		};
 
-		assert_eq!(
-			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
+		commit_grandpa_state::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&client,
+			&authority_set,
+			Some(&new_set),
+			HandoffHistory::Wipe,
 			None,
-		);
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
+		).unwrap();
 
-		// should perform the migration
-		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+		let wiped = load_set_state();
+		check_handoff(&wiped);
+		assert_eq!(wiped.iter_completed_rounds().count(), 1);
+
+		commit_grandpa_state::<substrate_test_runtime_client::runtime::Block, _, _>(
 			&client,
-			H256::random(),
-			0,
-			|| unreachable!(),
+			&authority_set,
+			Some(&new_set),
+			HandoffHistory::Preserve(&voter_state, None),
+			None,
+			None,
+			None,
+			|values| client.insert_aux(values, &[]),
 		).unwrap();
 
+		let preserved = load_set_state();
+		check_handoff(&preserved);
 		assert_eq!(
-			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
-			Some(2),
+			preserved.iter_completed_rounds().map(|round| round.number).collect::<Vec<_>>(),
+			vec![41, 42, 0],
 		);
 
-		let PersistentData { authority_set, set_state, .. } = load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
-			&client,
-			H256::random(),
+		// both handoffs are recorded alike.
+		let handoff = (new_set.set_id, new_set.canon_hash, new_set.canon_number);
+		assert_eq!(
+			load_handoff_log::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			vec![handoff, handoff],
+		);
+		assert_eq!(
+			load_last_handoff::<substrate_test_runtime_client::runtime::Block, _>(&client).unwrap(),
+			Some(handoff),
+		);
+		assert_eq!(
+			load_decode::<_, (H256, u64)>(&client, BEST_FINALIZED_KEY).unwrap(),
+			Some((new_set.canon_hash, 100)),
+		);
+	}
+
+	#[test]
+	fn stored_version_without_authority_set_is_not_a_first_startup() {
+		let genesis_hash = H256::random();
+		let backend = MockAuxStore::default();
+		backend.insert_aux(&[(VERSION_KEY, CURRENT_VERSION.encode().as_slice())], &[]).unwrap();
+
+		let err = load_persistent_with_options::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&backend,
+			genesis_hash,
 			0,
 			|| unreachable!(),
-		).unwrap();
+			LoadOptions { strict: true, ..Default::default() },
+		).err().unwrap();
 
 		assert_eq!(
-			*authority_set.inner().read(),
-			AuthoritySet::new(
-				authorities.clone(),
-				set_id,
-				ForkTree::new(),
-				Vec::new(),
-			).unwrap(),
+			err.to_string(),
+			ClientError::from(GrandpaAuxError::MissingAuthoritySet(CURRENT_VERSION)).to_string(),
 		);
+		assert_eq!(backend.get_aux(AUTHORITY_SET_KEY).unwrap(), None);
 
-		let mut current_rounds = CurrentRounds::new();
-		current_rounds.insert(round_number + 1, HasVoted::No);
+		// without strict mode we warn and initialize from genesis.
+		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+			&backend,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 1)]),
+		).unwrap();
 
-		assert_eq!(
-			&*set_state.read(),
-			&VoterSetState::Live {
-				completed_rounds: CompletedRounds::new(
-					CompletedRound {
-						number: round_number,
-						state: round_state.clone(),
-						base: round_state.prevote_ghost.unwrap(),
-						votes: vec![],
-					},
-					set_id,
-					&*authority_set.inner().read(),
-				),
-				current_rounds,
-			},
-		);
+		assert!(backend.get_aux(AUTHORITY_SET_KEY).unwrap().is_some());
 	}
 
 	#[test]
-	fn load_decode_from_v1_migrates_data_format() {
-		let client = substrate_test_runtime_client::new();
+	fn interrupted_genesis_init_does_not_store_version() {
+		type Block = substrate_test_runtime_client::runtime::Block;
 
-		let authorities = vec![(AuthorityId::default(), 100)];
-		let set_id = 3;
-		let round_number: RoundNumber = 42;
-		let round_state = RoundState::<H256, u64> {
-			prevote_ghost: Some((H256::random(), 32)),
-			finalized: None,
-			estimate: None,
-			completable: false,
-		};
+		let genesis_hash = H256::random();
+		let backend = MockAuxStore::default();
+		backend.fail_writes_to(AUTHORITY_SET_KEY);
 
-		{
-			let authority_set = AuthoritySet::<H256, u64>::new(
-				authorities.clone(),
-				set_id,
-				ForkTree::new(),
-				Vec::new(),
-			).unwrap();
+		assert!(load_persistent::<Block, _, _>(
+			&backend,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 1)]),
+		).is_err());
 
-			let voter_set_state = V1VoterSetState::Live(round_number, round_state.clone());
+		assert_eq!(backend.get_aux(VERSION_KEY).unwrap(), None);
 
-			client.insert_aux(
-				&[
-					(AUTHORITY_SET_KEY, authority_set.encode().as_slice()),
-					(SET_STATE_KEY, voter_set_state.encode().as_slice()),
-					(VERSION_KEY, 1u32.encode().as_slice()),
-				],
-				&[],
-			).unwrap();
-		}
+		// a strict restart initializes from genesis instead of finding a version
+		// without an authority set.
+		*backend.failing_key.lock() = None;
+		load_persistent_with_options::<Block, _, _>(
+			&backend,
+			genesis_hash,
+			0,
+			|| Ok(vec![(AuthorityId::default(), 1)]),
+			LoadOptions { strict: true, ..Default::default() },
+		).unwrap();
 
-		assert_eq!(
-			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
-			Some(1),
-		);
+		assert_eq!(backend.get_aux(VERSION_KEY).unwrap(), Some(CURRENT_VERSION.encode()));
+		assert!(backend.get_aux(AUTHORITY_SET_KEY).unwrap().is_some());
+	}
 
-		// should perform the migration
-		load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
-			&client,
-			H256::random(),
+	#[test]
+	fn deleted_authority_set_of_initialized_db_is_an_error() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let genesis_hash = H256::random();
+		let backend = MockAuxStore::default();
+		assert_eq!(backend.get_aux(INIT_KEY).unwrap(), None);
+
+		load_persistent::<Block, _, _>(
+			&backend,
+			genesis_hash,
 			0,
-			|| unreachable!(),
+			|| Ok(vec![(AuthorityId::default(), 1)]),
 		).unwrap();
 
-		assert_eq!(
-			load_decode::<_, u32>(&client, VERSION_KEY).unwrap(),
-			Some(2),
-		);
+		assert_eq!(backend.get_aux(INIT_KEY).unwrap(), Some(CURRENT_VERSION.encode()));
 
-		let PersistentData { authority_set, set_state, .. } = load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
-			&client,
-			H256::random(),
+		backend.insert_aux(&[], &[AUTHORITY_SET_KEY]).unwrap();
+
+		let err = load_persistent::<Block, _, _>(
+			&backend,
+			genesis_hash,
 			0,
 			|| unreachable!(),
-		).unwrap();
+		).err().unwrap();
 
 		assert_eq!(
-			*authority_set.inner().read(),
-			AuthoritySet::new(
-				authorities.clone(),
-				set_id,
-				ForkTree::new(),
-				Vec::new(),
-			).unwrap(),
+			err.to_string(),
+			ClientError::from(GrandpaAuxError::AuthoritySetDeleted).to_string(),
 		);
+	}
 
-		let mut current_rounds = CurrentRounds::new();
-		current_rounds.insert(round_number + 1, HasVoted::No);
+	#[test]
+	fn v3_completed_round_without_votes_decodes() {
+		type Block = substrate_test_runtime_client::runtime::Block;
 
-		assert_eq!(
-			&*set_state.read(),
-			&VoterSetState::Live {
-				completed_rounds: CompletedRounds::new(
-					CompletedRound {
-						number: round_number,
-						state: round_state.clone(),
+		let state = RoundState::genesis((H256::random(), 10));
+		let base = state.prevote_ghost.unwrap();
+
+		let encoded = (42u64, state.clone(), base).encode();
+		let round = V3CompletedRound::<Block>::decode(&mut &encoded[..]).unwrap();
+		assert_eq!(round.number, 42);
+		assert_eq!(round.state, state);
+		assert_eq!(round.base, base);
+		assert!(round.votes.is_empty());
+
+		// well-formed rounds are unaffected.
+		let round = V3CompletedRound::<Block> {
+			number: 42,
+			state: state.clone(),
+			base,
+			votes: Vec::new(),
+		};
+		let mut encoded = round.encode();
+		encoded.extend(round.encode());
+
+		let mut input = &encoded[..];
+		assert_eq!(V3CompletedRound::<Block>::decode(&mut input).unwrap().number, 42);
+		assert_eq!(V3CompletedRound::<Block>::decode(&mut input).unwrap().number, 42);
+		assert!(input.is_empty());
+	}
+
+	#[test]
+	fn migrations_cover_all_previous_versions() {
+		let versions = migrations::<
+			substrate_test_runtime_client::runtime::Block,
+			MockAuxStore,
+			fn() -> RoundState<H256, u64>,
+		>().into_iter().map(|(from, _)| from).collect::<Vec<_>>();
+
+		assert_eq!(versions, (0..CURRENT_VERSION).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn default_retention_evicts_the_oldest_round() {
+		type Block = substrate_test_runtime_client::runtime::Block;
+
+		let authority_set = AuthoritySet::<H256, u64>::genesis(
+			vec![(AuthorityId::default(), 1)],
+		).unwrap();
+
+		let completed_round = |number, finalized| {
+			let round_state = RoundState::genesis((H256::random(), finalized));
+			CompletedRound::<Block> {
+				number,
+				base: round_state.prevote_ghost.unwrap(),
+				state: round_state,
+				votes: vec![],
+				completed_at: None,
+				set_id: Some(0),
+			}
+		};
+
+		// the oldest round holds the highest finalized block.
+		let mut default = CompletedRounds::new(completed_round(0, 100), 0, &authority_set);
+		let mut configured = CompletedRounds::with_retention(completed_round(0, 100), 0, &authority_set, 2);
+		for completed_rounds in &mut [&mut default, &mut configured] {
+			completed_rounds.push(completed_round(1, 1));
+			completed_rounds.push(completed_round(2, 2));
+		}
+
+		let numbers = |completed_rounds: &CompletedRounds<Block>| completed_rounds.iter()
+			.map(|round| round.number)
+			.collect::<Vec<_>>();
+
+		assert_eq!(numbers(&default), vec![1, 2]);
+		assert_eq!(numbers(&configured), vec![0, 2]);
+	}
+
+	#[test]
+	fn completed_rounds_retention_is_configurable() {
+		let genesis_hash = H256::random();
+		let client = substrate_test_runtime_client::new();
+
+		let PersistentData { set_state, .. } =
+			load_persistent_with_options::<substrate_test_runtime_client::runtime::Block, _, _>(
+				&client,
+				genesis_hash,
+				0,
+				|| Ok(vec![(AuthorityId::default(), 1)]),
+				LoadOptions { completed_rounds_retention: Some(10), ..Default::default() },
+			).unwrap();
+
+		let mut state = set_state.read().clone();
+		match &mut state {
+			VoterSetState::Live { completed_rounds, .. } => {
+				for number in 1..=100 {
+					let round_state = RoundState::genesis((H256::random(), number));
+					completed_rounds.push(CompletedRound {
+						number,
 						base: round_state.prevote_ghost.unwrap(),
+						state: round_state,
 						votes: vec![],
-					},
-					set_id,
-					&*authority_set.inner().read(),
-				),
-				current_rounds,
+						completed_at: None,
+						set_id: None,
+					});
+				}
 			},
+			VoterSetState::Paused { .. } => panic!("expected live voter set state"),
+		}
+
+		write_voter_set_state(&client, &state).unwrap();
+
+		let PersistentData { set_state, .. } =
+			load_persistent::<substrate_test_runtime_client::runtime::Block, _, _>(
+				&client,
+				genesis_hash,
+				0,
+				|| unreachable!(),
+			).unwrap();
+
+		assert_eq!(
+			set_state.read().iter_completed_rounds().map(|round| round.number).collect::<Vec<_>>(),
+			(91..=100).collect::<Vec<_>>(),
 		);
 	}
 
@@ -636,6 +7865,8 @@ mod test {
 			state: round_state.clone(),
 			base: round_state.prevote_ghost.unwrap(),
 			votes: vec![],
+			completed_at: None,
+			set_id: None,
 		};
 
 		assert!(write_concluded_round(&client, &completed_round).is_ok());
@@ -1902,13 +1902,15 @@ mod tests {
 	fn unanswerable_catch_up_requests_discarded() {
 		// create voter set state with round 2 completed
 		let set_state: SharedVoterSetState<Block> = {
-			let mut completed_rounds = voter_set_state().read().completed_rounds();
+			let mut completed_rounds = voter_set_state().read().completed_rounds().clone();
 
 			completed_rounds.push(environment::CompletedRound {
 				number: 2,
 				state: finality_grandpa::round::State::genesis(Default::default()),
 				base: Default::default(),
 				votes: Default::default(),
+				completed_at: None,
+				set_id: None,
 			});
 
 			let mut current_rounds = environment::CurrentRounds::new();
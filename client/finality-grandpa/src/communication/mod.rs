@@ -199,7 +199,7 @@ impl<B: BlockT, N: Network<B>> NetworkBridge<B, N> {
 		{
 			// register all previous votes with the gossip service so that they're
 			// available to peers potentially stuck on a previous round.
-			let completed = set_state.read().completed_rounds();
+			let completed = set_state.read().completed_rounds().clone();
 			let (set_id, voters) = completed.set_info();
 			validator.note_set(SetId(set_id), voters.to_vec(), |_, _| {});
 			for round in completed.iter() {
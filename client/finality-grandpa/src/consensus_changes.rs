@@ -37,12 +37,64 @@ impl<H: Copy + PartialEq, N: Copy + Ord> ConsensusChanges<H, N> {
 		&self.pending_changes
 	}
 
-	/// Note unfinalized change of consensus-related data.
-	pub(crate) fn note_change(&mut self, at: (N, H)) {
+	/// Returns the pending changes at or below the given block number.
+	pub(crate) fn finalized_up_to(&self, number: N) -> Vec<(N, H)> {
+		self.pending_changes.iter()
+			.take_while(|(at_height, _)| *at_height <= number)
+			.cloned()
+			.collect()
+	}
+
+	/// Drop all pending changes above the given block number. Returns true if
+	/// any changes were dropped.
+	pub(crate) fn revert(&mut self, number: N) -> bool {
+		let split_idx = self.pending_changes.iter()
+			.take_while(|(at_height, _)| *at_height <= number)
+			.count();
+
+		let altered_changes = split_idx < self.pending_changes.len();
+		self.pending_changes.truncate(split_idx);
+		altered_changes
+	}
+
+	/// Note unfinalized change of consensus-related data. An exact duplicate
+	/// of a pending change is ignored. Returns true if the change was noted.
+	pub(crate) fn note_change(&mut self, at: (N, H)) -> bool {
+		if self.pending_changes.contains(&at) {
+			return false;
+		}
+
 		let idx = self.pending_changes
 			.binary_search_by_key(&at.0, |change| change.0)
 			.unwrap_or_else(|i| i);
 		self.pending_changes.insert(idx, at);
+		true
+	}
+
+	/// Drop exact duplicates of pending changes, keeping the first occurrence
+	/// of each. Returns the number of changes dropped.
+	pub(crate) fn dedup(&mut self) -> usize {
+		let len = self.pending_changes.len();
+		let mut pending_changes = Vec::with_capacity(len);
+		for change in self.pending_changes.drain(..) {
+			if !pending_changes.contains(&change) {
+				pending_changes.push(change);
+			}
+		}
+
+		self.pending_changes = pending_changes;
+		len - self.pending_changes.len()
+	}
+
+	/// Drop all pending changes at or below the given finalized block number,
+	/// which are settled. Returns true if any changes were dropped.
+	pub(crate) fn prune_finalized(&mut self, finalized_number: N) -> bool {
+		let split_idx = self.pending_changes.iter()
+			.take_while(|(at_height, _)| *at_height <= finalized_number)
+			.count();
+
+		self.pending_changes.drain(..split_idx);
+		split_idx > 0
 	}
 
 	/// Finalize all pending consensus changes that are finalized by given block.
@@ -52,24 +104,18 @@ impl<H: Copy + PartialEq, N: Copy + Ord> ConsensusChanges<H, N> {
 		block: (N, H),
 		canonical_at_height: F,
 	) -> ::sp_blockchain::Result<(bool, bool)> {
-		let (split_idx, has_finalized_changes) = self.pending_changes.iter()
-			.enumerate()
-			.take_while(|(_, &(at_height, _))| at_height <= block.0)
-			.fold((None, Ok(false)), |(_, has_finalized_changes), (idx, ref at)|
-				(
-					Some(idx),
-					has_finalized_changes
-						.and_then(|has_finalized_changes| if has_finalized_changes {
-							Ok(has_finalized_changes)
-						} else {
-							canonical_at_height(at.0).map(|can_hash| Some(at.1) == can_hash)
-						}),
-				));
-
-		let altered_changes = split_idx.is_some();
-		if let Some(split_idx) = split_idx {
-			self.pending_changes = self.pending_changes.split_off(split_idx + 1);
-		}
+		let has_finalized_changes = self.pending_changes.iter()
+			.take_while(|(at_height, _)| *at_height <= block.0)
+			.fold(Ok(false), |has_finalized_changes, at|
+				has_finalized_changes
+					.and_then(|has_finalized_changes| if has_finalized_changes {
+						Ok(has_finalized_changes)
+					} else {
+						canonical_at_height(at.0).map(|can_hash| Some(at.1) == can_hash)
+					}),
+			);
+
+		let altered_changes = self.prune_finalized(block.0);
 		has_finalized_changes.map(|has_finalized_changes| (altered_changes, has_finalized_changes))
 	}
 }
@@ -15,10 +15,13 @@
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "compressed-rounds")]
+use std::io::{Read, Write};
 use std::iter::FromIterator;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use log::{debug, warn};
 use parity_scale_codec::{Decode, Encode};
@@ -28,7 +31,7 @@ use parking_lot::RwLock;
 use sp_blockchain::{HeaderBackend, Error as ClientError, HeaderMetadata};
 use std::marker::PhantomData;
 
-use sc_client_api::{backend::{Backend, apply_aux}, utils::is_descendent_of};
+use sc_client_api::{backend::{AuxStore, Backend, apply_aux}, utils::is_descendent_of};
 use finality_grandpa::{
 	BlockNumberOps, Equivocation, Error as GrandpaError, round::State as RoundState,
 	voter, voter_set::VoterSet,
@@ -48,6 +51,7 @@ use crate::{
 use sp_consensus::SelectChain;
 
 use crate::authorities::{AuthoritySet, SharedAuthoritySet};
+use crate::aux_schema::{StateCipher, StoredEquivocation};
 use crate::communication::Network as NetworkT;
 use crate::consensus_changes::SharedConsensusChanges;
 use crate::justification::GrandpaJustification;
@@ -75,27 +79,65 @@ pub struct CompletedRound<Block: BlockT> {
 	pub base: (Block::Hash, NumberFor<Block>),
 	/// All the votes observed in the round.
 	pub votes: Vec<SignedMessage<Block>>,
+	/// Unix time in milliseconds at which the round completed, if known.
+	pub completed_at: Option<u64>,
+	/// The id of the authority set the round belonged to, if known.
+	pub set_id: Option<SetId>,
 }
 
-// Data about last completed rounds within a single voter set. Stores
-// NUM_LAST_COMPLETED_ROUNDS and always contains data about at least one round
-// (genesis).
-#[derive(Debug, Clone, PartialEq)]
+impl<Block: BlockT> CompletedRound<Block> {
+	/// Create a completed round with an unknown completion time.
+	#[cfg(feature = "test-helpers")]
+	pub fn new_for_test(
+		number: RoundNumber,
+		state: RoundState<Block::Hash, NumberFor<Block>>,
+		base: (Block::Hash, NumberFor<Block>),
+		votes: Vec<SignedMessage<Block>>,
+	) -> CompletedRound<Block> {
+		CompletedRound { number, state, base, votes, completed_at: None, set_id: None }
+	}
+}
+
+// Data about last completed rounds within a single voter set. Stores up to
+// `retention` rounds (NUM_LAST_COMPLETED_ROUNDS by default) and always
+// contains data about at least one round (genesis).
+#[derive(Debug, Clone)]
 pub struct CompletedRounds<Block: BlockT> {
 	rounds: Vec<CompletedRound<Block>>,
 	set_id: SetId,
 	voters: Vec<AuthorityId>,
+	// not persisted, bounds the number of rounds kept in memory and on disk.
+	retention: usize,
+	// not persisted, set when the retention is configured explicitly, in which
+	// case the round with the highest finalized block is never evicted.
+	keep_best_finalized: bool,
 }
 
 // NOTE: the current strategy for persisting completed rounds is very naive
 // (update everything) and we also rely on cloning to do atomic updates,
 // therefore this value should be kept small for now.
-const NUM_LAST_COMPLETED_ROUNDS: usize = 2;
+pub(crate) const NUM_LAST_COMPLETED_ROUNDS: usize = 2;
+
+impl<Block: BlockT> PartialEq for CompletedRounds<Block> {
+	fn eq(&self, other: &Self) -> bool {
+		self.rounds == other.rounds && self.set_id == other.set_id && self.voters == other.voters
+	}
+}
+
+// Leading byte of completed rounds encoded compressed, followed by the
+// compressed plain encoding. A `0xff` byte can't start the plain encoding,
+// which starts with a compact length prefix.
+const COMPRESSED_ROUNDS_MAGIC: u8 = 0xff;
 
 impl<Block: BlockT> Encode for CompletedRounds<Block> {
+	#[cfg(feature = "compressed-rounds")]
 	fn encode(&self) -> Vec<u8> {
-		let v = Vec::from_iter(&self.rounds);
-		(&v, &self.set_id, &self.voters).encode()
+		self.encode_compressed()
+	}
+
+	#[cfg(not(feature = "compressed-rounds"))]
+	fn encode(&self) -> Vec<u8> {
+		self.encode_plain()
 	}
 }
 
@@ -103,16 +145,111 @@ impl<Block: BlockT> parity_scale_codec::EncodeLike for CompletedRounds<Block> {}
 
 impl<Block: BlockT> Decode for CompletedRounds<Block> {
 	fn decode<I: parity_scale_codec::Input>(value: &mut I) -> Result<Self, parity_scale_codec::Error> {
-		<(Vec<CompletedRound<Block>>, SetId, Vec<AuthorityId>)>::decode(value)
-			.map(|(rounds, set_id, voters)| CompletedRounds {
-				rounds: rounds.into(),
-				set_id,
-				voters,
-			})
+		Self::decode_as::<CompletedRound<Block>, _>(value)
+	}
+}
+
+// Input that yields an already consumed byte before the rest of the input.
+struct PrefixedInput<'a, I> {
+	prefix: Option<u8>,
+	input: &'a mut I,
+}
+
+impl<'a, I: parity_scale_codec::Input> parity_scale_codec::Input for PrefixedInput<'a, I> {
+	fn remaining_len(&mut self) -> Result<Option<usize>, parity_scale_codec::Error> {
+		let prefix_len = if self.prefix.is_some() { 1 } else { 0 };
+		Ok(self.input.remaining_len()?.map(|len| len + prefix_len))
+	}
+
+	fn read(&mut self, into: &mut [u8]) -> Result<(), parity_scale_codec::Error> {
+		match self.prefix.take() {
+			Some(prefix) if !into.is_empty() => {
+				into[0] = prefix;
+				self.input.read(&mut into[1..])
+			},
+			prefix => {
+				self.prefix = prefix;
+				self.input.read(into)
+			},
+		}
 	}
 }
 
 impl<Block: BlockT> CompletedRounds<Block> {
+	fn encode_plain(&self) -> Vec<u8> {
+		let v = Vec::from_iter(&self.rounds);
+		(&v, &self.set_id, &self.voters).encode()
+	}
+
+	/// Encode the completed rounds compressed, this is the default encoding
+	/// with the `compressed-rounds` feature. Only plain encodings are decoded
+	/// without it.
+	#[cfg(feature = "compressed-rounds")]
+	pub(crate) fn encode_compressed(&self) -> Vec<u8> {
+		let mut encoder = flate2::write::DeflateEncoder::new(
+			Vec::new(),
+			flate2::Compression::default(),
+		);
+		encoder.write_all(&self.encode_plain())
+			.and_then(|_| encoder.finish())
+			.map(|compressed| {
+				let mut encoded = vec![COMPRESSED_ROUNDS_MAGIC];
+				compressed.encode_to(&mut encoded);
+				encoded
+			})
+			.expect("writing to a Vec never fails; qed")
+	}
+
+	/// Decode completed rounds whose rounds are encoded as `R`, e.g. in the
+	/// format of an older schema version. Compressed encodings are only
+	/// decoded with the `compressed-rounds` feature.
+	pub(crate) fn decode_as<R, I>(value: &mut I) -> Result<Self, parity_scale_codec::Error> where
+		R: Decode + Into<CompletedRound<Block>>,
+		I: parity_scale_codec::Input,
+	{
+		let first = value.read_byte()?;
+		if first == COMPRESSED_ROUNDS_MAGIC {
+			Self::decode_compressed::<R, _>(value)
+		} else {
+			Self::decode_plain::<R, _>(&mut PrefixedInput { prefix: Some(first), input: value })
+		}
+	}
+
+	#[cfg(feature = "compressed-rounds")]
+	fn decode_compressed<R, I>(value: &mut I) -> Result<Self, parity_scale_codec::Error> where
+		R: Decode + Into<CompletedRound<Block>>,
+		I: parity_scale_codec::Input,
+	{
+		let compressed = Vec::<u8>::decode(value)?;
+		let mut plain = Vec::new();
+		flate2::read::DeflateDecoder::new(&compressed[..])
+			.read_to_end(&mut plain)
+			.map_err(|_| "Invalid compressed completed rounds")?;
+
+		Self::decode_plain::<R, _>(&mut &plain[..])
+	}
+
+	#[cfg(not(feature = "compressed-rounds"))]
+	fn decode_compressed<R, I>(_value: &mut I) -> Result<Self, parity_scale_codec::Error> where
+		R: Decode + Into<CompletedRound<Block>>,
+		I: parity_scale_codec::Input,
+	{
+		Err("Compressed completed rounds require the `compressed-rounds` feature".into())
+	}
+
+	fn decode_plain<R, I>(value: &mut I) -> Result<Self, parity_scale_codec::Error> where
+		R: Decode + Into<CompletedRound<Block>>,
+		I: parity_scale_codec::Input,
+	{
+		let (rounds, set_id, voters) = <(Vec<R>, SetId, Vec<AuthorityId>)>::decode(value)?;
+		if rounds.is_empty() {
+			return Err("Completed rounds must contain at least one round".into());
+		}
+
+		let rounds = rounds.into_iter().map(Into::into).collect();
+		Ok(CompletedRounds::from_rounds(rounds, set_id, voters))
+	}
+
 	/// Create a new completed rounds tracker with NUM_LAST_COMPLETED_ROUNDS capacity.
 	pub(crate) fn new(
 		genesis: CompletedRound<Block>,
@@ -125,7 +262,67 @@ impl<Block: BlockT> CompletedRounds<Block> {
 		rounds.push(genesis);
 
 		let voters = voters.current().1.iter().map(|(a, _)| a.clone()).collect();
-		CompletedRounds { rounds, set_id, voters }
+		CompletedRounds {
+			rounds,
+			set_id,
+			voters,
+			retention: NUM_LAST_COMPLETED_ROUNDS,
+			keep_best_finalized: false,
+		}
+	}
+
+	/// Create a completed rounds tracker from existing rounds, ordered from
+	/// newest to oldest. At least as many rounds as given are retained.
+	pub(crate) fn from_rounds(
+		rounds: Vec<CompletedRound<Block>>,
+		set_id: SetId,
+		voters: Vec<AuthorityId>,
+	)
+		-> CompletedRounds<Block>
+	{
+		CompletedRounds {
+			retention: rounds.len().max(NUM_LAST_COMPLETED_ROUNDS),
+			rounds,
+			set_id,
+			voters,
+			keep_best_finalized: false,
+		}
+	}
+
+	/// Create a completed rounds tracker for the given authorities from
+	/// existing rounds, ordered from newest to oldest. Fails if no rounds are
+	/// given, since at least one round is always tracked.
+	#[cfg(feature = "test-helpers")]
+	pub fn from_rounds_for_test(
+		set_id: SetId,
+		authorities: &[(AuthorityId, u64)],
+		rounds: Vec<CompletedRound<Block>>,
+	) -> Result<CompletedRounds<Block>, &'static str> {
+		if rounds.is_empty() {
+			return Err("Completed rounds must contain at least one round");
+		}
+
+		let voters = authorities.iter().map(|(id, _)| id.clone()).collect();
+		Ok(CompletedRounds::from_rounds(rounds, set_id, voters))
+	}
+
+	/// Create a new completed rounds tracker keeping at most `retention`
+	/// rounds (at least one). Unlike with `new`, the round with the highest
+	/// finalized block is never evicted.
+	pub(crate) fn with_retention(
+		genesis: CompletedRound<Block>,
+		set_id: SetId,
+		voters: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+		retention: usize,
+	)
+		-> CompletedRounds<Block>
+	{
+		let retention = retention.max(1);
+		let mut rounds = Vec::with_capacity(retention);
+		rounds.push(genesis);
+
+		let voters = voters.current().1.iter().map(|(a, _)| a.clone()).collect();
+		CompletedRounds { rounds, set_id, voters, retention, keep_best_finalized: true }
 	}
 
 	/// Get the set-id and voter set of the completed rounds.
@@ -133,6 +330,41 @@ impl<Block: BlockT> CompletedRounds<Block> {
 		(self.set_id, &self.voters[..])
 	}
 
+	/// Assign the completed rounds to the given authority set, keeping the
+	/// rounds themselves.
+	pub(crate) fn reassign(
+		&mut self,
+		set_id: SetId,
+		voters: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+	) {
+		self.set_id = set_id;
+		self.voters = voters.authorities().iter().map(|(a, _)| a.clone()).collect();
+	}
+
+	/// Record the current set id on the rounds that don't have one, up to the
+	/// genesis round of the current set. Rounds kept from previous sets (see
+	/// `hand_off`) are older than it and are left without a set id.
+	pub(crate) fn fill_round_set_ids(&mut self) {
+		for round in self.rounds.iter_mut() {
+			if round.set_id.is_none() {
+				round.set_id = Some(self.set_id);
+			}
+
+			if round.number == 0 {
+				break;
+			}
+		}
+	}
+
+	/// Returns the id of the authority set the newest completed round with the
+	/// given number belonged to, if it is kept and its set id is known. Round
+	/// numbers restart with each authority set.
+	pub fn round_set_id(&self, number: RoundNumber) -> Option<SetId> {
+		self.rounds.iter()
+			.find(|round| round.number == number)
+			.and_then(|round| round.set_id)
+	}
+
 	/// Iterate over all completed rounds.
 	pub fn iter(&self) -> impl Iterator<Item=&CompletedRound<Block>> {
 		self.rounds.iter().rev()
@@ -144,21 +376,74 @@ impl<Block: BlockT> CompletedRounds<Block> {
 			.expect("inner is never empty; always contains at least genesis; qed")
 	}
 
-	/// Push a new completed round, oldest round is evicted if number of rounds
-	/// is higher than `NUM_LAST_COMPLETED_ROUNDS`.
+	/// Returns the number of the last (latest) completed round.
+	pub fn last_number(&self) -> RoundNumber {
+		self.last().number
+	}
+
+	/// Start tracking the rounds of a new voter set from the given genesis
+	/// round, keeping the rounds completed by previous sets as history. The
+	/// history is evicted first once the retention bound is exceeded.
+	pub(crate) fn hand_off(
+		&mut self,
+		genesis: CompletedRound<Block>,
+		set_id: SetId,
+		voters: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+	) {
+		self.rounds.insert(0, genesis);
+		self.set_id = set_id;
+		self.voters = voters.current().1.iter().map(|(a, _)| a.clone()).collect();
+	}
+
+	/// Drop the completed rounds that finalized a block below the given number.
+	/// The latest completed round is always kept.
+	pub fn prune_below(&mut self, number: NumberFor<Block>) {
+		let mut index = 0;
+		self.rounds.retain(|round| {
+			let keep = index == 0 || round.state.finalized
+				.as_ref()
+				.map_or(true, |(_, finalized)| *finalized >= number);
+			index += 1;
+			keep
+		});
+	}
+
+	/// Push a new completed round, oldest rounds are evicted if number of rounds
+	/// is higher than the retention bound. If the retention was configured, see
+	/// `with_retention`, the round with the highest finalized block is never
+	/// evicted.
 	pub fn push(&mut self, completed_round: CompletedRound<Block>) {
-		use std::cmp::Reverse;
-
-		match self.rounds.binary_search_by_key(
-			&Reverse(completed_round.number),
-			|completed_round| Reverse(completed_round.number),
-		) {
-			Ok(idx) => self.rounds[idx] = completed_round,
-			Err(idx) => self.rounds.insert(idx, completed_round),
+		// rounds kept from previous voter sets (see `hand_off`) follow the rounds
+		// of the current set, so we can't binary search by round number.
+		match self.rounds.iter().position(|round| round.number <= completed_round.number) {
+			Some(idx) if self.rounds[idx].number == completed_round.number =>
+				self.rounds[idx] = completed_round,
+			Some(idx) => self.rounds.insert(idx, completed_round),
+			None => self.rounds.push(completed_round),
 		};
 
-		if self.rounds.len() > NUM_LAST_COMPLETED_ROUNDS {
-			self.rounds.pop();
+		if !self.keep_best_finalized {
+			while self.rounds.len() > self.retention {
+				self.rounds.pop();
+			}
+
+			return;
+		}
+
+		while self.rounds.len() > self.retention {
+			let oldest = self.rounds.len() - 1;
+			let best_finalized = self.rounds.iter()
+				.enumerate()
+				.filter_map(|(idx, round)| round.state.finalized.map(|(_, n)| (n, round.number, idx)))
+				.max()
+				.map(|(_, _, idx)| idx);
+
+			// the latest round is always kept.
+			if best_finalized == Some(oldest) && oldest > 1 {
+				self.rounds.remove(oldest - 1);
+			} else {
+				self.rounds.pop();
+			}
 		}
 	}
 }
@@ -185,9 +470,37 @@ pub enum VoterSetState<Block: BlockT> {
 	Paused {
 		/// The previously completed rounds.
 		completed_rounds: CompletedRounds<Block>,
+		/// Why the voter was paused.
+		reason: PauseReason,
+		/// The last finalized block when the voter was paused.
+		since: NumberFor<Block>,
 	},
 }
 
+/// The reason a voter set was paused.
+#[derive(Debug, Clone, Copy, Decode, Encode, PartialEq, Eq)]
+pub enum PauseReason {
+	/// The reason wasn't recorded.
+	Unknown,
+	/// A forced authority set change was scheduled.
+	ForcedChange,
+	/// The voter stopped making progress.
+	Stalled,
+	/// The voter was paused by the operator.
+	Manual,
+}
+
+impl fmt::Display for PauseReason {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			PauseReason::Unknown => write!(f, "Unknown reason"),
+			PauseReason::ForcedChange => write!(f, "Forced change scheduled after inactivity"),
+			PauseReason::Stalled => write!(f, "Voter stalled"),
+			PauseReason::Manual => write!(f, "Paused by operator"),
+		}
+	}
+}
+
 impl<Block: BlockT> VoterSetState<Block> {
 	/// Create a new live VoterSetState with round 0 as a completed round using
 	/// the given genesis state and the given authorities. Round 1 is added as a
@@ -196,14 +509,78 @@ impl<Block: BlockT> VoterSetState<Block> {
 		set_id: SetId,
 		authority_set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
 		genesis_state: (Block::Hash, NumberFor<Block>),
+	) -> VoterSetState<Block> {
+		Self::live_with_retention(set_id, authority_set, genesis_state, None)
+	}
+
+	/// Like `live`, but keeps up to `retention` completed rounds if given, see
+	/// `CompletedRounds::with_retention`.
+	pub(crate) fn live_with_retention(
+		set_id: SetId,
+		authority_set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+		genesis_state: (Block::Hash, NumberFor<Block>),
+		retention: Option<usize>,
 	) -> VoterSetState<Block> {
 		let state = RoundState::genesis((genesis_state.0, genesis_state.1));
-		let completed_rounds = CompletedRounds::new(
+		let genesis = CompletedRound {
+			number: 0,
+			state,
+			base: (genesis_state.0, genesis_state.1),
+			votes: Vec::new(),
+			completed_at: None,
+			set_id: Some(set_id),
+		};
+		let completed_rounds = match retention {
+			Some(retention) => CompletedRounds::with_retention(genesis, set_id, authority_set, retention),
+			None => CompletedRounds::new(genesis, set_id, authority_set),
+		};
+
+		let mut current_rounds = CurrentRounds::new();
+		current_rounds.insert(1, HasVoted::No);
+
+		VoterSetState::Live {
+			completed_rounds,
+			current_rounds,
+		}
+	}
+
+	/// Create a new live VoterSetState like `live`, for the current set id of
+	/// the given authority set.
+	pub(crate) fn genesis_for(
+		authority_set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+		base: (Block::Hash, NumberFor<Block>),
+	) -> VoterSetState<Block> {
+		Self::genesis_for_with_retention(authority_set, base, None)
+	}
+
+	/// Like `genesis_for`, but keeps up to `retention` completed rounds if
+	/// given.
+	pub(crate) fn genesis_for_with_retention(
+		authority_set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+		base: (Block::Hash, NumberFor<Block>),
+		retention: Option<usize>,
+	) -> VoterSetState<Block> {
+		Self::live_with_retention(authority_set.current().0, authority_set, base, retention)
+	}
+
+	/// Hand off to a new voter set like `live`, but keep the completed rounds
+	/// of this state as history. Round 1 of the new set is added as a current
+	/// round (with state `HasVoted::No`).
+	pub(crate) fn hand_off(
+		&self,
+		set_id: SetId,
+		authority_set: &AuthoritySet<Block::Hash, NumberFor<Block>>,
+		genesis_state: (Block::Hash, NumberFor<Block>),
+	) -> VoterSetState<Block> {
+		let mut completed_rounds = self.completed_rounds().clone();
+		completed_rounds.hand_off(
 			CompletedRound {
 				number: 0,
-				state,
+				state: RoundState::genesis((genesis_state.0, genesis_state.1)),
 				base: (genesis_state.0, genesis_state.1),
 				votes: Vec::new(),
+				completed_at: None,
+				set_id: Some(set_id),
 			},
 			set_id,
 			authority_set,
@@ -218,26 +595,72 @@ impl<Block: BlockT> VoterSetState<Block> {
 		}
 	}
 
+	/// Pause a voter set with the given completed rounds, paused since the
+	/// given last finalized block.
+	pub(crate) fn paused(
+		completed_rounds: CompletedRounds<Block>,
+		reason: PauseReason,
+		since: NumberFor<Block>,
+	) -> VoterSetState<Block> {
+		VoterSetState::Paused { completed_rounds, reason, since }
+	}
+
+	/// Resume a paused voter set, keeping its completed rounds. The round
+	/// after the last completed one is added as a current round (with state
+	/// `HasVoted::No`). A live voter set state is returned unchanged.
+	pub fn resume(self) -> VoterSetState<Block> {
+		match self {
+			VoterSetState::Paused { completed_rounds, .. } => {
+				let mut current_rounds = CurrentRounds::new();
+				current_rounds.insert(completed_rounds.last_number() + 1, HasVoted::No);
+
+				VoterSetState::Live {
+					completed_rounds,
+					current_rounds,
+				}
+			},
+			live => live,
+		}
+	}
+
 	/// Returns the last completed rounds.
-	pub(crate) fn completed_rounds(&self) -> CompletedRounds<Block> {
+	pub(crate) fn completed_rounds(&self) -> &CompletedRounds<Block> {
 		match self {
-			VoterSetState::Live { completed_rounds, .. } =>
-				completed_rounds.clone(),
-			VoterSetState::Paused { completed_rounds } =>
-				completed_rounds.clone(),
+			VoterSetState::Live { completed_rounds, .. } |
+			VoterSetState::Paused { completed_rounds, .. } =>
+				completed_rounds,
 		}
 	}
 
-	/// Returns the last completed round.
-	pub(crate) fn last_completed_round(&self) -> CompletedRound<Block> {
+	/// Whether the voter is paused.
+	pub(crate) fn is_paused(&self) -> bool {
 		match self {
-			VoterSetState::Live { completed_rounds, .. } =>
-				completed_rounds.last().clone(),
-			VoterSetState::Paused { completed_rounds } =>
-				completed_rounds.last().clone(),
+			VoterSetState::Live { .. } => false,
+			VoterSetState::Paused { .. } => true,
 		}
 	}
 
+	/// Iterate over all completed rounds, from oldest to newest.
+	pub(crate) fn iter_completed_rounds(&self) -> impl Iterator<Item=&CompletedRound<Block>> {
+		self.completed_rounds().iter()
+	}
+
+	/// Returns the last completed round.
+	pub(crate) fn last_completed_round(&self) -> CompletedRound<Block> {
+		self.completed_rounds().last().clone()
+	}
+
+	/// Returns the state of the last completed round.
+	pub(crate) fn last_round_state(&self) -> &RoundState<Block::Hash, NumberFor<Block>> {
+		&self.completed_rounds().last().state
+	}
+
+	/// Returns the base block of the last completed round, which the voter
+	/// resumes voting from.
+	pub(crate) fn current_base(&self) -> (Block::Hash, NumberFor<Block>) {
+		self.completed_rounds().last().base.clone()
+	}
+
 	/// Returns the voter set state validating that it includes the given round
 	/// in current rounds and that the voter isn't paused.
 	pub fn with_current_round(&self, round: RoundNumber)
@@ -307,6 +730,21 @@ impl<Block: BlockT> HasVoted<Block> {
 		}
 	}
 
+	/// Returns the target of the proposal we voted with (if any.)
+	pub fn propose_target(&self) -> Option<(Block::Hash, NumberFor<Block>)> {
+		self.propose().map(|propose| (propose.target_hash, propose.target_number))
+	}
+
+	/// Returns true if the voter has already prevoted, false otherwise.
+	pub fn has_prevoted(&self) -> bool {
+		self.prevote().is_some()
+	}
+
+	/// Returns true if the voter has already precommitted, false otherwise.
+	pub fn has_precommitted(&self) -> bool {
+		self.precommit().is_some()
+	}
+
 	/// Returns true if the voter can still propose, false otherwise.
 	pub fn can_propose(&self) -> bool {
 		self.propose().is_none()
@@ -373,6 +811,7 @@ impl<Block: BlockT> SharedVoterSetState<Block> {
 #[derive(Clone)]
 pub(crate) struct Metrics {
 	finality_grandpa_round: Gauge<U64>,
+	finality_grandpa_completed_rounds: Gauge<U64>,
 }
 
 impl Metrics {
@@ -382,8 +821,20 @@ impl Metrics {
 				Gauge::new("finality_grandpa_round", "Highest completed GRANDPA round.")?,
 				registry
 			)?,
+			finality_grandpa_completed_rounds: register(
+				Gauge::new(
+					"grandpa_completed_rounds_total",
+					"Number of completed GRANDPA rounds in the persisted voter set state.",
+				)?,
+				registry
+			)?,
 		})
 	}
+
+	/// Note the number of completed rounds in a persisted voter set state.
+	pub(crate) fn note_completed_rounds(&self, count: usize) {
+		self.finality_grandpa_completed_rounds.set(count as u64);
+	}
 }
 
 
@@ -400,6 +851,7 @@ pub(crate) struct Environment<Backend, Block: BlockT, C, N: NetworkT<Block>, SC,
 	pub(crate) voter_set_state: SharedVoterSetState<Block>,
 	pub(crate) voting_rule: VR,
 	pub(crate) metrics: Option<Metrics>,
+	pub(crate) state_cipher: Option<Arc<dyn StateCipher>>,
 	pub(crate) _phantom: PhantomData<Backend>,
 }
 
@@ -681,7 +1133,12 @@ where
 				current_rounds,
 			};
 
-			crate::aux_schema::write_voter_set_state(&*self.client, &set_state)?;
+			crate::aux_schema::write_voter_set_state_with_metrics(
+				&*self.client,
+				&set_state,
+				self.metrics.as_ref(),
+				self.state_cipher.as_deref(),
+			)?;
 
 			Ok(Some(set_state))
 		})?;
@@ -722,7 +1179,12 @@ where
 				current_rounds,
 			};
 
-			crate::aux_schema::write_voter_set_state(&*self.client, &set_state)?;
+			crate::aux_schema::write_voter_set_state_with_metrics(
+				&*self.client,
+				&set_state,
+				self.metrics.as_ref(),
+				self.state_cipher.as_deref(),
+			)?;
 
 			Ok(Some(set_state))
 		})?;
@@ -773,7 +1235,12 @@ where
 				current_rounds,
 			};
 
-			crate::aux_schema::write_voter_set_state(&*self.client, &set_state)?;
+			crate::aux_schema::write_voter_set_state_with_metrics(
+				&*self.client,
+				&set_state,
+				self.metrics.as_ref(),
+				self.state_cipher.as_deref(),
+			)?;
 
 			Ok(Some(set_state))
 		})?;
@@ -814,11 +1281,18 @@ where
 			// TODO: Future integration will store the prevote and precommit index. See #2611.
 			let votes = historical_votes.seen().to_vec();
 
+			let completed_at = SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.ok()
+				.map(|elapsed| elapsed.as_millis() as u64);
+
 			completed_rounds.push(CompletedRound {
 				number: round,
 				state: state.clone(),
 				base,
 				votes,
+				completed_at,
+				set_id: Some(self.set_id),
 			});
 
 			// remove the round from live rounds and start tracking the next round
@@ -831,7 +1305,12 @@ where
 				current_rounds,
 			};
 
-			crate::aux_schema::write_voter_set_state(&*self.client, &set_state)?;
+			crate::aux_schema::write_voter_set_state_completing_round(
+				&*self.client,
+				&set_state,
+				self.metrics.as_ref(),
+				self.state_cipher.as_deref(),
+			)?;
 
 			Ok(Some(set_state))
 		})?;
@@ -887,7 +1366,12 @@ where
 				current_rounds: current_rounds.clone(),
 			};
 
-			crate::aux_schema::write_voter_set_state(&*self.client, &set_state)?;
+			crate::aux_schema::write_voter_set_state_with_metrics(
+				&*self.client,
+				&set_state,
+				self.metrics.as_ref(),
+				self.state_cipher.as_deref(),
+			)?;
 
 			Ok(Some(set_state))
 		})?;
@@ -911,6 +1395,7 @@ where
 			number,
 			(round, commit).into(),
 			false,
+			self.state_cipher.as_deref(),
 		)
 	}
 
@@ -929,6 +1414,13 @@ where
 	) {
 		warn!(target: "afg", "Detected prevote equivocation in the finality worker: {:?}", equivocation);
 		// nothing yet; this could craft misbehavior reports of some kind.
+		// keep the evidence until then.
+		store_equivocation(
+			&*self.client,
+			self.set_id,
+			equivocation,
+			finality_grandpa::Message::Prevote,
+		);
 	}
 
 	fn precommit_equivocation(
@@ -938,6 +1430,47 @@ where
 	) {
 		warn!(target: "afg", "Detected precommit equivocation in the finality worker: {:?}", equivocation);
 		// nothing yet
+		store_equivocation(
+			&*self.client,
+			self.set_id,
+			equivocation,
+			finality_grandpa::Message::Precommit,
+		);
+	}
+}
+
+/// Append the evidence of an equivocation of the given authority set to the
+/// stored equivocations, wrapping the conflicting votes with `message`.
+/// Failing to store it is logged, since the voter can go on without it.
+fn store_equivocation<Block: BlockT, B: AuxStore, V>(
+	backend: &B,
+	set_id: SetId,
+	equivocation: Equivocation<AuthorityId, V, AuthoritySignature>,
+	message: impl Fn(V) -> crate::Message<Block>,
+) {
+	let Equivocation { round_number, identity, first, second } = equivocation;
+	let signed = |(vote, signature): (V, AuthoritySignature)| SignedMessage::<Block> {
+		message: message(vote),
+		signature,
+		id: identity.clone(),
+	};
+
+	let stored = StoredEquivocation {
+		set_id,
+		round: round_number,
+		authority: identity.clone(),
+		first: signed(first),
+		second: signed(second),
+	};
+
+	let result = crate::aux_schema::load_equivocations::<Block, _>(backend)
+		.and_then(|mut equivocations| {
+			equivocations.push(stored);
+			crate::aux_schema::write_equivocations(backend, &equivocations)
+		});
+
+	if let Err(e) = result {
+		warn!(target: "afg", "Failed to store GRANDPA equivocation: {:?}", e);
 	}
 }
 
@@ -971,6 +1504,7 @@ pub(crate) fn finalize_block<BE, Block, Client>(
 	number: NumberFor<Block>,
 	justification_or_commit: JustificationOrCommit<Block>,
 	initial_sync: bool,
+	state_cipher: Option<&dyn StateCipher>,
 ) -> Result<(), CommandOrError<Block::Hash, NumberFor<Block>>> where
 	Block:  BlockT,
 	BE: Backend<Block>,
@@ -1021,7 +1555,10 @@ pub(crate) fn finalize_block<BE, Block, Client>(
 
 		if alters_consensus_changes {
 			old_consensus_changes = Some(consensus_changes.clone());
+		}
 
+		// otherwise written along with the authority set below.
+		if alters_consensus_changes && !status.changed {
 			let write_result = crate::aux_schema::update_consensus_changes(
 				&*consensus_changes,
 				|insert| apply_aux(import_op, insert, &[]),
@@ -1092,6 +1629,8 @@ pub(crate) fn finalize_block<BE, Block, Client>(
 			// the authority set has changed.
 			let (new_id, set_ref) = authority_set.current();
 
+			crate::authorities::log_authority_set_diff(&old_authority_set, &*authority_set);
+
 			if set_ref.len() > 16 {
 				afg_log!(initial_sync,
 					"👴 Applying GRANDPA set change to new set with {} authorities",
@@ -1120,11 +1659,18 @@ pub(crate) fn finalize_block<BE, Block, Client>(
 		};
 
 		if status.changed {
-			let write_result = crate::aux_schema::update_authority_set::<Block, _, _>(
+			let write_result = crate::aux_schema::check_set_id_guard::<Block, _>(
+				&*client,
+				&authority_set,
+				crate::aux_schema::SetIdGuard::Warn,
+			).and_then(|_| crate::aux_schema::apply_standard_change::<Block, _, _>(
+				&*client,
 				&authority_set,
 				new_authorities.as_ref(),
+				&*consensus_changes,
+				state_cipher,
 				|insert| apply_aux(import_op, insert, &[]),
-			);
+			));
 
 			if let Err(e) = write_result {
 				warn!(target: "afg", "Failed to write updated authority set to disk. Bailing.");
@@ -16,7 +16,7 @@
 
 use std::{sync::Arc, collections::HashMap};
 
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use parity_scale_codec::Encode;
 use parking_lot::RwLockWriteGuard;
 
@@ -39,8 +39,9 @@ use sp_runtime::traits::{
 
 use crate::{Error, CommandOrError, NewAuthoritySet, VoterCommand};
 use crate::authorities::{AuthoritySet, SharedAuthoritySet, DelayKind, PendingChange};
+use crate::aux_schema::StateCipher;
 use crate::consensus_changes::SharedConsensusChanges;
-use crate::environment::finalize_block;
+use crate::environment::{finalize_block, PauseReason};
 use crate::justification::GrandpaJustification;
 use std::marker::PhantomData;
 
@@ -60,6 +61,7 @@ pub struct GrandpaBlockImport<Backend, Block: BlockT, Client, SC> {
 	send_voter_commands: TracingUnboundedSender<VoterCommand<Block::Hash, NumberFor<Block>>>,
 	consensus_changes: SharedConsensusChanges<Block::Hash, NumberFor<Block>>,
 	authority_set_hard_forks: HashMap<Block::Hash, PendingChange<Block::Hash, NumberFor<Block>>>,
+	state_cipher: Option<Arc<dyn StateCipher>>,
 	_phantom: PhantomData<Backend>,
 }
 
@@ -74,6 +76,7 @@ impl<Backend, Block: BlockT, Client, SC: Clone> Clone for
 			send_voter_commands: self.send_voter_commands.clone(),
 			consensus_changes: self.consensus_changes.clone(),
 			authority_set_hard_forks: self.authority_set_hard_forks.clone(),
+			state_cipher: self.state_cipher.clone(),
 			_phantom: PhantomData,
 		}
 	}
@@ -379,20 +382,42 @@ where
 
 		// consume the guard safely and write necessary changes.
 		let just_in_case = guard.consume();
-		if let Some((_, ref authorities)) = just_in_case {
+		if let Some((ref old_set, ref authorities)) = just_in_case {
 			let authorities_change = match applied_changes {
 				AppliedChanges::Forced(ref new) => Some(new),
 				AppliedChanges::Standard(_) => None, // the change isn't actually applied yet.
 				AppliedChanges::None => None,
 			};
 
-			crate::aux_schema::update_authority_set::<Block, _, _>(
+			if let Some(new) = authorities_change {
+				crate::authorities::log_authority_set_diff(old_set, authorities);
+			}
+
+			// a forced change may be imported on a fork lagging behind the
+			// stored authority set.
+			let set_id_guard = match applied_changes {
+				AppliedChanges::Forced(_) => crate::aux_schema::SetIdGuard::Bypass,
+				_ => crate::aux_schema::SetIdGuard::Warn,
+			};
+
+			crate::aux_schema::check_set_id_guard::<Block, _>(&*self.inner, authorities, set_id_guard)
+				.map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
+
+			crate::aux_schema::commit_grandpa_state::<Block, _, _>(
+				&*self.inner,
 				authorities,
 				authorities_change,
-				|insert| block.auxiliary.extend(
-					insert.iter().map(|(k, v)| (k.to_vec(), Some(v.to_vec())))
-				)
-			);
+				crate::aux_schema::HandoffHistory::Wipe,
+				None,
+				None,
+				self.state_cipher.as_deref(),
+				|insert| {
+					block.auxiliary.extend(
+						insert.iter().map(|(k, v)| (k.to_vec(), Some(v.to_vec())))
+					);
+					Ok(())
+				},
+			).map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
 		}
 
 		Ok(PendingSetChanges { just_in_case, applied_changes, do_pause })
@@ -466,7 +491,7 @@ impl<BE, Block: BlockT, Client, SC> BlockImport<Block>
 		// Send the pause signal after import but BEFORE sending a `ChangeAuthorities` message.
 		if do_pause {
 			let _ = self.send_voter_commands.unbounded_send(
-				VoterCommand::Pause(format!("Forced change scheduled after inactivity"))
+				VoterCommand::Pause(PauseReason::ForcedChange)
 			);
 		}
 
@@ -558,6 +583,7 @@ impl<Backend, Block: BlockT, Client, SC> GrandpaBlockImport<Backend, Block, Clie
 		send_voter_commands: TracingUnboundedSender<VoterCommand<Block::Hash, NumberFor<Block>>>,
 		consensus_changes: SharedConsensusChanges<Block::Hash, NumberFor<Block>>,
 		authority_set_hard_forks: Vec<(SetId, PendingChange<Block::Hash, NumberFor<Block>>)>,
+		state_cipher: Option<Arc<dyn StateCipher>>,
 	) -> GrandpaBlockImport<Backend, Block, Client, SC> {
 		// check for and apply any forced authority set hard fork that applies
 		// to the *current* authority set.
@@ -601,6 +627,7 @@ impl<Backend, Block: BlockT, Client, SC> GrandpaBlockImport<Backend, Block, Clie
 			send_voter_commands,
 			consensus_changes,
 			authority_set_hard_forks,
+			state_cipher,
 			_phantom: PhantomData,
 		}
 	}
@@ -625,16 +652,16 @@ where
 		enacts_change: bool,
 		initial_sync: bool,
 	) -> Result<(), ConsensusError> {
-		let justification = GrandpaJustification::decode_and_verify_finalizes(
+		let verified = GrandpaJustification::decode_and_verify_finalizes(
 			&justification,
 			(hash, number),
 			self.authority_set.set_id(),
 			&self.authority_set.current_authorities(),
 		);
 
-		let justification = match justification {
+		let verified = match verified {
 			Err(e) => return Err(ConsensusError::ClientImport(e.to_string()).into()),
-			Ok(justification) => justification,
+			Ok(verified) => verified,
 		};
 
 		let result = finalize_block(
@@ -644,8 +671,9 @@ where
 			None,
 			hash,
 			number,
-			justification.into(),
+			verified.into(),
 			initial_sync,
+			self.state_cipher.as_deref(),
 		);
 
 		match result {
@@ -672,6 +700,12 @@ where
 			},
 			Ok(_) => {
 				assert!(!enacts_change, "returns Ok when no authority set change should be enacted; qed;");
+
+				// the justification is of the current set, since no handoff
+				// was enacted. the block is finalized either way.
+				if let Err(e) = crate::aux_schema::write_last_justification(&*self.inner, &justification) {
+					warn!(target: "afg", "Failed to write last GRANDPA justification: {:?}", e);
+				}
 			},
 		}
 
@@ -54,7 +54,7 @@
 
 use futures::prelude::*;
 use futures::StreamExt;
-use log::{debug, info};
+use log::{debug, info, warn};
 use sc_client_api::{
 	backend::{AuxStore, Backend},
 	LockImportRun, BlockchainEvents, CallExecutor,
@@ -114,6 +114,21 @@ mod observer;
 mod until_imported;
 mod voting_rule;
 
+pub use aux_schema::{
+	apply_diff, authority_stats, current_authority_list, current_set_id, diff_voter_set_state,
+	downgrade_to_version2, export_current_authorities_scale, finality_health, inspect_persistent,
+	load_best_finalized, load_last_handoff, load_last_justification, migration_pending,
+	pending_changes_at, restore, revert_consensus_changes, run_migrations_stepwise, schema_keys,
+	snapshot, storage_footprint, update_authority_weights, AuxWriteObserver, DiffStatus,
+	EntryStatus, FinalityHealth, ObservedAuxStore, PrefixedAuxStore, SchemaKeys, SchemaReport,
+	StateCipher, StateDiff,
+};
+pub use authorities::{DelayKind, PendingChange};
+#[cfg(feature = "state-json")]
+pub use aux_schema::{export_state_json, import_state_json};
+#[cfg(feature = "test-helpers")]
+pub use environment::{CompletedRound, CompletedRounds};
+pub use environment::{CurrentRounds, HasVoted, Vote, VoterSetState};
 pub use finality_proof::{FinalityProofProvider, StorageAndProofProvider};
 pub use justification::GrandpaJustification;
 pub use light_import::light_block_import;
@@ -122,7 +137,7 @@ pub use voting_rule::{
 };
 
 use aux_schema::PersistentData;
-use environment::{Environment, VoterSetState};
+use environment::{Environment, PauseReason};
 use import::GrandpaBlockImport;
 use until_imported::UntilGlobalMessageBlocksImported;
 use communication::{NetworkBridge, Network as NetworkT};
@@ -334,7 +349,7 @@ pub(crate) struct NewAuthoritySet<H, N> {
 #[derive(Debug)]
 pub(crate) enum VoterCommand<H, N> {
 	/// Pause the voter for given reason.
-	Pause(String),
+	Pause(PauseReason),
 	/// New authorities.
 	ChangeAuthorities(NewAuthoritySet<H, N>)
 }
@@ -472,6 +487,106 @@ pub fn block_import_with_authority_set_hard_forks<BE, Block: BlockT, Client, SC>
 	),
 	ClientError,
 >
+where
+	SC: SelectChain<Block>,
+	BE: Backend<Block> + 'static,
+	Client: ClientForGrandpa<Block, BE> + 'static,
+{
+	block_import_with_state_cipher(
+		client,
+		genesis_authorities_provider,
+		select_chain,
+		authority_set_hard_forks,
+		None,
+		None,
+	)
+}
+
+/// Make block importer and link half necessary to tie the background voter to
+/// it, like `block_import_with_state_cipher`, recovering from a corrupted
+/// voter set state instead of failing. The voter set state is then reset to
+/// round 0 of the stored authority set, which is never reset. Fails if no
+/// authority set is stored, rather than initializing it from genesis.
+pub fn block_import_with_recovery<BE, Block: BlockT, Client, SC>(
+	client: Arc<Client>,
+	genesis_authorities_provider: &dyn GenesisAuthoritySetProvider<Block>,
+	select_chain: SC,
+	authority_set_hard_forks: Vec<(SetId, (Block::Hash, NumberFor<Block>), AuthorityList)>,
+	state_cipher: Option<Arc<dyn StateCipher>>,
+	prometheus_registry: Option<&Registry>,
+) -> Result<
+	(
+		GrandpaBlockImport<BE, Block, Client, SC>,
+		LinkHalf<Block, Client, SC>,
+	),
+	ClientError,
+>
+where
+	SC: SelectChain<Block>,
+	BE: Backend<Block> + 'static,
+	Client: ClientForGrandpa<Block, BE> + 'static,
+{
+	make_block_import(
+		client,
+		genesis_authorities_provider,
+		select_chain,
+		authority_set_hard_forks,
+		state_cipher,
+		prometheus_registry,
+		true,
+	)
+}
+
+/// Make block importer and link half necessary to tie the background voter to
+/// it, like `block_import_with_authority_set_hard_forks`. The voter set state
+/// is encrypted at rest with the given cipher, if any, by the voter or
+/// observer run with the returned link half. Migrations and decoding of the
+/// GRANDPA DB are reported to the given prometheus registry, if any.
+pub fn block_import_with_state_cipher<BE, Block: BlockT, Client, SC>(
+	client: Arc<Client>,
+	genesis_authorities_provider: &dyn GenesisAuthoritySetProvider<Block>,
+	select_chain: SC,
+	authority_set_hard_forks: Vec<(SetId, (Block::Hash, NumberFor<Block>), AuthorityList)>,
+	state_cipher: Option<Arc<dyn StateCipher>>,
+	prometheus_registry: Option<&Registry>,
+) -> Result<
+	(
+		GrandpaBlockImport<BE, Block, Client, SC>,
+		LinkHalf<Block, Client, SC>,
+	),
+	ClientError,
+>
+where
+	SC: SelectChain<Block>,
+	BE: Backend<Block> + 'static,
+	Client: ClientForGrandpa<Block, BE> + 'static,
+{
+	make_block_import(
+		client,
+		genesis_authorities_provider,
+		select_chain,
+		authority_set_hard_forks,
+		state_cipher,
+		prometheus_registry,
+		false,
+	)
+}
+
+fn make_block_import<BE, Block: BlockT, Client, SC>(
+	client: Arc<Client>,
+	genesis_authorities_provider: &dyn GenesisAuthoritySetProvider<Block>,
+	select_chain: SC,
+	authority_set_hard_forks: Vec<(SetId, (Block::Hash, NumberFor<Block>), AuthorityList)>,
+	state_cipher: Option<Arc<dyn StateCipher>>,
+	prometheus_registry: Option<&Registry>,
+	recover_set_state: bool,
+) -> Result<
+	(
+		GrandpaBlockImport<BE, Block, Client, SC>,
+		LinkHalf<Block, Client, SC>,
+	),
+	ClientError,
+>
 where
 	SC: SelectChain<Block>,
 	BE: Backend<Block> + 'static,
@@ -480,18 +595,85 @@ where
 	let chain_info = client.info();
 	let genesis_hash = chain_info.genesis_hash;
 
-	let persistent_data = aux_schema::load_persistent(
-		&*client,
-		genesis_hash,
-		<NumberFor<Block>>::zero(),
-		|| {
-			let authorities = genesis_authorities_provider.get()?;
-			telemetry!(CONSENSUS_DEBUG; "afg.loading_authorities";
-				"authorities_len" => ?authorities.len()
-			);
-			Ok(authorities)
+	let migration_metrics = match prometheus_registry.map(aux_schema::MigrationMetrics::register) {
+		Some(Ok(metrics)) => Some(metrics),
+		Some(Err(e)) => {
+			debug!(target: "afg", "Failed to register migration metrics: {:?}", e);
+			None
 		}
-	)?;
+		None => None,
+	};
+	let decode_metrics = match prometheus_registry.map(aux_schema::DecodeMetrics::register) {
+		Some(Ok(metrics)) => Some(metrics),
+		Some(Err(e)) => {
+			debug!(target: "afg", "Failed to register decode metrics: {:?}", e);
+			None
+		}
+		None => None,
+	};
+
+	let options = aux_schema::LoadOptions {
+		state_cipher,
+		migration_metrics,
+		decode_metrics,
+		..Default::default()
+	};
+
+	let loaded = if recover_set_state {
+		aux_schema::load_persistent_with_recovery(
+			&*client,
+			genesis_hash,
+			<NumberFor<Block>>::zero(),
+			options,
+		)
+	} else {
+		aux_schema::load_persistent_with_options(
+			&*client,
+			genesis_hash,
+			<NumberFor<Block>>::zero(),
+			|| {
+				let authorities = genesis_authorities_provider.get()?;
+				telemetry!(CONSENSUS_DEBUG; "afg.loading_authorities";
+					"authorities_len" => ?authorities.len()
+				);
+				Ok(authorities)
+			},
+			options,
+		)
+	};
+
+	let persistent_data = match loaded {
+		Ok(persistent_data) => persistent_data,
+		Err(e) => {
+			// e.g. the DB was written by a newer node, let operators at least
+			// inspect the authority set.
+			if let Ok(Some(set)) = aux_schema::load_authority_set_forward_compat::<Block, _>(&*client) {
+				let (set_id, authorities) = set.current();
+				warn!(target: "afg", "Failed to load GRANDPA persistent data, stored authority set {} is {:?}.",
+					set_id,
+					authorities,
+				);
+			}
+
+			return Err(e);
+		},
+	};
+
+	if cfg!(debug_assertions) {
+		if let Err(violations) = aux_schema::validate_persistent(&persistent_data) {
+			for violation in violations {
+				warn!(target: "afg", "Inconsistent GRANDPA persistent data: {}", violation);
+			}
+		}
+
+		let inconsistencies = aux_schema::cross_check::<Block>(
+			&persistent_data.authority_set.inner().read(),
+			&persistent_data.consensus_changes.lock(),
+		);
+		for inconsistency in inconsistencies {
+			warn!(target: "afg", "Inconsistent GRANDPA pending changes: {:?}", inconsistency);
+		}
+	}
 
 	let (voter_commands_tx, voter_commands_rx) = tracing_unbounded("mpsc_grandpa_voter_command");
 
@@ -521,6 +703,7 @@ where
 			voter_commands_tx,
 			persistent_data.consensus_changes.clone(),
 			authority_set_hard_forks,
+			persistent_data.state_cipher.clone(),
 		),
 		LinkHalf {
 			client,
@@ -784,6 +967,7 @@ where
 			consensus_changes: persistent_data.consensus_changes.clone(),
 			voter_set_state: persistent_data.set_state.clone(),
 			metrics: metrics.as_ref().map(|m| m.environment.clone()),
+			state_cipher: persistent_data.state_cipher.clone(),
 			_phantom: PhantomData,
 		});
 
@@ -830,7 +1014,8 @@ where
 			},
 		);
 
-		match &*self.env.voter_set_state.read() {
+		let voter_set_state = self.env.voter_set_state.read();
+		match &*voter_set_state {
 			VoterSetState::Live { completed_rounds, .. } => {
 				let last_finalized = (
 					chain_info.finalized_hash,
@@ -854,7 +1039,7 @@ where
 					global_comms,
 					last_completed_round.number,
 					last_completed_round.votes.clone(),
-					last_completed_round.base.clone(),
+					voter_set_state.current_base(),
 					last_finalized,
 				);
 
@@ -890,7 +1075,11 @@ where
 						(new.canon_hash, new.canon_number),
 					);
 
-					aux_schema::write_voter_set_state(&*self.env.client, &set_state)?;
+					aux_schema::write_voter_set_state_encrypted(
+						&*self.env.client,
+						&set_state,
+						self.env.state_cipher.as_deref(),
+					)?;
 					Ok(Some(set_state))
 				})?;
 
@@ -915,6 +1104,7 @@ where
 					network: self.env.network.clone(),
 					voting_rule: self.env.voting_rule.clone(),
 					metrics: self.env.metrics.clone(),
+					state_cipher: self.env.state_cipher.clone(),
 					_phantom: PhantomData,
 				});
 
@@ -924,12 +1114,18 @@ where
 			VoterCommand::Pause(reason) => {
 				info!(target: "afg", "Pausing old validator set: {}", reason);
 
+				let since = self.env.client.info().finalized_number;
+
 				// not racing because old voter is shut down.
 				self.env.update_voter_set_state(|voter_set_state| {
-					let completed_rounds = voter_set_state.completed_rounds();
-					let set_state = VoterSetState::Paused { completed_rounds };
-
-					aux_schema::write_voter_set_state(&*self.env.client, &set_state)?;
+					let completed_rounds = voter_set_state.completed_rounds().clone();
+					let set_state = VoterSetState::paused(completed_rounds, reason, since);
+
+					aux_schema::write_voter_set_state_encrypted(
+						&*self.env.client,
+						&set_state,
+						self.env.state_cipher.as_deref(),
+					)?;
 					Ok(Some(set_state))
 				})?;
 
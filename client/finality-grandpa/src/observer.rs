@@ -36,6 +36,7 @@ use crate::{
 	LinkHalf, Error, aux_schema::PersistentData, VoterCommand, VoterSetState,
 };
 use crate::authorities::SharedAuthoritySet;
+use crate::aux_schema::StateCipher;
 use crate::communication::{Network as NetworkT, NetworkBridge};
 use crate::consensus_changes::SharedConsensusChanges;
 use sp_finality_grandpa::AuthorityId;
@@ -70,6 +71,7 @@ fn grandpa_observer<BE, Block: BlockT, Client, S, F>(
 	last_finalized_number: NumberFor<Block>,
 	commits: S,
 	note_round: F,
+	state_cipher: Option<Arc<dyn StateCipher>>,
 ) -> impl Future<Output=Result<(), CommandOrError<Block::Hash, NumberFor<Block>>>> where
 	NumberFor<Block>: BlockNumberOps,
 	S: Stream<
@@ -125,6 +127,7 @@ fn grandpa_observer<BE, Block: BlockT, Client, S, F>(
 				finalized_number,
 				(round, commit).into(),
 				false,
+				state_cipher.as_deref(),
 			) {
 				Ok(_) => {},
 				Err(e) => return future::err(e),
@@ -287,6 +290,7 @@ where
 			last_finalized_number,
 			global_in,
 			note_round,
+			self.persistent_data.state_cipher.clone(),
 		);
 
 		self.observer = Box::pin(observer);
@@ -302,10 +306,15 @@ where
 			VoterCommand::Pause(reason) => {
 				info!(target: "afg", "Pausing old validator set: {}", reason);
 
-				let completed_rounds = self.persistent_data.set_state.read().completed_rounds();
-				let set_state = VoterSetState::Paused { completed_rounds };
+				let completed_rounds = self.persistent_data.set_state.read().completed_rounds().clone();
+				let since = self.client.info().finalized_number;
+				let set_state = VoterSetState::paused(completed_rounds, reason, since);
 
-				crate::aux_schema::write_voter_set_state(&*self.client, &set_state)?;
+				crate::aux_schema::write_voter_set_state_encrypted(
+					&*self.client,
+					&set_state,
+					self.persistent_data.state_cipher.as_deref(),
+				)?;
 
 				set_state
 			},
@@ -318,7 +327,11 @@ where
 					(new.canon_hash, new.canon_number),
 				);
 
-				crate::aux_schema::write_voter_set_state(&*self.client, &set_state)?;
+				crate::aux_schema::write_voter_set_state_encrypted(
+					&*self.client,
+					&set_state,
+					self.persistent_data.state_cipher.as_deref(),
+				)?;
 
 				set_state
 			},
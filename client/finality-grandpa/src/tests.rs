@@ -1550,6 +1550,7 @@ fn grandpa_environment_respects_voting_rules() {
 			network,
 			voting_rule,
 			metrics: None,
+			state_cipher: None,
 			_phantom: PhantomData,
 		}
 	};
@@ -1629,6 +1630,71 @@ fn grandpa_environment_respects_voting_rules() {
 	);
 }
 
+#[test]
+fn grandpa_environment_encrypts_voter_set_state_writes() {
+	use finality_grandpa::voter::Environment as _;
+
+	struct XorCipher;
+
+	impl StateCipher for XorCipher {
+		fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+			plaintext.iter().map(|byte| byte ^ 7).collect()
+		}
+
+		fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+			Some(ciphertext.iter().map(|byte| byte ^ 7).collect())
+		}
+	}
+
+	let peers = &[Ed25519Keyring::Alice];
+	let voters = make_ids(peers);
+
+	let mut net = GrandpaTestNet::new(TestApi::new(voters), 1);
+	let peer = net.peer(0);
+	let network_service = peer.network_service().clone();
+	let link = peer.data.lock().take().unwrap();
+
+	let PersistentData { ref authority_set, ref consensus_changes, ref set_state, .. } = link.persistent_data;
+
+	let config = Config {
+		gossip_duration: TEST_GOSSIP_DURATION,
+		justification_period: 32,
+		keystore: None,
+		name: None,
+		is_authority: true,
+		observer_enabled: true,
+	};
+
+	let environment = Environment {
+		authority_set: authority_set.clone(),
+		config: config.clone(),
+		consensus_changes: consensus_changes.clone(),
+		client: link.client.clone(),
+		select_chain: link.select_chain.clone(),
+		set_id: authority_set.set_id(),
+		voter_set_state: set_state.clone(),
+		voters: Arc::new(authority_set.current_authorities()),
+		network: NetworkBridge::new(network_service.clone(), config, set_state.clone(), None),
+		voting_rule: (),
+		metrics: None,
+		state_cipher: Some(Arc::new(XorCipher)),
+		_phantom: PhantomData,
+	};
+
+	let genesis = (peer.client().info().genesis_hash, 0);
+	environment.completed(
+		1,
+		finality_grandpa::round::State::genesis(genesis),
+		genesis,
+		&finality_grandpa::HistoricalVotes::new(),
+	).unwrap();
+
+	// the voter set state is stored under the `grandpa_completed_round` key,
+	// prefixed with the encryption magic.
+	let stored = link.client.get_aux(b"grandpa_completed_round").unwrap().unwrap();
+	assert!(stored.starts_with(b"\xffenc"));
+}
+
 #[test]
 fn imports_justification_for_regular_blocks_on_import() {
 	// NOTE: this is a regression test since initially we would only import